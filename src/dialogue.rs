@@ -0,0 +1,712 @@
+//! Branching dialogue trees, loaded from every `.dlg` file in
+//! `data/dialogue`. Each node is a `[tree:node]` section with the NPC's
+//! `text` and the player's replies:
+//!
+//! ```text
+//! [blacksmith:start]
+//! text = Well met! Need a blade mended?
+//! reply = Any work for me? | work | if level 2, quest ore none
+//! reply = Here is your ore. | end | if item Iron Ore | do take Iron Ore
+//! reply = Heard any news? | end | if reputation 5
+//! ```
+//!
+//! A reply is its text, the node it leads to (`end` closes the dialogue)
+//! and optional `if` conditions and `do` effects, comma separated.
+//! Conditions: `level N`, `charisma N` and `reputation N` (at least N),
+//! `item NAME` and `quest ID none|active|done`; replies whose conditions
+//! fail are not offered. Effects: `give NAME`, `take NAME`,
+//! `start-quest ID`, `complete-quest ID` and `reputation +N` /
+//! `reputation -N`. A node has at most 9 replies, one per number key.
+//! Every tree needs a `start` node. NPCs get a tree through the
+//! `conversation` field of their template.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::entities::player::Player;
+use crate::inventory::item::Item;
+use crate::maps::position::Position;
+use crate::quest::quest::QuestState;
+
+pub const DIALOGUE_DIR: &str = "data/dialogue";
+
+/// Node every conversation begins at.
+pub const START_NODE: &str = "start";
+
+/// Reply target that closes the dialogue.
+const END: &str = "end";
+
+/// Replies are picked with the keys 1 to 9.
+const MAX_REPLIES: usize = 9;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Level(u32),
+    Charisma(i32),
+    Reputation(i32),
+    Item(String),
+    /// `None` means the quest was never started.
+    Quest(String, Option<QuestState>),
+}
+
+impl Condition {
+    pub fn holds(&self, player: &Player) -> bool {
+        match self {
+            Condition::Level(level) => player.level >= *level,
+            Condition::Charisma(charisma) => player.charisma >= *charisma,
+            Condition::Reputation(reputation) => player.reputation >= *reputation,
+            Condition::Item(name) => player.inventory.iter().any(|item| item.name == *name),
+            Condition::Quest(id, state) => player.quests.get(id) == state.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    GiveItem(String),
+    TakeItem(String),
+    StartQuest(String),
+    CompleteQuest(String),
+    Reputation(i32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    pub text: String,
+    /// Node the reply leads to; `None` ends the dialogue.
+    pub next: Option<String>,
+    pub conditions: Vec<Condition>,
+    pub effects: Vec<Effect>,
+}
+
+impl Reply {
+    pub fn is_available(&self, player: &Player) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.holds(player))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Node {
+    pub text: String,
+    pub replies: Vec<Reply>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DialogueTree {
+    pub nodes: BTreeMap<String, Node>,
+}
+
+#[derive(Debug)]
+pub enum DialogueError {
+    Io {
+        file: PathBuf,
+        err: io::Error,
+    },
+    MalformedLine {
+        file: PathBuf,
+        line: usize,
+        reason: String,
+    },
+    UnknownNode {
+        file: PathBuf,
+        line: usize,
+        node: String,
+    },
+    MissingStart {
+        file: PathBuf,
+        tree: String,
+    },
+    Duplicate {
+        file: PathBuf,
+        line: usize,
+        node: String,
+    },
+}
+
+impl fmt::Display for DialogueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DialogueError::Io { file, err } => {
+                write!(f, "{}: cannot read file: {}", file.display(), err)
+            }
+            DialogueError::MalformedLine { file, line, reason } => {
+                write!(f, "{}: line {}: {}", file.display(), line, reason)
+            }
+            DialogueError::UnknownNode { file, line, node } => {
+                write!(
+                    f,
+                    "{}: line {}: reply leads to unknown node '{}'",
+                    file.display(),
+                    line,
+                    node
+                )
+            }
+            DialogueError::MissingStart { file, tree } => {
+                write!(
+                    f,
+                    "{}: dialogue '{}' has no '{}' node",
+                    file.display(),
+                    tree,
+                    START_NODE
+                )
+            }
+            DialogueError::Duplicate { file, line, node } => {
+                write!(
+                    f,
+                    "{}: line {}: node '{}' is defined twice",
+                    file.display(),
+                    line,
+                    node
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for DialogueError {}
+
+/// Every dialogue tree, by id.
+#[derive(Debug, Clone, Default)]
+pub struct DialogueLibrary {
+    trees: BTreeMap<String, DialogueTree>,
+}
+
+impl DialogueLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, tree: &str) -> Option<&DialogueTree> {
+        self.trees.get(tree)
+    }
+
+    pub fn node(&self, tree: &str, node: &str) -> Option<&Node> {
+        self.get(tree).and_then(|tree| tree.nodes.get(node))
+    }
+}
+
+/// Loads every `.dlg` file in `dir`, in file name order.
+pub fn load_dialogues<P: AsRef<Path>>(dir: P) -> Result<DialogueLibrary, DialogueError> {
+    let dir = dir.as_ref();
+    let entries = fs::read_dir(dir).map_err(|err| DialogueError::Io {
+        file: dir.to_path_buf(),
+        err,
+    })?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "dlg"))
+        .collect();
+    paths.sort();
+
+    let mut library = DialogueLibrary::new();
+    for path in paths {
+        let contents = fs::read_to_string(&path).map_err(|err| DialogueError::Io {
+            file: path.clone(),
+            err,
+        })?;
+        parse_dialogues(&path, &contents, &mut library)?;
+    }
+    Ok(library)
+}
+
+/// Adds the trees of one file to `library`. `file` is only used in error
+/// messages. A tree must be complete within its file.
+pub fn parse_dialogues(
+    file: &Path,
+    contents: &str,
+    library: &mut DialogueLibrary,
+) -> Result<(), DialogueError> {
+    let malformed = |line: usize, reason: String| DialogueError::MalformedLine {
+        file: file.to_path_buf(),
+        line,
+        reason,
+    };
+    let mut trees: BTreeMap<String, DialogueTree> = BTreeMap::new();
+    // Targets are checked once every node of the file is known.
+    let mut targets: Vec<(usize, String, String)> = Vec::new();
+    let mut current: Option<(String, String)> = None;
+
+    for (index, raw_line) in contents.lines().enumerate() {
+        let line = index + 1;
+        let trimmed = raw_line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(';') {
+            continue;
+        }
+        if let Some(header) = trimmed
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            let (tree, node) = header
+                .split_once(':')
+                .map(|(tree, node)| (tree.trim(), node.trim()))
+                .filter(|(tree, node)| is_id(tree) && is_id(node))
+                .ok_or_else(|| {
+                    malformed(
+                        line,
+                        format!("expected '[tree:node]', found '[{}]'", header),
+                    )
+                })?;
+            if library.get(tree).is_some() {
+                return Err(malformed(
+                    line,
+                    format!("dialogue '{}' is already defined in another file", tree),
+                ));
+            }
+            let nodes = &mut trees.entry(tree.to_string()).or_default().nodes;
+            if nodes.contains_key(node) {
+                return Err(DialogueError::Duplicate {
+                    file: file.to_path_buf(),
+                    line,
+                    node: format!("{}:{}", tree, node),
+                });
+            }
+            nodes.insert(node.to_string(), Node::default());
+            current = Some((tree.to_string(), node.to_string()));
+            continue;
+        }
+
+        let Some((tree, node)) = current.as_ref() else {
+            return Err(malformed(
+                line,
+                "expected a '[tree:node]' header first".to_string(),
+            ));
+        };
+        let (key, value) = trimmed
+            .split_once('=')
+            .ok_or_else(|| malformed(line, "expected 'key = value'".to_string()))?;
+        let node_entry = trees
+            .get_mut(tree)
+            .and_then(|tree| tree.nodes.get_mut(node))
+            .expect("header inserted the node");
+        match key.trim() {
+            "text" => node_entry.text = value.trim().to_string(),
+            "reply" => {
+                if node_entry.replies.len() == MAX_REPLIES {
+                    return Err(malformed(
+                        line,
+                        format!("a node has at most {} replies", MAX_REPLIES),
+                    ));
+                }
+                let reply = parse_reply(value).map_err(|reason| malformed(line, reason))?;
+                if let Some(next) = &reply.next {
+                    targets.push((line, tree.clone(), next.clone()));
+                }
+                node_entry.replies.push(reply);
+            }
+            other => return Err(malformed(line, format!("unknown key '{}'", other))),
+        }
+    }
+
+    for (line, tree, node) in targets {
+        if !trees[&tree].nodes.contains_key(&node) {
+            return Err(DialogueError::UnknownNode {
+                file: file.to_path_buf(),
+                line,
+                node,
+            });
+        }
+    }
+    for (id, tree) in trees {
+        if !tree.nodes.contains_key(START_NODE) {
+            return Err(DialogueError::MissingStart {
+                file: file.to_path_buf(),
+                tree: id,
+            });
+        }
+        library.trees.insert(id, tree);
+    }
+    Ok(())
+}
+
+fn is_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// `Text | next | if conditions | do effects`
+fn parse_reply(value: &str) -> Result<Reply, String> {
+    let fields: Vec<&str> = value.split('|').map(str::trim).collect();
+    if fields.len() < 2 || fields[0].is_empty() || fields[1].is_empty() {
+        return Err("expected 'text | next node', then optional 'if ...' and 'do ...'".to_string());
+    }
+    let mut reply = Reply {
+        text: fields[0].to_string(),
+        next: (fields[1] != END).then(|| fields[1].to_string()),
+        conditions: Vec::new(),
+        effects: Vec::new(),
+    };
+    for field in &fields[2..] {
+        if let Some(conditions) = field.strip_prefix("if ") {
+            for condition in conditions.split(',').map(str::trim) {
+                reply.conditions.push(parse_condition(condition)?);
+            }
+        } else if let Some(effects) = field.strip_prefix("do ") {
+            for effect in effects.split(',').map(str::trim) {
+                reply.effects.push(parse_effect(effect)?);
+            }
+        } else {
+            return Err(format!("expected 'if ...' or 'do ...', found '{}'", field));
+        }
+    }
+    Ok(reply)
+}
+
+fn parse_condition(condition: &str) -> Result<Condition, String> {
+    let (kind, argument) = condition
+        .split_once(' ')
+        .map(|(kind, argument)| (kind, argument.trim()))
+        .unwrap_or((condition, ""));
+    let invalid = || format!("invalid number '{}' in '{}'", argument, condition);
+    match kind {
+        "level" => argument
+            .parse()
+            .map(Condition::Level)
+            .map_err(|_| invalid()),
+        "charisma" => argument
+            .parse()
+            .map(Condition::Charisma)
+            .map_err(|_| invalid()),
+        "reputation" => argument
+            .parse()
+            .map(Condition::Reputation)
+            .map_err(|_| invalid()),
+        "item" if !argument.is_empty() => Ok(Condition::Item(argument.to_string())),
+        "quest" => {
+            let (id, state) = argument.split_once(' ').ok_or_else(|| {
+                format!(
+                    "expected 'quest ID none|active|done', found '{}'",
+                    condition
+                )
+            })?;
+            let state = match state.trim() {
+                "none" => None,
+                name => Some(
+                    QuestState::from_name(name)
+                        .ok_or_else(|| format!("unknown quest state '{}'", name))?,
+                ),
+            };
+            Ok(Condition::Quest(id.to_string(), state))
+        }
+        _ => Err(format!("unknown condition '{}'", condition)),
+    }
+}
+
+fn parse_effect(effect: &str) -> Result<Effect, String> {
+    let (kind, argument) = effect
+        .split_once(' ')
+        .map(|(kind, argument)| (kind, argument.trim()))
+        .unwrap_or((effect, ""));
+    if argument.is_empty() {
+        return Err(format!("effect '{}' needs an argument", effect));
+    }
+    match kind {
+        "give" => Ok(Effect::GiveItem(argument.to_string())),
+        "take" => Ok(Effect::TakeItem(argument.to_string())),
+        "start-quest" => Ok(Effect::StartQuest(argument.to_string())),
+        "complete-quest" => Ok(Effect::CompleteQuest(argument.to_string())),
+        "reputation" => argument
+            .parse()
+            .map(Effect::Reputation)
+            .map_err(|_| format!("invalid reputation change '{}'", argument)),
+        _ => Err(format!("unknown effect '{}'", effect)),
+    }
+}
+
+/// What picking a reply did.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Choice {
+    pub messages: Vec<String>,
+    pub ended: bool,
+}
+
+/// A dialogue in progress between the player and one NPC.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversation {
+    pub npc_name: String,
+    pub tree: String,
+    pub node: String,
+}
+
+impl Conversation {
+    pub fn new(npc_name: &str, tree: &str) -> Self {
+        Self {
+            npc_name: npc_name.to_string(),
+            tree: tree.to_string(),
+            node: START_NODE.to_string(),
+        }
+    }
+
+    pub fn text<'a>(&self, library: &'a DialogueLibrary) -> &'a str {
+        library
+            .node(&self.tree, &self.node)
+            .map_or("", |node| node.text.as_str())
+    }
+
+    /// Replies the player may pick right now, numbered from 1 in the UI.
+    pub fn replies<'a>(&self, library: &'a DialogueLibrary, player: &Player) -> Vec<&'a Reply> {
+        library
+            .node(&self.tree, &self.node)
+            .map(|node| {
+                node.replies
+                    .iter()
+                    .filter(|reply| reply.is_available(player))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Picks reply `number` (1-based) of `replies`, applies its effects to
+    /// the player and moves on. Returns `None` for a number not on offer.
+    pub fn choose(
+        &mut self,
+        library: &DialogueLibrary,
+        player: &mut Player,
+        number: usize,
+    ) -> Option<Choice> {
+        let reply = number
+            .checked_sub(1)
+            .and_then(|index| self.replies(library, player).get(index).copied())?
+            .clone();
+        let mut choice = Choice::default();
+        for effect in &reply.effects {
+            apply_effect(effect, player, &mut choice);
+        }
+        match reply.next {
+            Some(next) => self.node = next,
+            None => choice.ended = true,
+        }
+        Some(choice)
+    }
+}
+
+fn apply_effect(effect: &Effect, player: &mut Player, choice: &mut Choice) {
+    match effect {
+        Effect::GiveItem(name) => {
+            player.inventory.push(Item::new(name, Position::default()));
+            choice.messages.push(format!("You receive {}.", name));
+        }
+        Effect::TakeItem(name) => {
+            if let Some(index) = player.inventory.iter().position(|item| item.name == *name) {
+                player.inventory.remove(index);
+                choice.messages.push(format!("You hand over {}.", name));
+            }
+        }
+        Effect::StartQuest(id) => {
+            player.quests.insert(id.clone(), QuestState::Active);
+            choice.messages.push(format!("Quest started: {}.", id));
+        }
+        Effect::CompleteQuest(id) => {
+            player.quests.insert(id.clone(), QuestState::Done);
+            choice.messages.push(format!("Quest completed: {}.", id));
+        }
+        Effect::Reputation(change) => {
+            player.reputation += change;
+            let direction = if *change >= 0 { "rises" } else { "falls" };
+            choice
+                .messages
+                .push(format!("Your reputation {}.", direction));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMITH: &str = "\
+; The Britain blacksmith
+[smith:start]
+text = Need a blade mended?
+reply = Any work for me? | work | if level 2, quest ore none
+reply = Here is your ore. | thanks | if item Iron Ore, quest ore active | do take Iron Ore, complete-quest ore, reputation +5
+reply = Any news from the mine? | end | if reputation 5
+reply = Farewell. | end
+
+[smith:work]
+text = Bring me iron ore from the mine.
+reply = I will. | start | do start-quest ore, give Pickaxe
+
+[smith:thanks]
+text = Fine ore! Thank you.
+reply = Goodbye. | end
+";
+
+    fn library() -> DialogueLibrary {
+        let mut library = DialogueLibrary::new();
+        parse_dialogues(Path::new("britain.dlg"), SMITH, &mut library).unwrap();
+        library
+    }
+
+    fn error(contents: &str) -> String {
+        parse_dialogues(
+            Path::new("britain.dlg"),
+            contents,
+            &mut DialogueLibrary::new(),
+        )
+        .unwrap_err()
+        .to_string()
+    }
+
+    fn texts(replies: Vec<&Reply>) -> Vec<&str> {
+        replies
+            .into_iter()
+            .map(|reply| reply.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_tree() {
+        let library = library();
+        let start = library.node("smith", "start").unwrap();
+        assert_eq!(start.text, "Need a blade mended?");
+        assert_eq!(start.replies.len(), 4);
+        assert_eq!(
+            start.replies[0].conditions,
+            vec![
+                Condition::Level(2),
+                Condition::Quest("ore".to_string(), None)
+            ]
+        );
+        assert_eq!(start.replies[2].conditions, vec![Condition::Reputation(5)]);
+        assert_eq!(start.replies[3].next, None);
+        assert_eq!(
+            start.replies[1].effects,
+            vec![
+                Effect::TakeItem("Iron Ore".to_string()),
+                Effect::CompleteQuest("ore".to_string()),
+                Effect::Reputation(5)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conditions_hide_replies() {
+        let library = library();
+        let mut player = Player::new("Hero");
+        let conversation = Conversation::new("Blacksmith", "smith");
+        assert_eq!(
+            texts(conversation.replies(&library, &player)),
+            vec!["Farewell."]
+        );
+
+        player.level = 2;
+        assert_eq!(conversation.replies(&library, &player).len(), 2);
+        player.quests.insert("ore".to_string(), QuestState::Active);
+        assert_eq!(
+            texts(conversation.replies(&library, &player)),
+            vec!["Farewell."]
+        );
+        player
+            .inventory
+            .push(Item::new("Iron Ore", Position::default()));
+        assert!(texts(conversation.replies(&library, &player)).contains(&"Here is your ore."));
+        assert!(Condition::Charisma(5).holds(&player) && !Condition::Charisma(6).holds(&player));
+        player.reputation = 5;
+        assert!(texts(conversation.replies(&library, &player)).contains(&"Any news from the mine?"));
+    }
+
+    #[test]
+    fn test_quest_through_dialogue() {
+        let library = library();
+        let mut player = Player::new("Hero");
+        player.level = 2;
+        let mut conversation = Conversation::new("Blacksmith", "smith");
+
+        assert_eq!(conversation.choose(&library, &mut player, 9), None);
+        let choice = conversation.choose(&library, &mut player, 1).unwrap();
+        assert_eq!(conversation.node, "work");
+        assert!(choice.messages.is_empty() && !choice.ended);
+        assert_eq!(
+            conversation.text(&library),
+            "Bring me iron ore from the mine."
+        );
+
+        let choice = conversation.choose(&library, &mut player, 1).unwrap();
+        assert_eq!(
+            choice.messages,
+            vec![
+                "Quest started: ore.".to_string(),
+                "You receive Pickaxe.".to_string()
+            ]
+        );
+        assert_eq!(player.quests.get("ore"), Some(&QuestState::Active));
+        assert_eq!(conversation.node, "start");
+
+        player
+            .inventory
+            .push(Item::new("Iron Ore", Position::default()));
+        let choice = conversation.choose(&library, &mut player, 1).unwrap();
+        assert_eq!(choice.messages.last().unwrap(), "Your reputation rises.");
+        assert_eq!(
+            (player.quests.get("ore"), player.reputation),
+            (Some(&QuestState::Done), 5)
+        );
+        assert!(!player.inventory.iter().any(|item| item.name == "Iron Ore"));
+
+        conversation.node = START_NODE.to_string();
+        assert_eq!(
+            texts(conversation.replies(&library, &player)),
+            vec!["Any news from the mine?", "Farewell."]
+        );
+        assert!(conversation.choose(&library, &mut player, 1).unwrap().ended);
+    }
+
+    #[test]
+    fn test_errors_name_file_and_line() {
+        assert_eq!(
+            error("[smith:start]\ntext = Hi\nreply = Go | nowhere\n"),
+            "britain.dlg: line 3: reply leads to unknown node 'nowhere'"
+        );
+        assert_eq!(
+            error("[smith:intro]\ntext = Hi\n"),
+            "britain.dlg: dialogue 'smith' has no 'start' node"
+        );
+        assert!(error("[smith:start]\nreply = Go | end | if luck 3\n")
+            .starts_with("britain.dlg: line 2: unknown condition"));
+        assert!(error("[smith:start]\nreply = Go | end | do dance\n").contains("line 2"));
+        assert!(error("[smith:start]\nreply = Go | end | maybe\n")
+            .contains("expected 'if ...' or 'do ...'"));
+        assert!(error("[smith]\n").contains("line 1"));
+        assert!(error("text = Hi\n").contains("header first"));
+        assert!(error("[smith:start]\n[smith:start]\n").contains("defined twice"));
+        assert!(error("[smith:start]\nreply = Go | end | if level -1\n")
+            .contains("invalid number '-1'"));
+        let crowded = format!("[smith:start]\n{}", "reply = Go | end\n".repeat(10));
+        assert_eq!(
+            error(&crowded),
+            "britain.dlg: line 11: a node has at most 9 replies"
+        );
+    }
+
+    #[test]
+    fn test_load_bundled_dialogues() {
+        let library = load_dialogues(DIALOGUE_DIR).unwrap();
+        for tree in ["banker", "blacksmith", "farmer"] {
+            assert!(
+                library.node(tree, START_NODE).is_some(),
+                "missing dialogue {}",
+                tree
+            );
+        }
+        let templates =
+            crate::entities::template::load_templates(crate::entities::template::TEMPLATES_DIR)
+                .unwrap();
+        for template in templates.iter() {
+            if let Some(tree) = &template.conversation {
+                assert!(
+                    library.get(tree).is_some(),
+                    "template {} has unknown conversation {}",
+                    template.id,
+                    tree
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,486 @@
+//! Map editor: paints terrain, places NPCs and items and moves the spawn
+//! point of the current map, then writes it back to its map file. The UI
+//! only turns key presses into calls on `Editor`.
+//!
+//! Every edit is applied twice: to the live map, so the designer sees it,
+//! and to the map as authored, which is what gets saved. NPCs that walked
+//! away or items the player picked up therefore stay where the designer
+//! put them. Streamed maps have no map file; their chunks are written back
+//! as they are left behind.
+
+use std::path::{Path, PathBuf};
+
+use crate::entities::npc::NPC;
+use crate::inventory::item::Item;
+use crate::maps::map_file::{check_field, format_map, load_map, parse_map, save_map, MapFile};
+use crate::maps::maps::Maps;
+use crate::maps::position::{Direction, Position};
+use crate::maps::terrain::{Terrain, ALL_TERRAIN};
+
+const DEFAULT_HEALTH: i32 = 50;
+const DEFAULT_STRENGTH: i32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PromptKind {
+    Npc,
+    Item,
+}
+
+/// Text typed by the designer to configure what gets placed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub input: String,
+}
+
+impl Prompt {
+    pub fn usage(&self) -> &'static str {
+        match self.kind {
+            PromptKind::Npc => "name | dialogue | health | strength",
+            PromptKind::Item => "name",
+        }
+    }
+}
+
+pub struct Editor {
+    pub cursor: Position,
+    brush: usize,
+    pub prompt: Option<Prompt>,
+    /// The map being edited as authored, without anything that happened
+    /// in the game since it was loaded.
+    design: Option<MapFile>,
+}
+
+impl Editor {
+    pub fn new(cursor: Position) -> Self {
+        Self {
+            cursor,
+            brush: 0,
+            prompt: None,
+            design: None,
+        }
+    }
+
+    /// Terrain painted by `paint`.
+    pub fn brush(&self) -> Terrain {
+        ALL_TERRAIN[self.brush]
+    }
+
+    pub fn next_brush(&mut self) {
+        self.brush = (self.brush + 1) % ALL_TERRAIN.len();
+    }
+
+    pub fn previous_brush(&mut self) {
+        self.brush = (self.brush + ALL_TERRAIN.len() - 1) % ALL_TERRAIN.len();
+    }
+
+    pub fn move_cursor(&mut self, map: &Maps, direction: Direction) {
+        if let Some(next) = map.neighbour(self.cursor, direction) {
+            self.cursor = next;
+        }
+    }
+
+    pub fn paint(&mut self, map_file: &mut MapFile) -> Result<String, String> {
+        let (cursor, brush) = (self.cursor, self.brush());
+        self.apply(map_file, |map_file| map_file.map.set_tile(cursor, brush))?;
+        Ok(format!("Painted {} at {}.", brush.name(), cursor))
+    }
+
+    pub fn set_spawn(&mut self, map_file: &mut MapFile) -> Result<String, String> {
+        let cursor = self.cursor;
+        self.apply(map_file, |map_file| map_file.spawn = cursor)?;
+        Ok(format!("Spawn point moved to {}.", cursor))
+    }
+
+    /// Removes every NPC and item under the cursor.
+    pub fn clear(&mut self, map_file: &mut MapFile) -> Result<String, String> {
+        let cursor = self.cursor;
+        let npcs = map_file.npcs.at(cursor).count();
+        let items = map_file.items.at(cursor).count();
+        self.apply(map_file, |map_file| {
+            map_file.npcs.take_where(|npc| npc.position == cursor);
+            map_file.items.take_where(|item| item.position == cursor);
+            map_file
+                .placements
+                .retain(|placement| placement.position != cursor);
+        })?;
+        Ok(format!(
+            "Removed {} NPCs and {} items at {}.",
+            npcs, items, cursor
+        ))
+    }
+
+    /// Opens the NPC prompt, filled in with the NPC under the cursor so it
+    /// can be reconfigured.
+    pub fn start_npc_prompt(&mut self, map_file: &MapFile) {
+        let input = match map_file.npcs.at(self.cursor).next() {
+            Some(index) => {
+                let npc = &map_file.npcs[index];
+                format!(
+                    "{} | {} | {} | {}",
+                    npc.name, npc.dialogue, npc.health, npc.strength
+                )
+            }
+            None => String::new(),
+        };
+        self.prompt = Some(Prompt {
+            kind: PromptKind::Npc,
+            input,
+        });
+    }
+
+    pub fn start_item_prompt(&mut self) {
+        self.prompt = Some(Prompt {
+            kind: PromptKind::Item,
+            input: String::new(),
+        });
+    }
+
+    /// Applies the open prompt and closes it.
+    pub fn submit_prompt(&mut self, map_file: &mut MapFile) -> Result<String, String> {
+        let Some(prompt) = self.prompt.take() else {
+            return Err("Nothing to place.".to_string());
+        };
+        match prompt.kind {
+            PromptKind::Npc => self.place_npc(map_file, &prompt.input),
+            PromptKind::Item => self.place_item(map_file, &prompt.input),
+        }
+    }
+
+    /// Places an NPC described as `name | dialogue | health | strength`;
+    /// everything but the name is optional. Replaces the NPC under the cursor.
+    pub fn place_npc(&mut self, map_file: &mut MapFile, spec: &str) -> Result<String, String> {
+        let fields: Vec<&str> = spec.split('|').map(str::trim).collect();
+        let name = fields[0];
+        if name.is_empty() {
+            return Err("An NPC needs a name.".to_string());
+        }
+        let dialogue = fields.get(1).copied().unwrap_or("");
+        let health = parse_stat(fields.get(2).copied(), "health", DEFAULT_HEALTH)?;
+        let strength = parse_stat(fields.get(3).copied(), "strength", DEFAULT_STRENGTH)?;
+        if !map_file.map.is_walkable(self.cursor) {
+            return Err(format!(
+                "{} cannot stand on {}.",
+                name,
+                self.describe_terrain(map_file)
+            ));
+        }
+
+        let cursor = self.cursor;
+        self.apply(map_file, |map_file| {
+            map_file.npcs.take_where(|npc| npc.position == cursor);
+            map_file
+                .placements
+                .retain(|placement| placement.position != cursor);
+            map_file
+                .npcs
+                .push(NPC::new(name, dialogue, cursor, health, strength));
+        })?;
+        Ok(format!("Placed {} at {}.", name, cursor))
+    }
+
+    /// Adds an item to the pile under the cursor.
+    pub fn place_item(&mut self, map_file: &mut MapFile, name: &str) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("An item needs a name.".to_string());
+        }
+        check_field("The item name", name)?;
+        if !map_file.map.is_walkable(self.cursor) {
+            return Err(format!(
+                "Items cannot lie on {}.",
+                self.describe_terrain(map_file)
+            ));
+        }
+        let cursor = self.cursor;
+        self.apply(map_file, |map_file| {
+            map_file.items.push(Item::new(name, cursor))
+        })?;
+        Ok(format!("Placed {} at {}.", name, cursor))
+    }
+
+    /// Writes the authored map to the `.map` file it came from, or to a new
+    /// file named after the map inside `maps_dir`.
+    pub fn save(&mut self, map_file: &mut MapFile, maps_dir: &Path) -> Result<String, String> {
+        let Some(design) = self.design(map_file)? else {
+            return Ok(format!(
+                "{} is streamed; its changes are saved as you explore.",
+                map_file.name
+            ));
+        };
+        let path = design
+            .path
+            .clone()
+            .unwrap_or_else(|| default_path(maps_dir, &design.name));
+        save_map(&path, design)
+            .map_err(|err| format!("Could not save {}: {}", path.display(), err))?;
+        let message = format!("Saved {} to {}.", design.name, path.display());
+        design.path = Some(path.clone());
+        map_file.path = Some(path);
+        Ok(message)
+    }
+
+    /// Status lines for the side panel.
+    pub fn status(&self, map_file: &MapFile) -> String {
+        let mut lines = vec![
+            format!("Cursor: {}", self.cursor),
+            format!("Terrain: {}", self.describe_terrain(map_file)),
+            format!("Brush: {} '{}'", self.brush().name(), self.brush().glyph()),
+            format!("Spawn: {}", map_file.spawn),
+        ];
+        lines.extend(
+            map_file
+                .npcs
+                .at(self.cursor)
+                .map(|index| format!("NPC: {}", map_file.npcs[index].name)),
+        );
+        lines.extend(
+            map_file
+                .items
+                .at(self.cursor)
+                .map(|index| format!("Item: {}", map_file.items[index].name)),
+        );
+        lines.join("\n")
+    }
+
+    /// Runs `edit` on the live map and on its authored copy. Neither is
+    /// changed when the authored copy cannot be made.
+    fn apply(&mut self, map_file: &mut MapFile, edit: impl Fn(&mut MapFile)) -> Result<(), String> {
+        if let Some(design) = self.design(map_file)? {
+            edit(design);
+        }
+        edit(map_file);
+        Ok(())
+    }
+
+    /// The authored copy of `map_file`, read again from its `.map` file
+    /// when the editor moves to another map. Maps without one (generated or
+    /// imported from Tiled) start from how they are now. `None` for
+    /// streamed maps.
+    fn design(&mut self, map_file: &MapFile) -> Result<Option<&mut MapFile>, String> {
+        if map_file.map.is_streamed() {
+            return Ok(None);
+        }
+        if self
+            .design
+            .as_ref()
+            .is_none_or(|design| design.name != map_file.name)
+        {
+            let from_file = map_file
+                .path
+                .as_ref()
+                .filter(|path| path.extension().is_some_and(|extension| extension == "map"))
+                .and_then(|path| load_map(path).ok());
+            let design = match from_file {
+                Some(design) => design,
+                None => {
+                    let mut snapshot = parse_map(&format_map(map_file)).map_err(|err| {
+                        format!("Could not copy {} for editing: {}", map_file.name, err)
+                    })?;
+                    snapshot.path = None;
+                    snapshot
+                }
+            };
+            self.design = Some(design);
+        }
+        Ok(self.design.as_mut())
+    }
+
+    fn describe_terrain(&self, map_file: &MapFile) -> &'static str {
+        map_file
+            .map
+            .terrain(self.cursor)
+            .map_or("nothing", |terrain| terrain.name())
+    }
+}
+
+fn parse_stat(field: Option<&str>, name: &str, default: i32) -> Result<i32, String> {
+    match field {
+        None | Some("") => Ok(default),
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("Invalid {} '{}'.", name, value)),
+    }
+}
+
+/// `Dark Forest` becomes `maps_dir/dark_forest.map`.
+fn default_path(maps_dir: &Path, name: &str) -> PathBuf {
+    let stem: String = name
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let stem = if stem.is_empty() {
+        "untitled".to_string()
+    } else {
+        stem
+    };
+    maps_dir.join(format!("{}.map", stem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maps::map_file::load_map;
+    use crate::maps::spatial::EntityList;
+    use crate::world::weather::Climate;
+
+    fn map_file() -> MapFile {
+        MapFile {
+            name: "Dark Forest".to_string(),
+            spawn: Position::new(1, 1),
+            climate: Climate::default(),
+            map: Maps::with_walls(8, 6),
+            npcs: EntityList::new(),
+            items: EntityList::new(),
+            portals: Vec::new(),
+            placements: Vec::new(),
+            spawners: Vec::new(),
+            path: None,
+        }
+    }
+
+    #[test]
+    fn test_paint_with_brush() {
+        let mut map_file = map_file();
+        let mut editor = Editor::new(Position::new(2, 2));
+        assert_eq!(editor.brush(), ALL_TERRAIN[0]);
+        editor.previous_brush();
+        assert_eq!(editor.brush(), ALL_TERRAIN[ALL_TERRAIN.len() - 1]);
+        while editor.brush() != Terrain::Water {
+            editor.next_brush();
+        }
+        editor.paint(&mut map_file).unwrap();
+        assert_eq!(
+            map_file.map.terrain(Position::new(2, 2)),
+            Some(Terrain::Water)
+        );
+    }
+
+    #[test]
+    fn test_cursor_stays_on_map() {
+        let map_file = map_file();
+        let mut editor = Editor::new(Position::new(0, 0));
+        editor.move_cursor(&map_file.map, Direction::North);
+        assert_eq!(editor.cursor, Position::new(0, 0));
+        editor.move_cursor(&map_file.map, Direction::SouthEast);
+        assert_eq!(editor.cursor, Position::new(1, 1));
+    }
+
+    #[test]
+    fn test_place_and_configure_npc() {
+        let mut map_file = map_file();
+        let mut editor = Editor::new(Position::new(3, 3));
+        assert!(editor.place_npc(&mut map_file, "Guard | Halt!").is_ok());
+        assert_eq!(map_file.npcs[0].health, DEFAULT_HEALTH);
+
+        editor.start_npc_prompt(&map_file);
+        assert_eq!(
+            editor.prompt.as_ref().unwrap().input,
+            "Guard | Halt! | 50 | 10"
+        );
+        editor.prompt.as_mut().unwrap().input = "Captain | Report! | 80 | 15".to_string();
+        assert!(editor.submit_prompt(&mut map_file).is_ok());
+        assert_eq!(map_file.npcs.len(), 1);
+        assert_eq!(map_file.npcs[0].name, "Captain");
+        assert_eq!(map_file.npcs[0].strength, 15);
+        assert!(editor.prompt.is_none());
+
+        assert!(editor.place_npc(&mut map_file, "Orc | Grr | lots").is_err());
+        assert!(editor.place_npc(&mut map_file, " | Grr").is_err());
+        editor.cursor = Position::new(0, 0);
+        assert!(editor.place_npc(&mut map_file, "Ghost").is_err());
+    }
+
+    #[test]
+    fn test_items_stack_and_clear() {
+        let mut map_file = map_file();
+        let mut editor = Editor::new(Position::new(2, 3));
+        editor.place_item(&mut map_file, "Health Potion").unwrap();
+        editor.place_item(&mut map_file, "Gold").unwrap();
+        assert!(editor.place_item(&mut map_file, "Gold | 5").is_err());
+        editor.place_npc(&mut map_file, "Guard").unwrap();
+        assert_eq!(map_file.items.len(), 2);
+        assert!(editor.status(&map_file).contains("Item: Gold"));
+
+        assert_eq!(
+            editor.clear(&mut map_file).unwrap(),
+            "Removed 1 NPCs and 2 items at 2,3."
+        );
+        assert!(map_file.items.is_empty() && map_file.npcs.is_empty());
+    }
+
+    #[test]
+    fn test_uncopyable_map_is_reported() {
+        let mut map_file = map_file();
+        map_file.spawn = Position::new(20, 20);
+        let mut editor = Editor::new(Position::new(2, 2));
+        let err = editor.paint(&mut map_file).unwrap_err();
+        assert!(
+            err.starts_with("Could not copy Dark Forest for editing: "),
+            "{}",
+            err
+        );
+        assert_eq!(
+            map_file.map.terrain(Position::new(2, 2)),
+            Some(Terrain::Floor)
+        );
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        let folder = std::env::temp_dir().join(format!("uo_editor_{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let mut map_file = map_file();
+        let mut editor = Editor::new(Position::new(4, 2));
+        while editor.brush() != Terrain::Tree {
+            editor.next_brush();
+        }
+        editor.paint(&mut map_file).unwrap();
+        editor.set_spawn(&mut map_file).unwrap();
+        editor.place_item(&mut map_file, "Health Potion").unwrap();
+
+        editor.save(&mut map_file, &folder).unwrap();
+        let path = folder.join("dark_forest.map");
+        assert_eq!(map_file.path.as_deref(), Some(path.as_path()));
+
+        let loaded = load_map(&path).unwrap();
+        assert_eq!(loaded.map.terrain(Position::new(4, 2)), Some(Terrain::Tree));
+        assert_eq!(loaded.spawn, Position::new(4, 2));
+        assert_eq!(loaded.items[0].name, "Health Potion");
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_save_keeps_the_authored_map() {
+        let folder = std::env::temp_dir().join(format!("uo_editor_design_{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("dark_forest.map");
+        let mut authored = map_file();
+        authored
+            .npcs
+            .push(NPC::new("Guard", "Halt!", Position::new(2, 2), 50, 10));
+        authored.items.push(Item::new("Gold", Position::new(3, 3)));
+        save_map(&path, &authored).unwrap();
+
+        // What happened in the game since the map was loaded.
+        let mut live = load_map(&path).unwrap();
+        live.npcs.get_mut(0).unwrap().position = Position::new(5, 4);
+        live.items.take_where(|_| true);
+
+        let mut editor = Editor::new(Position::new(1, 4));
+        editor.place_item(&mut live, "Torch").unwrap();
+        editor.cursor = Position::new(2, 2);
+        editor.place_npc(&mut live, "Captain").unwrap();
+        assert_eq!(live.npcs.len(), 2);
+        editor.save(&mut live, &folder).unwrap();
+
+        let saved = load_map(&path).unwrap();
+        assert_eq!(saved.npcs.len(), 1);
+        assert_eq!(saved.npcs[0].name, "Captain");
+        let items: Vec<&str> = saved.items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(items, vec!["Gold", "Torch"]);
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
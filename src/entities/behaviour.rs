@@ -0,0 +1,412 @@
+//! What NPCs do on their own. `tick_npcs` runs once per game tick and moves
+//! every NPC through the same occupancy rules as the player: idle NPCs stay
+//! put, wanderers roam around their home, guards walk their patrol route and
+//! hostile monsters chase the player once they see them. Any NPC with a flee
+//! threshold runs from the player when wounded below it.
+//!
+//! Behaviours are written in map files as a short spec:
+//!
+//! ```text
+//! wander 3               ; roam up to 3 tiles from home
+//! patrol 5,5 12,5 12,9   ; walk the waypoints in a loop
+//! hostile 8 10           ; chase the player seen within 8 tiles, at most
+//!                        ; 10 tiles from home
+//! hostile 6 6 flee 10    ; ... and run away below 10 health
+//! ```
+
+use std::fmt;
+
+use rand::Rng;
+
+use crate::entities::npc::NPC;
+use crate::maps::maps::Maps;
+use crate::maps::occupancy::step_npc;
+use crate::maps::pathfinding::{FlowField, Movement};
+use crate::maps::position::{Direction, Position};
+use crate::maps::spatial::EntityList;
+
+/// How far a wounded NPC looks out for the player.
+const FLEE_SIGHT: usize = 8;
+/// Chance that a wanderer takes a step on a given tick.
+const WANDER_CHANCE: f64 = 0.5;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Behaviour {
+    /// Stands still.
+    #[default]
+    Idle,
+    /// Roams at random, never further than `leash` tiles from `home`.
+    Wander { home: Position, leash: usize },
+    /// Walks from waypoint to waypoint, starting over after the last one.
+    /// `next` is the waypoint currently headed for.
+    Patrol {
+        waypoints: Vec<Position>,
+        next: usize,
+    },
+    /// Wanders like `Wander` until the player comes within `sight` tiles,
+    /// then chases them as long as they stay in sight and near home.
+    Hostile {
+        home: Position,
+        leash: usize,
+        sight: usize,
+    },
+}
+
+/// What an NPC is busy with right now; changes are announced to the player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mood {
+    #[default]
+    Calm,
+    Chasing,
+    Fleeing,
+}
+
+impl Behaviour {
+    /// Parses a behaviour spec plus an optional `flee <health>` suffix.
+    /// `home` is where wanderers and hostiles return to. Returns the
+    /// behaviour and the flee threshold (0 when the NPC never flees).
+    pub fn parse(spec: &str, home: Position) -> Result<(Behaviour, i32), String> {
+        let words: Vec<&str> = spec.split_whitespace().collect();
+        let (words, flee_below) = match words.iter().position(|&word| word == "flee") {
+            Some(at) => match &words[at + 1..] {
+                [health] => (&words[..at], parse_number(health, "flee health")?),
+                _ => return Err("expected 'flee <health>' at the end".to_string()),
+            },
+            None => (&words[..], 0),
+        };
+
+        let behaviour = match words {
+            [] | ["idle"] => Behaviour::Idle,
+            ["wander", leash] => Behaviour::Wander { home, leash: parse_number(leash, "leash")? },
+            ["patrol", waypoints @ ..] if !waypoints.is_empty() => Behaviour::Patrol {
+                waypoints: waypoints.iter().map(|waypoint| parse_position(waypoint)).collect::<Result<_, _>>()?,
+                next: 0,
+            },
+            ["hostile", sight, leash] => Behaviour::Hostile {
+                home,
+                leash: parse_number(leash, "leash")?,
+                sight: parse_number(sight, "sight")?,
+            },
+            _ => {
+                return Err(format!(
+                    "unknown behaviour '{}', expected idle, wander <leash>, patrol <x,y> ... or hostile <sight> <leash>",
+                    words.join(" ")
+                ))
+            }
+        };
+        Ok((behaviour, flee_below))
+    }
+
+    /// The patrol route, empty for every other behaviour.
+    pub fn waypoints(&self) -> &[Position] {
+        match self {
+            Behaviour::Patrol { waypoints, .. } => waypoints,
+            _ => &[],
+        }
+    }
+}
+
+impl fmt::Display for Behaviour {
+    /// The spec `parse` reads, without the flee threshold.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Behaviour::Idle => write!(f, "idle"),
+            Behaviour::Wander { leash, .. } => write!(f, "wander {}", leash),
+            Behaviour::Patrol { waypoints, .. } => {
+                let waypoints: Vec<String> = waypoints.iter().map(Position::to_string).collect();
+                write!(f, "patrol {}", waypoints.join(" "))
+            }
+            Behaviour::Hostile { leash, sight, .. } => write!(f, "hostile {} {}", sight, leash),
+        }
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, field: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid {} '{}'", field, value))
+}
+
+fn parse_position(value: &str) -> Result<Position, String> {
+    let invalid = || format!("invalid waypoint '{}', expected 'x,y'", value);
+    let (x, y) = value.split_once(',').ok_or_else(invalid)?;
+    Ok(Position::new(
+        x.parse().map_err(|_| invalid())?,
+        y.parse().map_err(|_| invalid())?,
+    ))
+}
+
+/// Runs one tick of every NPC's behaviour on the map. Returns what the
+/// player should be told, e.g. that a monster noticed them.
+pub fn tick_npcs<R: Rng>(
+    map: &Maps,
+    npcs: &mut EntityList<NPC>,
+    player: Position,
+    rng: &mut R,
+) -> Vec<String> {
+    let mut messages = Vec::new();
+    // Every chaser heads for the same target, so they share one flow field.
+    let mut chase_field: Option<FlowField> = None;
+
+    for index in 0..npcs.len() {
+        let mood = next_mood(map, &npcs[index], player);
+        if mood != npcs[index].mood {
+            match mood {
+                Mood::Chasing => messages.push(format!("{} notices you!", npcs[index].name)),
+                Mood::Fleeing => messages.push(format!("{} flees!", npcs[index].name)),
+                Mood::Calm => (),
+            }
+            if let Some(mut npc) = npcs.get_mut(index) {
+                npc.mood = mood;
+            }
+        }
+
+        match mood {
+            Mood::Fleeing => flee(map, npcs, index, player),
+            Mood::Chasing => {
+                let field =
+                    chase_field.get_or_insert_with(|| map.flow_field(player, Movement::FourWay));
+                chase(map, npcs, index, field, player);
+            }
+            Mood::Calm => follow_behaviour(map, npcs, index, rng),
+        }
+    }
+    messages
+}
+
+fn next_mood(map: &Maps, npc: &NPC, player: Position) -> Mood {
+    if npc.health < npc.flee_below && map.can_see(npc.position, player, FLEE_SIGHT) {
+        return Mood::Fleeing;
+    }
+    match npc.behaviour {
+        Behaviour::Hostile { home, leash, sight }
+            if player.distance(home) <= leash + sight
+                && map.can_see(npc.position, player, sight) =>
+        {
+            Mood::Chasing
+        }
+        _ => Mood::Calm,
+    }
+}
+
+fn chase(
+    map: &Maps,
+    npcs: &mut EntityList<NPC>,
+    index: usize,
+    field: &FlowField,
+    player: Position,
+) {
+    let from = npcs[index].position;
+    if let Some(next) = field.next_step(from).filter(|&next| next != player) {
+        step_towards(map, npcs, index, next);
+    }
+}
+
+/// Steps to the free neighbour furthest from the player, if any is further
+/// than where the NPC stands.
+fn flee(map: &Maps, npcs: &mut EntityList<NPC>, index: usize, player: Position) {
+    let from = npcs[index].position;
+    let away = Direction::CARDINAL
+        .into_iter()
+        .filter_map(|direction| map.try_step(from, direction, npcs).ok())
+        .filter(|next| next.distance(player) > from.distance(player))
+        .max_by_key(|next| next.distance(player));
+    if let Some(next) = away {
+        step_towards(map, npcs, index, next);
+    }
+}
+
+fn follow_behaviour<R: Rng>(map: &Maps, npcs: &mut EntityList<NPC>, index: usize, rng: &mut R) {
+    let position = npcs[index].position;
+    match npcs[index].behaviour.clone() {
+        Behaviour::Idle => (),
+        Behaviour::Wander { home, leash } | Behaviour::Hostile { home, leash, .. } => {
+            wander(map, npcs, index, home, leash, rng)
+        }
+        Behaviour::Patrol {
+            waypoints,
+            mut next,
+        } => {
+            if waypoints.get(next) == Some(&position) {
+                next = (next + 1) % waypoints.len();
+                if let Some(mut npc) = npcs.get_mut(index) {
+                    npc.behaviour = Behaviour::Patrol {
+                        waypoints: waypoints.clone(),
+                        next,
+                    };
+                }
+            }
+            if let Some(&target) = waypoints.get(next) {
+                walk_to(map, npcs, index, target);
+            }
+        }
+    }
+}
+
+/// Takes a random step now and then, or heads home when too far away (e.g.
+/// after a chase).
+fn wander<R: Rng>(
+    map: &Maps,
+    npcs: &mut EntityList<NPC>,
+    index: usize,
+    home: Position,
+    leash: usize,
+    rng: &mut R,
+) {
+    let position = npcs[index].position;
+    if position.distance(home) > leash {
+        walk_to(map, npcs, index, home);
+        return;
+    }
+    if !rng.gen_bool(WANDER_CHANCE) {
+        return;
+    }
+    let direction = Direction::CARDINAL[rng.gen_range(0..Direction::CARDINAL.len())];
+    if position
+        .step(direction)
+        .is_some_and(|next| next.distance(home) <= leash)
+    {
+        step_npc(map, npcs, index, direction);
+    }
+}
+
+fn walk_to(map: &Maps, npcs: &mut EntityList<NPC>, index: usize, target: Position) {
+    let from = npcs[index].position;
+    if let Some(next) = map
+        .find_path(from, target, Movement::FourWay)
+        .and_then(|path| path.first().copied())
+    {
+        step_towards(map, npcs, index, next);
+    }
+}
+
+fn step_towards(map: &Maps, npcs: &mut EntityList<NPC>, index: usize, next: Position) {
+    if let Some(direction) = npcs[index].position.direction_to(next) {
+        step_npc(map, npcs, index, direction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn npc_with(behaviour: &str, position: Position) -> NPC {
+        let mut npc = NPC::new("Orc", "Grrr!", position, 40, 8);
+        let (behaviour, flee_below) = Behaviour::parse(behaviour, position).unwrap();
+        npc.behaviour = behaviour;
+        npc.flee_below = flee_below;
+        npc
+    }
+
+    #[test]
+    fn test_parse_and_format() {
+        let home = Position::new(4, 4);
+        assert_eq!(Behaviour::parse("", home), Ok((Behaviour::Idle, 0)));
+        assert_eq!(
+            Behaviour::parse("wander 3", home),
+            Ok((Behaviour::Wander { home, leash: 3 }, 0))
+        );
+        let (patrol, flee_below) = Behaviour::parse("patrol 1,1 5,1 flee 20", home).unwrap();
+        assert_eq!(flee_below, 20);
+        assert_eq!(patrol.to_string(), "patrol 1,1 5,1");
+        let (hostile, _) = Behaviour::parse("hostile 8 10", home).unwrap();
+        assert_eq!(
+            hostile,
+            Behaviour::Hostile {
+                home,
+                leash: 10,
+                sight: 8
+            }
+        );
+        assert_eq!(hostile.to_string(), "hostile 8 10");
+
+        assert!(Behaviour::parse("dance", home).is_err());
+        assert!(Behaviour::parse("wander far", home).is_err());
+        assert!(Behaviour::parse("patrol", home).is_err());
+        assert!(Behaviour::parse("wander 3 flee", home).is_err());
+    }
+
+    #[test]
+    fn test_wanderers_stay_on_their_leash() {
+        let map = Maps::new(20, 20);
+        let home = Position::new(10, 10);
+        let mut npcs = EntityList::from(vec![npc_with("wander 2", home)]);
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut moved = false;
+        for _ in 0..100 {
+            tick_npcs(&map, &mut npcs, Position::new(0, 0), &mut rng);
+            assert!(npcs[0].position.distance(home) <= 2);
+            moved |= npcs[0].position != home;
+        }
+        assert!(moved);
+    }
+
+    #[test]
+    fn test_guards_patrol_waypoints() {
+        let map = Maps::new(10, 10);
+        let mut npcs = EntityList::from(vec![npc_with("patrol 1,1 4,1", Position::new(1, 1))]);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut visited = Vec::new();
+        for _ in 0..6 {
+            tick_npcs(&map, &mut npcs, Position::new(9, 9), &mut rng);
+            visited.push(npcs[0].position.x);
+        }
+        assert_eq!(visited, vec![2, 3, 4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn test_hostiles_chase_what_they_see() {
+        let map = Maps::new(20, 5);
+        let mut npcs = EntityList::from(vec![npc_with("hostile 5 10", Position::new(10, 2))]);
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let messages = tick_npcs(&map, &mut npcs, Position::new(1, 2), &mut rng);
+        assert!(messages.is_empty(), "the player is out of sight");
+        assert_eq!(npcs[0].mood, Mood::Calm);
+
+        let player = Position::new(6, 2);
+        let messages = tick_npcs(&map, &mut npcs, player, &mut rng);
+        assert_eq!(messages, vec!["Orc notices you!".to_string()]);
+        for _ in 0..10 {
+            tick_npcs(&map, &mut npcs, player, &mut rng);
+        }
+        assert_eq!(
+            npcs[0].position.distance(player),
+            1,
+            "stops next to the player"
+        );
+    }
+
+    #[test]
+    fn test_wounded_npcs_flee() {
+        let map = Maps::new(20, 5);
+        let mut npcs =
+            EntityList::from(vec![npc_with("hostile 5 10 flee 15", Position::new(8, 2))]);
+        let mut rng = StdRng::seed_from_u64(1);
+        let player = Position::new(6, 2);
+
+        if let Some(mut orc) = npcs.get_mut(0) {
+            orc.health = 10;
+        }
+        let messages = tick_npcs(&map, &mut npcs, player, &mut rng);
+        assert_eq!(messages, vec!["Orc flees!".to_string()]);
+        assert_eq!(npcs[0].position, Position::new(9, 2));
+        tick_npcs(&map, &mut npcs, player, &mut rng);
+        assert_eq!(npcs[0].position, Position::new(10, 2));
+    }
+
+    #[test]
+    fn test_npcs_do_not_walk_through_each_other() {
+        let map = Maps::new(10, 3);
+        let mut npcs = EntityList::from(vec![
+            npc_with("patrol 5,1", Position::new(3, 1)),
+            NPC::new("Statue", "...", Position::new(4, 1), 100, 0),
+        ]);
+        let mut rng = StdRng::seed_from_u64(1);
+        tick_npcs(&map, &mut npcs, Position::new(9, 2), &mut rng);
+        assert_ne!(npcs[0].position, Position::new(4, 1));
+        assert_eq!(npcs[1].position, Position::new(4, 1));
+    }
+}
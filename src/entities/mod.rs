@@ -1,2 +1,5 @@
-pub mod player;
+pub mod behaviour;
+pub mod notoriety;
 pub mod npc;
+pub mod player;
+pub mod template;
@@ -0,0 +1,146 @@
+//! Notoriety: how the world sees a character. It decides who may attack
+//! whom, who attacks first and what a fight costs, after Ultima Online's
+//! blue (innocent), grey (criminal) and red (murderer) names.
+
+use crate::entities::npc::NPC;
+use crate::entities::player::Player;
+use crate::maps::spatial::EntityList;
+
+/// Innocents killed before the player is branded a murderer.
+pub const MURDERER_KILLS: u32 = 5;
+
+/// Game minutes a criminal flag lasts after the last crime.
+pub const CRIMINAL_MINUTES: u64 = 2 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Notoriety {
+    /// Law-abiding: attacking them is a crime.
+    #[default]
+    Innocent,
+    /// On the player's side: attacking them is a crime too.
+    Ally,
+    /// Monsters and foes: anyone may attack them, and they attack first.
+    Enemy,
+    /// Recently attacked an innocent: anyone may attack them.
+    Criminal,
+    /// Killed too many innocents: anyone may attack them, and they attack
+    /// first.
+    Murderer,
+    /// Cannot be attacked at all, like town guards and healers.
+    Invulnerable,
+}
+
+pub const ALL_NOTORIETY: [Notoriety; 6] = [
+    Notoriety::Innocent,
+    Notoriety::Ally,
+    Notoriety::Enemy,
+    Notoriety::Criminal,
+    Notoriety::Murderer,
+    Notoriety::Invulnerable,
+];
+
+impl Notoriety {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Notoriety::Innocent => "innocent",
+            Notoriety::Ally => "ally",
+            Notoriety::Enemy => "enemy",
+            Notoriety::Criminal => "criminal",
+            Notoriety::Murderer => "murderer",
+            Notoriety::Invulnerable => "invulnerable",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Notoriety> {
+        ALL_NOTORIETY
+            .iter()
+            .copied()
+            .find(|notoriety| notoriety.name() == name)
+    }
+
+    pub fn can_be_attacked(&self) -> bool {
+        *self != Notoriety::Invulnerable
+    }
+
+    /// Whether attacking someone of this notoriety flags the attacker as a
+    /// criminal.
+    pub fn is_protected(&self) -> bool {
+        matches!(self, Notoriety::Innocent | Notoriety::Ally)
+    }
+
+    /// Whether someone of this notoriety attacks `target` without being
+    /// provoked.
+    pub fn attacks_on_sight(&self, target: Notoriety) -> bool {
+        match self {
+            Notoriety::Enemy => !matches!(target, Notoriety::Enemy | Notoriety::Invulnerable),
+            Notoriety::Murderer => target.can_be_attacked(),
+            // Guards hunt down lawbreakers, allies fight the player's foes.
+            Notoriety::Invulnerable => matches!(target, Notoriety::Criminal | Notoriety::Murderer),
+            Notoriety::Ally => matches!(target, Notoriety::Enemy | Notoriety::Murderer),
+            Notoriety::Innocent | Notoriety::Criminal => false,
+        }
+    }
+}
+
+/// Every NPC next to the player that attacks them unprovoked strikes once.
+/// Returns one message per blow.
+pub fn npcs_attack(npcs: &EntityList<NPC>, player: &mut Player) -> Vec<String> {
+    let mut messages = Vec::new();
+    for index in npcs.in_range(player.position, 1) {
+        let npc = &npcs[index];
+        if npc.health > 0 && player.health > 0 && npc.notoriety.attacks_on_sight(player.notoriety) {
+            let damage = npc.attack();
+            player.health -= damage;
+            messages.push(format!("{} attacks you for {} damage!", npc.name, damage));
+        }
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maps::position::Position;
+
+    #[test]
+    fn test_who_attacks_whom() {
+        assert!(Notoriety::Enemy.attacks_on_sight(Notoriety::Innocent));
+        assert!(!Notoriety::Enemy.attacks_on_sight(Notoriety::Enemy));
+        assert!(!Notoriety::Innocent.attacks_on_sight(Notoriety::Murderer));
+        assert!(Notoriety::Invulnerable.attacks_on_sight(Notoriety::Criminal));
+        assert!(!Notoriety::Invulnerable.attacks_on_sight(Notoriety::Innocent));
+        assert!(!Notoriety::Murderer.attacks_on_sight(Notoriety::Invulnerable));
+        assert!(!Notoriety::Invulnerable.can_be_attacked());
+        assert!(Notoriety::Ally.is_protected() && !Notoriety::Criminal.is_protected());
+        assert_eq!(Notoriety::from_name("murderer"), Some(Notoriety::Murderer));
+    }
+
+    #[test]
+    fn test_npcs_attack_first() {
+        let mut player = Player::new("Test Player");
+        player.position = Position::new(2, 2);
+        let mut orc = NPC::new("Orc", "Grrr!", Position::new(3, 2), 40, 8);
+        orc.notoriety = Notoriety::Enemy;
+        let mut guard = NPC::new("Guard", "Halt!", Position::new(1, 2), 200, 40);
+        guard.notoriety = Notoriety::Invulnerable;
+        let mut far_orc = NPC::new("Orc", "Grrr!", Position::new(5, 2), 40, 8);
+        far_orc.notoriety = Notoriety::Enemy;
+        let npcs = EntityList::from(vec![
+            orc,
+            guard,
+            far_orc,
+            NPC::new("Farmer", "Hi", Position::new(2, 1), 20, 2),
+        ]);
+
+        assert_eq!(
+            npcs_attack(&npcs, &mut player),
+            vec!["Orc attacks you for 8 damage!".to_string()]
+        );
+        assert_eq!(player.health, 92);
+
+        player.notoriety = Notoriety::Criminal;
+        let messages = npcs_attack(&npcs, &mut player);
+        assert_eq!(messages.len(), 2, "the guard joins in against a criminal");
+        assert_eq!(player.health, 44);
+    }
+}
@@ -1,20 +1,62 @@
+use crate::entities::behaviour::{Behaviour, Mood};
+use crate::entities::notoriety::Notoriety;
+use crate::entities::template::LootEntry;
+use crate::maps::position::Position;
+use crate::maps::spatial::Positioned;
+use crate::maps::tile::Colour;
+
+// The name every module uses for non-player characters.
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug)]
 pub struct NPC {
     pub name: String,
     pub dialogue: String,
-    pub position: (usize, usize),
+    pub position: Position,
+    /// Where the NPC was placed; written back to map files instead of
+    /// wherever it has walked since.
+    pub home: Position,
     pub health: i32,
-    pub strength: i32
+    pub strength: i32,
+    /// Followers travel with the player between maps.
+    pub follower: bool,
+    pub behaviour: Behaviour,
+    /// Runs from the player when health drops below this; 0 never flees.
+    pub flee_below: i32,
+    pub mood: Mood,
+    pub notoriety: Notoriety,
+    pub glyph: char,
+    /// Drawn in its notoriety colour when unset.
+    pub colour: Option<Colour>,
+    /// Dropped where the NPC dies.
+    pub loot: Vec<LootEntry>,
+    /// Template the NPC was spawned from, if any.
+    pub template: Option<String>,
+    /// Index of the map spawner that keeps this NPC's population up.
+    pub spawner: Option<usize>,
+    /// Dialogue tree started by talking to the NPC, if any.
+    pub conversation: Option<String>,
 }
 
 impl NPC {
-    pub fn new(name: &str, dialogue: &str, position: (usize, usize), health: i32, strength: i32) -> Self {
+    pub fn new(name: &str, dialogue: &str, position: Position, health: i32, strength: i32) -> Self {
         Self {
             name: name.to_string(),
             dialogue: dialogue.to_string(),
             position,
+            home: position,
             health,
-            strength
+            strength,
+            follower: false,
+            behaviour: Behaviour::Idle,
+            flee_below: 0,
+            mood: Mood::Calm,
+            notoriety: Notoriety::Innocent,
+            glyph: 'N',
+            colour: None,
+            loot: Vec::new(),
+            template: None,
+            spawner: None,
+            conversation: None,
         }
     }
 
@@ -22,7 +64,13 @@ impl NPC {
         self.strength
     }
 
-    pub fn interact(&self) -> String{
+    pub fn interact(&self) -> String {
         self.dialogue.clone()
     }
 }
+
+impl Positioned for NPC {
+    fn position(&self) -> Position {
+        self.position
+    }
+}
@@ -1,6 +1,21 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::entities::notoriety::{Notoriety, CRIMINAL_MINUTES, MURDERER_KILLS};
 use crate::entities::npc::NPC;
 use crate::inventory::item::Item;
+use crate::maps::fov::FieldOfView;
+use crate::maps::light::TORCH;
 use crate::maps::maps::Maps;
+use crate::maps::occupancy::{Blocker, Step};
+use crate::maps::position::{Direction, Position};
+use crate::maps::region::Region;
+use crate::maps::spatial::EntityList;
+use crate::quest::quest::QuestState;
+
+/// Health of a new player, restored on respawn.
+const FULL_HEALTH: i32 = 100;
+/// Coming out of a fight with less health than this leaves the player injured.
+pub const INJURED_HEALTH: i32 = 25;
 
 pub struct Player {
     pub name: String,
@@ -12,9 +27,22 @@ pub struct Player {
     pub agility: i32,
     pub charisma: i32,
     pub inventory: Vec<Item>,
-    pub position: (usize, usize),
+    pub position: Position,
     //pub map_limits: (usize, usize),
-    pub status: PlayerStatus
+    pub status: PlayerStatus,
+    /// Tiles the player has seen at least once, by map name.
+    pub explored: HashMap<String, HashSet<Position>>,
+    /// Walking into a follower trades places with it instead of stopping.
+    pub swap_with_followers: bool,
+    pub notoriety: Notoriety,
+    /// Innocents killed so far.
+    pub murders: u32,
+    /// Game minutes until a criminal flag wears off.
+    pub criminal_minutes: u64,
+    /// Quests started so far, by id.
+    pub quests: BTreeMap<String, QuestState>,
+    /// Standing with the townsfolk, raised and lowered through dialogue.
+    pub reputation: i32,
 }
 
 #[derive(Debug, PartialEq)]
@@ -28,7 +56,7 @@ impl Player {
     pub fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
-            health: 100,
+            health: FULL_HEALTH,
             mana: 50,
             level: 1,
             experience: 0,
@@ -36,9 +64,16 @@ impl Player {
             agility: 8,
             charisma: 5,
             inventory: Vec::new(),
-            position: (1, 1),
+            position: Position::new(1, 1),
             //map_limits: (100, 100),
             status: PlayerStatus::Normal,
+            explored: HashMap::new(),
+            swap_with_followers: true,
+            notoriety: Notoriety::Innocent,
+            murders: 0,
+            criminal_minutes: 0,
+            quests: BTreeMap::new(),
+            reputation: 0,
         }
     }
 
@@ -63,15 +98,15 @@ impl Player {
 
     pub fn set_status(&mut self, status: PlayerStatus) {
         self.status = status;
-        println!("Your current status is: {:?}", self.status);
     }
 
-    pub fn check_status(&self) {
+    /// How the player is doing, for the stats panel.
+    pub fn check_status(&self) -> &'static str {
         match self.status {
-            PlayerStatus::Normal => println!("You are in good condition"),
-            PlayerStatus::InCombat => println!("You are in a fight!"),
-            PlayerStatus::Exhausted => println!("You are exhausted and need rest."),
-            PlayerStatus::Injured => println!("You are hurt and you need to heal."),
+            PlayerStatus::Normal => "You are in good condition",
+            PlayerStatus::InCombat => "You are in a fight!",
+            PlayerStatus::Exhausted => "You are exhausted and need rest.",
+            PlayerStatus::Injured => "You are hurt and you need to heal.",
         }
     }
 
@@ -79,8 +114,30 @@ impl Player {
         self.strength
     }
 
-    pub fn engage_in_combat(&mut self, npc: &mut NPC) -> String{
+    /// Fights `npc` to the end. Invulnerable NPCs cannot be fought and
+    /// regions can forbid it: in no-combat regions nothing happens, and in
+    /// guarded ones attacking an innocent calls the guards instead. Anywhere
+    /// else attacking an innocent or an ally is a crime, and killing one
+    /// counts as a murder.
+    pub fn engage_in_combat(&mut self, npc: &mut NPC, region: Option<&Region>) -> String {
+        if !npc.notoriety.can_be_attacked() {
+            return format!("{} cannot be harmed.", npc.name);
+        }
+        let crime = npc.notoriety.is_protected();
+        if let Some(region) = region {
+            if region.flags.no_combat {
+                return format!("You cannot fight in {}.", region.name);
+            }
+            if region.flags.guarded && crime {
+                self.commit_crime();
+                return format!("Guards! Fighting is forbidden in {}.", region.name);
+            }
+        }
         let mut combat_log = String::new();
+        if crime {
+            self.commit_crime();
+            combat_log.push_str("You have committed a crime!\n");
+        }
 
         loop {
             // player attack
@@ -90,6 +147,9 @@ impl Player {
 
             if npc.health <= 0 {
                 combat_log.push_str("You defeated the NPC!\n");
+                if crime && self.add_murder() {
+                    combat_log.push_str("You are now known as a murderer!\n");
+                }
                 break;
             }
 
@@ -107,7 +167,55 @@ impl Player {
         combat_log
     }
 
-    pub fn train_player(player: &mut Player){
+    /// Flags the player as a criminal for `CRIMINAL_MINUTES`. Murderers stay
+    /// murderers.
+    pub fn commit_crime(&mut self) {
+        self.criminal_minutes = CRIMINAL_MINUTES;
+        if self.notoriety != Notoriety::Murderer {
+            self.notoriety = Notoriety::Criminal;
+        }
+    }
+
+    /// Counts one more murder; returns whether it made the player a murderer.
+    fn add_murder(&mut self) -> bool {
+        self.murders += 1;
+        if self.murders >= MURDERER_KILLS && self.notoriety != Notoriety::Murderer {
+            self.notoriety = Notoriety::Murderer;
+            return true;
+        }
+        false
+    }
+
+    /// Lets game time pass for the criminal flag. Returns a message when it
+    /// wears off.
+    pub fn pass_time(&mut self, minutes: u64) -> Option<String> {
+        if self.criminal_minutes == 0 {
+            return None;
+        }
+        self.criminal_minutes = self.criminal_minutes.saturating_sub(minutes);
+        if self.criminal_minutes == 0 && self.notoriety == Notoriety::Criminal {
+            self.notoriety = Notoriety::Innocent;
+            return Some("You are no longer a criminal.".to_string());
+        }
+        None
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.health <= 0
+    }
+
+    /// Brings a dead player back at `spawn` with full health.
+    pub fn respawn(&mut self, spawn: Position) -> String {
+        self.health = FULL_HEALTH;
+        self.position = spawn;
+        self.status = PlayerStatus::Normal;
+        format!(
+            "You have died. You awaken at {}, your wounds healed.",
+            spawn
+        )
+    }
+
+    pub fn train_player(player: &mut Player) {
         player.strength += 1;
         player.agility += 1;
     }
@@ -116,46 +224,80 @@ impl Player {
         if self.inventory.is_empty() {
             "".to_string()
         } else {
-            self.inventory.iter()
+            self.inventory
+                .iter()
                 .enumerate()
                 .map(|(i, item)| format!("{}. {}", i + 1, item.name))
-                    .collect::<Vec<String>>()
-                    .join("\n")
+                .collect::<Vec<String>>()
+                .join("\n")
         }
     }
 
-    pub fn move_up(&mut self, map: &Maps) {
-            if self.position.1 > 0
-                && map.is_empty(self.position.0, self.position.1 - 1){
-                self.position.1 -= 1;
-            }
+    /// Whether the player has a torch to see by at night.
+    pub fn carries_light(&self) -> bool {
+        self.inventory.iter().any(|item| item.name == TORCH)
     }
 
-    pub fn move_down(&mut self, map: &Maps) {
-        if self.position.1 < map.height() - 1
-            && map.is_empty(self.position.0, self.position.1 + 1) {
-            self.position.1 += 1;
-        }
+    /// Remembers every tile currently in view on the given map.
+    pub fn explore(&mut self, map_name: &str, fov: &FieldOfView) {
+        let explored = self.explored.entry(map_name.to_string()).or_default();
+        explored.extend(fov.visible_positions());
     }
 
-    pub fn move_left(&mut self, map: &Maps) {
-        if self.position.0 > 0
-            && map.is_empty(self.position.0 - 1, self.position.1){
-            self.position.0 -= 1;
+    /// Steps one tile in `direction`. Terrain and NPCs block the way, except
+    /// followers, which trade places with the player when
+    /// `swap_with_followers` is on. Items are stepped over.
+    pub fn move_towards(
+        &mut self,
+        map: &Maps,
+        npcs: &mut EntityList<NPC>,
+        direction: Direction,
+    ) -> Step {
+        match map.try_step(self.position, direction, npcs) {
+            Ok(next) => {
+                self.position = next;
+                Step::Moved(next)
+            }
+            Err(Blocker::Npc(index)) if self.swap_with_followers && npcs[index].follower => {
+                let next = npcs[index].position;
+                if let Some(mut follower) = npcs.get_mut(index) {
+                    follower.position = self.position;
+                }
+                self.position = next;
+                Step::Swapped {
+                    npc: index,
+                    position: next,
+                }
+            }
+            Err(blocker) => Step::Blocked(blocker),
         }
     }
+}
 
-    pub fn move_right(&mut self, map: &Maps) {
-        if self.position.0 < map.width() - 1
-            && map.is_empty(self.position.0 + 1, self.position.1){
-            self.position.0 += 1;
-        }
+// Shorthands for `move_towards`; the key handlers pass the direction instead.
+#[allow(dead_code)]
+impl Player {
+    pub fn move_up(&mut self, map: &Maps, npcs: &mut EntityList<NPC>) -> Step {
+        self.move_towards(map, npcs, Direction::North)
     }
-}
 
+    pub fn move_down(&mut self, map: &Maps, npcs: &mut EntityList<NPC>) -> Step {
+        self.move_towards(map, npcs, Direction::South)
+    }
 
+    pub fn move_left(&mut self, map: &Maps, npcs: &mut EntityList<NPC>) -> Step {
+        self.move_towards(map, npcs, Direction::West)
+    }
+
+    pub fn move_right(&mut self, map: &Maps, npcs: &mut EntityList<NPC>) -> Step {
+        self.move_towards(map, npcs, Direction::East)
+    }
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
+    use crate::maps::terrain::{DoorState, Terrain};
 
     #[test]
     fn test_new_player() {
@@ -169,7 +311,7 @@ mod tests {
         assert_eq!(player.agility, 8);
         assert_eq!(player.charisma, 5);
         assert_eq!(player.inventory.len(), 0);
-        assert_eq!(player.position, (1, 1));
+        assert_eq!(player.position, Position::new(1, 1));
         //assert_eq!(player.map_limits, (100, 100));
     }
 
@@ -204,24 +346,30 @@ mod tests {
     fn test_check_status() {
         let mut player = Player::new("Test Player");
         player.set_status(PlayerStatus::Normal);
-        player.check_status();
+        assert_eq!(player.check_status(), "You are in good condition");
 
         player.set_status(PlayerStatus::InCombat);
-        player.check_status();
+        assert_eq!(player.check_status(), "You are in a fight!");
 
         player.set_status(PlayerStatus::Exhausted);
-        player.check_status();
+        assert_eq!(player.check_status(), "You are exhausted and need rest.");
 
         player.set_status(PlayerStatus::Injured);
-        player.check_status();
+        assert_eq!(player.check_status(), "You are hurt and you need to heal.");
     }
 
     #[test]
     fn test_engage_in_combat() {
         let mut player = Player::new("Test Player");
-        let mut npc = NPC::new("Test NPC", "You shall not pass!", (5, 5), 50, 10);
-
-        let combat_log = player.engage_in_combat(&mut npc);
+        let mut npc = NPC::new(
+            "Test NPC",
+            "You shall not pass!",
+            Position::new(5, 5),
+            50,
+            10,
+        );
+
+        let combat_log = player.engage_in_combat(&mut npc, None);
         assert!(combat_log.contains("You dealt"));
         assert!(combat_log.contains("The NPC dealt"));
 
@@ -229,6 +377,91 @@ mod tests {
         assert!(npc.health <= 50);
     }
 
+    #[test]
+    fn test_combat_rules_of_regions() {
+        use crate::maps::region::{Area, RegionFlags};
+        let area = Area::Rect {
+            top_left: Position::new(0, 0),
+            bottom_right: Position::new(9, 9),
+        };
+        let mut player = Player::new("Test Player");
+        let mut npc = NPC::new("Test NPC", "Hello", Position::new(5, 5), 50, 10);
+
+        let shrine = Region::new(
+            "the Shrine",
+            area.clone(),
+            RegionFlags {
+                no_combat: true,
+                ..RegionFlags::default()
+            },
+        );
+        assert_eq!(
+            player.engage_in_combat(&mut npc, Some(&shrine)),
+            "You cannot fight in the Shrine."
+        );
+        let britain = Region::new(
+            "Britain",
+            area,
+            RegionFlags {
+                guarded: true,
+                ..RegionFlags::default()
+            },
+        );
+        assert_eq!(
+            player.engage_in_combat(&mut npc, Some(&britain)),
+            "Guards! Fighting is forbidden in Britain."
+        );
+        assert_eq!((player.health, npc.health), (100, 50));
+        assert_eq!(player.notoriety, Notoriety::Criminal);
+
+        let mut orc = NPC::new("Orc", "Grrr!", Position::new(5, 5), 10, 1);
+        orc.notoriety = Notoriety::Enemy;
+        assert!(player
+            .engage_in_combat(&mut orc, Some(&britain))
+            .contains("You defeated the NPC!"));
+    }
+
+    #[test]
+    fn test_cost_of_attacking_innocents() {
+        let mut player = Player::new("Test Player");
+        let mut guard = NPC::new("Guard", "Halt!", Position::new(5, 5), 200, 40);
+        guard.notoriety = Notoriety::Invulnerable;
+        assert_eq!(
+            player.engage_in_combat(&mut guard, None),
+            "Guard cannot be harmed."
+        );
+        assert_eq!(player.notoriety, Notoriety::Innocent);
+
+        let mut orc = NPC::new("Orc", "Grrr!", Position::new(5, 5), 5, 1);
+        orc.notoriety = Notoriety::Enemy;
+        assert!(!player.engage_in_combat(&mut orc, None).contains("crime"));
+        assert_eq!(player.notoriety, Notoriety::Innocent);
+
+        let mut farmer = NPC::new("Farmer", "Hi", Position::new(5, 5), 5, 1);
+        assert!(player
+            .engage_in_combat(&mut farmer, None)
+            .starts_with("You have committed a crime!"));
+        assert_eq!((player.notoriety, player.murders), (Notoriety::Criminal, 1));
+        assert_eq!(player.pass_time(CRIMINAL_MINUTES - 1), None);
+        assert_eq!(
+            player.pass_time(1),
+            Some("You are no longer a criminal.".to_string())
+        );
+        assert_eq!(player.notoriety, Notoriety::Innocent);
+
+        for _ in 1..MURDERER_KILLS {
+            let mut farmer = NPC::new("Farmer", "Hi", Position::new(5, 5), 5, 1);
+            player.engage_in_combat(&mut farmer, None);
+        }
+        assert_eq!(player.notoriety, Notoriety::Murderer);
+        assert_eq!(
+            player.pass_time(CRIMINAL_MINUTES),
+            None,
+            "murderers stay murderers"
+        );
+        assert_eq!(player.notoriety, Notoriety::Murderer);
+    }
+
     #[test]
     fn test_train_player() {
         let mut player = Player::new("Test Player");
@@ -243,23 +476,169 @@ mod tests {
     #[test]
     fn test_move_up_down_left_right() {
         let mut player = Player::new("Test Player");
-        let mut maps = Maps::new(10, 10);
+        let mut npcs = EntityList::new();
+        let maps = Maps::new(10, 10);
 
         // Move up
-        player.position = (5, 5);
-        player.move_up(&maps);
-        assert_eq!(player.position, (5, 4));
+        player.position = Position::new(5, 5);
+        player.move_up(&maps, &mut npcs);
+        assert_eq!(player.position, Position::new(5, 4));
 
         // Move down
-        player.move_down(&maps);
-        assert_eq!(player.position, (5, 5));
+        player.move_down(&maps, &mut npcs);
+        assert_eq!(player.position, Position::new(5, 5));
 
         // Move left
-        player.move_left(&maps);
-        assert_eq!(player.position, (4, 5));
+        player.move_left(&maps, &mut npcs);
+        assert_eq!(player.position, Position::new(4, 5));
 
         // Move right
-        player.move_right(&maps);
-        assert_eq!(player.position, (5, 5));
+        player.move_right(&maps, &mut npcs);
+        assert_eq!(player.position, Position::new(5, 5));
+    }
+
+    #[test]
+    fn test_explore() {
+        let mut player = Player::new("Test Player");
+        let mut maps = Maps::new(20, 5);
+        maps.set_tile(Position::new(10, 2), Terrain::Wall);
+
+        player.explore("Britain", &maps.field_of_view(Position::new(5, 2), 3));
+        assert!(player.explored["Britain"].contains(&Position::new(5, 2)));
+        assert!(player.explored["Britain"].contains(&Position::new(8, 2)));
+        assert!(!player.explored["Britain"].contains(&Position::new(12, 2)));
+        assert!(!player.explored.contains_key("Despise"));
+
+        player.explore("Britain", &maps.field_of_view(Position::new(12, 2), 3));
+        assert!(player.explored["Britain"].contains(&Position::new(5, 2)));
+        assert!(player.explored["Britain"].contains(&Position::new(12, 2)));
+    }
+
+    #[test]
+    fn test_move_blocked_by_terrain() {
+        let mut player = Player::new("Test Player");
+        let mut npcs = EntityList::new();
+        let mut maps = Maps::new(10, 10);
+        maps.set_tile(Position::new(5, 4), Terrain::Wall);
+        maps.set_tile(Position::new(6, 5), Terrain::DeepWater);
+        maps.set_tile(Position::new(4, 5), Terrain::Door(DoorState::Closed));
+        maps.set_tile(Position::new(5, 6), Terrain::Water);
+
+        player.position = Position::new(5, 5);
+        player.move_up(&maps, &mut npcs);
+        player.move_right(&maps, &mut npcs);
+        player.move_left(&maps, &mut npcs);
+        assert_eq!(player.position, Position::new(5, 5));
+
+        player.move_down(&maps, &mut npcs);
+        assert_eq!(player.position, Position::new(5, 6));
+    }
+
+    #[test]
+    fn test_move_stops_at_map_edges() {
+        let mut player = Player::new("Test Player");
+        let mut npcs = EntityList::new();
+        let maps = Maps::new(3, 3);
+
+        player.position = Position::new(0, 0);
+        assert_eq!(
+            player.move_towards(&maps, &mut npcs, Direction::North),
+            Step::Blocked(Blocker::Edge)
+        );
+        assert_eq!(
+            player.move_towards(&maps, &mut npcs, Direction::West),
+            Step::Blocked(Blocker::Edge)
+        );
+        assert_eq!(player.position, Position::new(0, 0));
+
+        player.position = Position::new(2, 2);
+        assert_eq!(
+            player.move_towards(&maps, &mut npcs, Direction::South),
+            Step::Blocked(Blocker::Edge)
+        );
+        assert_eq!(
+            player.move_towards(&maps, &mut npcs, Direction::East),
+            Step::Blocked(Blocker::Edge)
+        );
+        assert_eq!(
+            player.move_towards(&maps, &mut npcs, Direction::NorthWest),
+            Step::Moved(Position::new(1, 1))
+        );
+    }
+
+    #[test]
+    fn test_npcs_block_and_followers_swap() {
+        let mut player = Player::new("Test Player");
+        let maps = Maps::new(5, 5);
+        let mut horse = NPC::new("Horse", "Neigh!", Position::new(2, 1), 30, 5);
+        horse.follower = true;
+        let mut npcs = EntityList::from(vec![
+            NPC::new("Guard", "Halt!", Position::new(1, 2), 50, 10),
+            horse,
+        ]);
+
+        assert_eq!(
+            player.move_down(&maps, &mut npcs),
+            Step::Blocked(Blocker::Npc(0))
+        );
+        assert_eq!(player.position, Position::new(1, 1));
+
+        assert_eq!(
+            player.move_right(&maps, &mut npcs),
+            Step::Swapped {
+                npc: 1,
+                position: Position::new(2, 1)
+            }
+        );
+        assert_eq!(player.position, Position::new(2, 1));
+        assert_eq!(npcs[1].position, Position::new(1, 1));
+
+        player.swap_with_followers = false;
+        assert_eq!(
+            player.move_left(&maps, &mut npcs),
+            Step::Blocked(Blocker::Npc(1))
+        );
+    }
+
+    #[test]
+    fn test_player_steps_onto_items() {
+        let mut player = Player::new("Test Player");
+        let maps = Maps::new(5, 5);
+        let mut npcs = EntityList::new();
+        let items = EntityList::from(vec![
+            Item::new("Gold", Position::new(2, 1)),
+            Item::new("Dagger", Position::new(2, 1)),
+        ]);
+
+        assert_eq!(
+            player.move_right(&maps, &mut npcs),
+            Step::Moved(Position::new(2, 1))
+        );
+        assert_eq!(items.at(player.position).count(), 2);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_move_on_empty_map() {
+        let mut player = Player::new("Test Player");
+        let mut npcs = EntityList::new();
+        let maps = Maps::new(0, 0);
+        player.move_down(&maps, &mut npcs);
+        player.move_right(&maps, &mut npcs);
+        assert_eq!(player.position, Position::new(1, 1));
+    }
+
+    #[test]
+    fn test_death_and_respawn() {
+        let mut player = Player::new("Test Player");
+        player.position = Position::new(5, 5);
+        let mut orc = NPC::new("Orc", "Grrr!", Position::new(5, 6), 500, 60);
+        orc.notoriety = Notoriety::Enemy;
+        player.engage_in_combat(&mut orc, None);
+        assert!(player.is_dead());
+
+        player.respawn(Position::new(2, 2));
+        assert!(!player.is_dead());
+        assert_eq!(player.health, FULL_HEALTH);
+        assert_eq!(player.position, Position::new(2, 2));
+    }
+}
@@ -0,0 +1,665 @@
+//! NPC and monster templates, loaded from every `.npc` file in
+//! `data/npcs`. Each template is a `[id]` section of `key = value` lines:
+//!
+//! ```text
+//! ; Monsters
+//! [orc]
+//! name = Orc
+//! glyph = o
+//! colour = green
+//! health = 40
+//! strength = 8
+//! behaviour = hostile 6 8 flee 10
+//! notoriety = enemy
+//! dialogue = Grrr!
+//! loot = Gold Coins | 60
+//! loot = Orcish Axe | 10
+//! weight = 10
+//! ```
+//!
+//! `name`, `health` and `strength` are required. The glyph defaults to `N`,
+//! the behaviour to `idle` and the notoriety to `innocent`. Without a
+//! `colour` the NPC is drawn in its notoriety colour. Each `loot` line
+//! is an item and its drop chance in percent. The weight decides how often
+//! the template is picked for random spawns; 0, the default, means never.
+//! `conversation` names a tree in `data/dialogue` that talking starts.
+//! NPCs are then spawned with `TemplateRegistry::spawn("orc", position)`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rand::Rng;
+
+use crate::entities::behaviour::Behaviour;
+use crate::entities::notoriety::Notoriety;
+use crate::entities::npc::NPC;
+use crate::inventory::item::Item;
+use crate::maps::position::Position;
+use crate::maps::tile::Colour;
+
+pub const TEMPLATES_DIR: &str = "data/npcs";
+
+/// An item an NPC may drop when it dies.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LootEntry {
+    pub item: String,
+    /// Drop chance in percent, 1 to 100.
+    pub chance: u32,
+}
+
+impl LootEntry {
+    pub fn new(item: &str, chance: u32) -> Self {
+        Self {
+            item: item.to_string(),
+            chance,
+        }
+    }
+}
+
+/// Rolls every entry of `loot` once; the items land at `position`.
+pub fn roll_loot<R: Rng>(loot: &[LootEntry], position: Position, rng: &mut R) -> Vec<Item> {
+    loot.iter()
+        .filter(|entry| rng.gen_range(0..100) < entry.chance)
+        .map(|entry| Item::new(&entry.item, position))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NpcTemplate {
+    pub id: String,
+    pub name: String,
+    pub glyph: char,
+    pub colour: Option<Colour>,
+    pub health: i32,
+    pub strength: i32,
+    /// Behaviour spec as written in the file; wander and hostile leashes are
+    /// measured from wherever the NPC is spawned.
+    pub behaviour: String,
+    pub notoriety: Notoriety,
+    pub dialogue: String,
+    /// Dialogue tree id; checked against the dialogue library at startup.
+    pub conversation: Option<String>,
+    pub loot: Vec<LootEntry>,
+    pub weight: u32,
+}
+
+impl NpcTemplate {
+    pub fn new(id: &str, name: &str, health: i32, strength: i32) -> Self {
+        Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            glyph: 'N',
+            colour: None,
+            health,
+            strength,
+            behaviour: "idle".to_string(),
+            notoriety: Notoriety::Innocent,
+            dialogue: String::new(),
+            conversation: None,
+            loot: Vec::new(),
+            weight: 0,
+        }
+    }
+
+    pub fn spawn(&self, position: Position) -> NPC {
+        let mut npc = NPC::new(
+            &self.name,
+            &self.dialogue,
+            position,
+            self.health,
+            self.strength,
+        );
+        // The spec was checked when the template was loaded.
+        let (behaviour, flee_below) =
+            Behaviour::parse(&self.behaviour, position).unwrap_or_default();
+        npc.behaviour = behaviour;
+        npc.flee_below = flee_below;
+        npc.notoriety = self.notoriety;
+        npc.glyph = self.glyph;
+        npc.colour = self.colour;
+        npc.loot = self.loot.clone();
+        npc.template = Some(self.id.clone());
+        npc.conversation = self.conversation.clone();
+        npc
+    }
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    Io {
+        file: PathBuf,
+        err: io::Error,
+    },
+    /// A line that is not `[id]`, `key = value` or a comment.
+    MalformedLine {
+        file: PathBuf,
+        line: usize,
+        reason: String,
+    },
+    UnknownField {
+        file: PathBuf,
+        template: String,
+        field: String,
+    },
+    InvalidField {
+        file: PathBuf,
+        template: String,
+        field: &'static str,
+        reason: String,
+    },
+    MissingField {
+        file: PathBuf,
+        template: String,
+        field: &'static str,
+    },
+    Duplicate {
+        file: PathBuf,
+        template: String,
+    },
+    UnknownTemplate(String),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Io { file, err } => {
+                write!(f, "{}: cannot read file: {}", file.display(), err)
+            }
+            TemplateError::MalformedLine { file, line, reason } => {
+                write!(f, "{}: line {}: {}", file.display(), line, reason)
+            }
+            TemplateError::UnknownField {
+                file,
+                template,
+                field,
+            } => {
+                write!(
+                    f,
+                    "{}: template '{}': unknown field '{}'",
+                    file.display(),
+                    template,
+                    field
+                )
+            }
+            TemplateError::InvalidField {
+                file,
+                template,
+                field,
+                reason,
+            } => {
+                write!(
+                    f,
+                    "{}: template '{}', field '{}': {}",
+                    file.display(),
+                    template,
+                    field,
+                    reason
+                )
+            }
+            TemplateError::MissingField {
+                file,
+                template,
+                field,
+            } => {
+                write!(
+                    f,
+                    "{}: template '{}' is missing '{}'",
+                    file.display(),
+                    template,
+                    field
+                )
+            }
+            TemplateError::Duplicate { file, template } => {
+                write!(
+                    f,
+                    "{}: template '{}' is defined twice",
+                    file.display(),
+                    template
+                )
+            }
+            TemplateError::UnknownTemplate(id) => write!(f, "unknown NPC template '{}'", id),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// Every known template, by id.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateRegistry {
+    templates: BTreeMap<String, NpcTemplate>,
+}
+
+impl TemplateRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&NpcTemplate> {
+        self.templates.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &NpcTemplate> {
+        self.templates.values()
+    }
+
+    pub fn insert(&mut self, template: NpcTemplate) {
+        self.templates.insert(template.id.clone(), template);
+    }
+
+    /// Spawns template `id` at `position`.
+    pub fn spawn(&self, id: &str, position: Position) -> Result<NPC, TemplateError> {
+        self.get(id)
+            .map(|template| template.spawn(position))
+            .ok_or_else(|| TemplateError::UnknownTemplate(id.to_string()))
+    }
+
+    /// Picks one of `ids` at random by spawn weight; every template weighs
+    /// at least 1 here so a list of unweighted ones still works.
+    pub fn choose_from<'a, R: Rng>(
+        &'a self,
+        ids: &[String],
+        rng: &mut R,
+    ) -> Option<&'a NpcTemplate> {
+        let candidates: Vec<&NpcTemplate> = ids.iter().filter_map(|id| self.get(id)).collect();
+        let total: u32 = candidates
+            .iter()
+            .map(|template| template.weight.max(1))
+            .sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = rng.gen_range(0..total);
+        for template in candidates {
+            let weight = template.weight.max(1);
+            if roll < weight {
+                return Some(template);
+            }
+            roll -= weight;
+        }
+        None
+    }
+
+    /// Picks any template with a spawn weight, at random by weight.
+    pub fn choose<R: Rng>(&self, rng: &mut R) -> Option<&NpcTemplate> {
+        let ids: Vec<String> = self
+            .iter()
+            .filter(|template| template.weight > 0)
+            .map(|template| template.id.clone())
+            .collect();
+        self.choose_from(&ids, rng)
+    }
+}
+
+/// Loads every `.npc` file in `dir`, in file name order.
+pub fn load_templates<P: AsRef<Path>>(dir: P) -> Result<TemplateRegistry, TemplateError> {
+    let dir = dir.as_ref();
+    let entries = fs::read_dir(dir).map_err(|err| TemplateError::Io {
+        file: dir.to_path_buf(),
+        err,
+    })?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "npc"))
+        .collect();
+    paths.sort();
+
+    let mut registry = TemplateRegistry::new();
+    for path in paths {
+        let contents = fs::read_to_string(&path).map_err(|err| TemplateError::Io {
+            file: path.clone(),
+            err,
+        })?;
+        parse_templates(&path, &contents, &mut registry)?;
+    }
+    Ok(registry)
+}
+
+/// Adds the templates of one file to `registry`. `file` is only used in
+/// error messages.
+pub fn parse_templates(
+    file: &Path,
+    contents: &str,
+    registry: &mut TemplateRegistry,
+) -> Result<(), TemplateError> {
+    let mut current: Option<(String, Vec<(String, String)>)> = None;
+    for (index, raw_line) in contents.lines().enumerate() {
+        let line = index + 1;
+        let trimmed = raw_line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(';') {
+            continue;
+        }
+        if let Some(id) = trimmed
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            let id = id.trim();
+            if id.is_empty()
+                || !id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                return Err(TemplateError::MalformedLine {
+                    file: file.to_path_buf(),
+                    line,
+                    reason: format!("bad template id '{}'", id),
+                });
+            }
+            if let Some((id, fields)) = current.take() {
+                add_template(file, &id, &fields, registry)?;
+            }
+            current = Some((id.to_string(), Vec::new()));
+            continue;
+        }
+        let Some((_, fields)) = current.as_mut() else {
+            return Err(TemplateError::MalformedLine {
+                file: file.to_path_buf(),
+                line,
+                reason: "expected a '[template]' header first".to_string(),
+            });
+        };
+        let (key, value) = trimmed
+            .split_once('=')
+            .ok_or_else(|| TemplateError::MalformedLine {
+                file: file.to_path_buf(),
+                line,
+                reason: "expected 'key = value'".to_string(),
+            })?;
+        fields.push((key.trim().to_string(), value.trim().to_string()));
+    }
+    if let Some((id, fields)) = current {
+        add_template(file, &id, &fields, registry)?;
+    }
+    Ok(())
+}
+
+fn add_template(
+    file: &Path,
+    id: &str,
+    fields: &[(String, String)],
+    registry: &mut TemplateRegistry,
+) -> Result<(), TemplateError> {
+    if registry.get(id).is_some() {
+        return Err(TemplateError::Duplicate {
+            file: file.to_path_buf(),
+            template: id.to_string(),
+        });
+    }
+    let invalid = |field: &'static str, reason: String| TemplateError::InvalidField {
+        file: file.to_path_buf(),
+        template: id.to_string(),
+        field,
+        reason,
+    };
+    let missing = |field: &'static str| TemplateError::MissingField {
+        file: file.to_path_buf(),
+        template: id.to_string(),
+        field,
+    };
+
+    let mut template = NpcTemplate::new(id, "", 0, 0);
+    let (mut name, mut health, mut strength) = (None, None, None);
+    for (key, value) in fields {
+        match key.as_str() {
+            "name" if value.is_empty() => {
+                return Err(invalid("name", "must not be empty".to_string()))
+            }
+            "name" => name = Some(value.clone()),
+            "glyph" => {
+                let mut chars = value.chars();
+                template.glyph = match (chars.next(), chars.next()) {
+                    (Some(glyph), None) => glyph,
+                    _ => {
+                        return Err(invalid(
+                            "glyph",
+                            format!("expected one character, found '{}'", value),
+                        ))
+                    }
+                };
+            }
+            "colour" => {
+                template.colour = Some(
+                    Colour::from_name(value)
+                        .ok_or_else(|| invalid("colour", format!("unknown colour '{}'", value)))?,
+                )
+            }
+            "health" => {
+                health = Some(parse_number(value).map_err(|reason| invalid("health", reason))?)
+            }
+            "strength" => {
+                strength = Some(parse_number(value).map_err(|reason| invalid("strength", reason))?)
+            }
+            "behaviour" => {
+                Behaviour::parse(value, Position::default())
+                    .map_err(|reason| invalid("behaviour", reason))?;
+                template.behaviour = value.clone();
+            }
+            "notoriety" => {
+                template.notoriety = Notoriety::from_name(value)
+                    .ok_or_else(|| invalid("notoriety", format!("unknown notoriety '{}'", value)))?
+            }
+            "dialogue" => template.dialogue = value.clone(),
+            "conversation" => template.conversation = Some(value.clone()),
+            "loot" => template
+                .loot
+                .push(parse_loot(value).map_err(|reason| invalid("loot", reason))?),
+            "weight" => {
+                template.weight = parse_number(value).map_err(|reason| invalid("weight", reason))?
+            }
+            _ => {
+                return Err(TemplateError::UnknownField {
+                    file: file.to_path_buf(),
+                    template: id.to_string(),
+                    field: key.clone(),
+                })
+            }
+        }
+    }
+    template.name = name.ok_or_else(|| missing("name"))?;
+    template.health = health.ok_or_else(|| missing("health"))?;
+    template.strength = strength.ok_or_else(|| missing("strength"))?;
+    if template.health <= 0 {
+        return Err(invalid("health", "must be above 0".to_string()));
+    }
+    registry.insert(template);
+    Ok(())
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("expected a number, found '{}'", value))
+}
+
+/// `Gold Coins | 60`
+fn parse_loot(value: &str) -> Result<LootEntry, String> {
+    let (item, chance) = value
+        .split_once('|')
+        .ok_or_else(|| "expected 'item | chance'".to_string())?;
+    let (item, chance) = (item.trim(), chance.trim().trim_end_matches('%'));
+    if item.is_empty() {
+        return Err("the item name is empty".to_string());
+    }
+    match chance.parse() {
+        Ok(chance @ 1..=100) => Ok(LootEntry::new(item, chance)),
+        _ => Err(format!(
+            "expected a chance from 1 to 100, found '{}'",
+            chance
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const MONSTERS: &str = "\
+; Monsters
+[orc]
+name = Orc
+glyph = o
+colour = green
+health = 40
+strength = 8
+behaviour = wander 3 flee 10
+notoriety = enemy
+dialogue = Grrr!
+loot = Gold Coins | 100
+loot = Orcish Axe | 10%
+weight = 10
+
+[banker]
+name = Banker
+health = 30
+strength = 2
+conversation = banker
+";
+
+    fn registry() -> TemplateRegistry {
+        let mut registry = TemplateRegistry::new();
+        parse_templates(Path::new("monsters.npc"), MONSTERS, &mut registry).unwrap();
+        registry
+    }
+
+    fn error(contents: &str) -> String {
+        parse_templates(
+            Path::new("monsters.npc"),
+            contents,
+            &mut TemplateRegistry::new(),
+        )
+        .unwrap_err()
+        .to_string()
+    }
+
+    #[test]
+    fn test_parse_and_spawn() {
+        let registry = registry();
+        assert_eq!(registry.iter().count(), 2);
+        let orc = registry.get("orc").unwrap();
+        assert_eq!(
+            (orc.glyph, orc.colour, orc.weight),
+            ('o', Some(Colour::Green), 10)
+        );
+        assert_eq!(
+            orc.loot,
+            vec![
+                LootEntry::new("Gold Coins", 100),
+                LootEntry::new("Orcish Axe", 10)
+            ]
+        );
+
+        let npc = registry.spawn("orc", Position::new(7, 3)).unwrap();
+        assert_eq!(
+            (npc.name.as_str(), npc.health, npc.strength),
+            ("Orc", 40, 8)
+        );
+        assert_eq!(
+            npc.behaviour,
+            Behaviour::Wander {
+                home: Position::new(7, 3),
+                leash: 3
+            }
+        );
+        assert_eq!(npc.flee_below, 10);
+        assert_eq!(
+            (npc.notoriety, npc.colour),
+            (Notoriety::Enemy, Some(Colour::Green))
+        );
+        assert_eq!(npc.template.as_deref(), Some("orc"));
+
+        let banker = registry.spawn("banker", Position::new(1, 1)).unwrap();
+        assert_eq!(
+            (banker.glyph, banker.behaviour, banker.notoriety),
+            ('N', Behaviour::Idle, Notoriety::Innocent)
+        );
+        assert_eq!(
+            (npc.conversation, banker.conversation.as_deref()),
+            (None, Some("banker"))
+        );
+        assert!(matches!(
+            registry.spawn("dragon", Position::new(1, 1)),
+            Err(TemplateError::UnknownTemplate(_))
+        ));
+    }
+
+    #[test]
+    fn test_validation_names_file_and_field() {
+        assert_eq!(
+            error("[orc]\nname = Orc\nhealth = lots\nstrength = 8\n"),
+            "monsters.npc: template 'orc', field 'health': expected a number, found 'lots'"
+        );
+        assert_eq!(
+            error("[orc]\nname = Orc\nhealth = 40\n"),
+            "monsters.npc: template 'orc' is missing 'strength'"
+        );
+        assert_eq!(
+            error("[orc]\nname = Orc\nhealth = 4\nstrength = 8\ncolour = mauve\n"),
+            "monsters.npc: template 'orc', field 'colour': unknown colour 'mauve'"
+        );
+        assert!(
+            error("[orc]\nname = Orc\nhealth = 4\nstrength = 8\nbehaviour = dance\n")
+                .contains("field 'behaviour'")
+        );
+        assert!(
+            error("[orc]\nname = Orc\nhealth = 4\nstrength = 8\nloot = Gold | 150\n")
+                .contains("field 'loot'")
+        );
+        assert!(
+            error("[orc]\nname = Orc\nhealth = 4\nstrength = 8\nglyph = oo\n")
+                .contains("field 'glyph'")
+        );
+        assert!(
+            error("[orc]\nname = Orc\nhealth = 4\nstrength = 8\nspeed = 3\n")
+                .contains("unknown field 'speed'")
+        );
+        assert_eq!(
+            error("name = Orc\n"),
+            "monsters.npc: line 1: expected a '[template]' header first"
+        );
+
+        let mut registry = registry();
+        let err = parse_templates(
+            Path::new("more.npc"),
+            "[orc]\nname = Orc\nhealth = 4\nstrength = 8\n",
+            &mut registry,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "more.npc: template 'orc' is defined twice");
+    }
+
+    #[test]
+    fn test_weights_and_loot() {
+        let registry = registry();
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..20 {
+            assert_eq!(
+                registry.choose(&mut rng).unwrap().id,
+                "orc",
+                "the banker has no spawn weight"
+            );
+        }
+        let both = ["orc".to_string(), "banker".to_string()];
+        let picks: Vec<&str> = (0..200)
+            .map(|_| registry.choose_from(&both, &mut rng).unwrap().id.as_str())
+            .collect();
+        assert!(picks.contains(&"banker") && picks.iter().filter(|&&id| id == "orc").count() > 150);
+
+        let loot = &registry.get("orc").unwrap().loot;
+        let drops = roll_loot(loot, Position::new(2, 2), &mut rng);
+        assert_eq!(drops[0].name, "Gold Coins");
+        assert_eq!(drops[0].position, Position::new(2, 2));
+    }
+
+    #[test]
+    fn test_load_bundled_templates() {
+        let registry = load_templates(TEMPLATES_DIR).unwrap();
+        for id in ["orc", "skeleton", "dragon", "banker", "blacksmith"] {
+            assert!(registry.get(id).is_some(), "missing template {}", id);
+        }
+    }
+}
@@ -1,35 +1,332 @@
-use rand::Rng;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::dialogue::{load_dialogues, DialogueLibrary, DIALOGUE_DIR};
 use crate::entities::player::Player;
-use crate::entities::npc::NPC;
+use crate::entities::template::{load_templates, TemplateRegistry, TEMPLATES_DIR};
+use crate::generator::dungeon::{generate_dungeon, DungeonConfig, DungeonStyle};
+use crate::generator::overworld::{generate_overworld, streamed_terrain, Biome, OverworldConfig};
 use crate::inventory::item::Item;
+use crate::maps::chunk::{DiskChunks, GeneratedChunks, STREAM_RADIUS};
+use crate::maps::map_file::{self, MapFile, Placement};
 use crate::maps::maps::Maps;
+use crate::maps::portal::Portal;
+use crate::maps::position::Position;
+use crate::maps::region::{Area, Region, RegionFlags};
+use crate::maps::spatial::EntityList;
+use crate::maps::terrain::Terrain;
+use crate::maps::tiled::{self, TiledMapping};
+use crate::save::{load_game, SAVE_FILE};
 use crate::ui;
+use crate::world::spawner::Spawner;
+use crate::world::weather::Climate;
+use crate::world::world::World;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+pub const MAPS_DIR: &str = "data/maps";
+const START_MAP: &str = "data/maps/start.map";
+/// Where the chunks of the wilderness the player changed are kept.
+const WILDERNESS_DIR: &str = "saves/chunks/sosaria";
+const WILDERNESS_SIZE: usize = 4096;
+const WILDERNESS_SEED: u64 = 1;
+/// Moongate linking the wilderness with Britain.
+const WILDERNESS_GATE: Position = Position { x: 2100, y: 2000 };
+const BRITAIN_GATE: Position = Position { x: 4, y: 36 };
+const ISLAND_SIZE: (usize, usize) = (160, 100);
+const ISLAND_SEED: u64 = 1;
+/// Where the moongate to Ilshenar leaves from and returns to in Britain.
+const BRITAIN_ISLAND_GATE: Position = Position { x: 4, y: 34 };
+/// Monsters roaming each biome of Ilshenar.
+const ISLAND_SPAWNERS: [(Biome, &str); 3] = [
+    (Biome::Plains, "rat"),
+    (Biome::Forest, "orc"),
+    (Biome::Beach, "skeleton"),
+];
+/// Translates tile ids and object types of Tiled exports in `MAPS_DIR`.
+const TILED_MAPPING: &str = "data/maps/tiled.mapping";
 
 pub fn start() {
-    // 10X10 test map
-    let mut map = Maps::new(1,1);
-    // Create and position player
-    let mut player = Player::new("Hero");
-
-    // Create and position NPCs
-    let npc1 = NPC::new("Guard", "You shall not pass!", (rand::thread_rng().gen_range(1..70), rand::thread_rng().gen_range(1..20)), 50, 10);
-    let npc2 = NPC::new("Guard", "You shall not pass!", (rand::thread_rng().gen_range(1..70), rand::thread_rng().gen_range(1..20)), 50, 10);
-    let npc3 = NPC::new("Guard", "You shall not pass!", (rand::thread_rng().gen_range(1..70), rand::thread_rng().gen_range(1..20)), 50, 10);
-
-    let mana = Item::new("Mana Potion", (14 ,7));
-    let life = Item::new("Health Potion", (10, 10));
-
-    let mut npcs:  Vec< crate::entities::npc::NPC >  = Vec::new();
-    npcs.push(npc1);
-    npcs.push(npc2);
-    npcs.push(npc3);
-    let mut items:  Vec< crate::inventory::item::Item >  = Vec::new();
-    items.push(mana);
-    items.push(life);
-
-    if let Err(err) = ui::run_ui(&mut player, &mut map, &mut npcs, &mut items) {
+    let templates = load_templates(TEMPLATES_DIR).unwrap_or_else(|err| {
+        eprintln!("Could not load NPC templates: {}", err);
+        TemplateRegistry::new()
+    });
+    let dialogues = load_dialogues(DIALOGUE_DIR).unwrap_or_else(|err| {
+        eprintln!("Could not load dialogues: {}", err);
+        DialogueLibrary::new()
+    });
+    for template in templates.iter() {
+        if let Some(tree) = template
+            .conversation
+            .as_ref()
+            .filter(|tree| dialogues.get(tree).is_none())
+        {
+            eprintln!(
+                "NPC template '{}' has unknown conversation '{}'",
+                template.id, tree
+            );
+        }
+    }
+    let mut start = match map_file::load_map(START_MAP) {
+        Ok(start) => start,
+        Err(err) => {
+            eprintln!("Could not load {}: {}", START_MAP, err);
+            default_world(&templates)
+        }
+    };
+    place_templates(&mut start, &templates);
+    let spawn = start.spawn;
+    let mut world = World::new(start);
+    load_other_maps(&mut world, &templates);
+    world.add_map(wilderness());
+    world.add_map(island());
+    world.templates = templates;
+    for portal in world.broken_portals() {
+        eprintln!("Broken portal: {}", portal);
+    }
+
+    let mut player = load_player(&mut world).unwrap_or_else(|| {
+        // Create and position player
+        let mut player = Player::new("Hero");
+        player.position = spawn;
+        player
+    });
+
+    if let Err(err) = ui::run_ui(&mut player, &mut world, &dialogues) {
         println!("Error: {:?}", err);
     }
+}
 
+/// Restores the saved player, if any, and moves the world to their map.
+fn load_player(world: &mut World) -> Option<Player> {
+    if !Path::new(SAVE_FILE).exists() {
+        return None;
+    }
+    match load_game(SAVE_FILE) {
+        Ok(mut saved) if world.set_current(&saved.map_name) => {
+            saved.forget_lost_tiles(world);
+            world.clock = saved.clock;
+            world.weather = saved.weather;
+            for state in &saved.maps {
+                if let Some((map_file, templates)) = world.get_mut_with_templates(&state.name) {
+                    state.restore(map_file, templates);
+                }
+            }
+            // The map may have changed since the game was saved.
+            let map_file = world.current_mut();
+            let position = saved.player.position;
+            let on_map = map_file.map.contains(position);
+            if on_map {
+                if let Err(err) = map_file.map.stream_around(position, STREAM_RADIUS) {
+                    eprintln!(
+                        "Could not load the world around the saved position: {}",
+                        err
+                    );
+                }
+            }
+            if !on_map || !map_file.map.is_walkable(position) {
+                eprintln!("Saved position {} on {} is blocked or off the map, starting at its spawn point", position, map_file.name);
+                saved.player.position = map_file.spawn;
+            }
+            Some(saved.player)
+        }
+        Ok(saved) => {
+            eprintln!(
+                "Saved map {} no longer exists, starting a new game",
+                saved.map_name
+            );
+            None
+        }
+        Err(err) => {
+            eprintln!("Could not load {}: {}", SAVE_FILE, err);
+            None
+        }
+    }
 }
 
+/// Adds every other map file found next to the start map, importing Tiled
+/// JSON exports (`.tmj` / `.json`) along the way.
+fn load_other_maps(world: &mut World, templates: &TemplateRegistry) {
+    let Ok(entries) = fs::read_dir(MAPS_DIR) else {
+        return;
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.as_path() != Path::new(START_MAP))
+        .collect();
+    paths.sort();
+
+    // Read on the first Tiled export; without it only those are skipped.
+    let mut mapping: Option<Result<TiledMapping, String>> = None;
+    for path in paths {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("");
+        let loaded = match extension {
+            "map" => map_file::load_map(&path).map_err(|err| err.to_string()),
+            "tmj" | "json" => {
+                let mapping = mapping.get_or_insert_with(|| {
+                    tiled::load_mapping(TILED_MAPPING).map_err(|err| err.to_string())
+                });
+                match mapping {
+                    Ok(mapping) => {
+                        tiled::import_tiled(&path, mapping).map_err(|err| err.to_string())
+                    }
+                    Err(err) => Err(format!("cannot read {}: {}", TILED_MAPPING, err)),
+                }
+            }
+            _ => continue,
+        };
+        match loaded {
+            Ok(mut map_file) => {
+                place_templates(&mut map_file, templates);
+                world.add_map(map_file)
+            }
+            Err(err) => eprintln!("Could not load {}: {}", path.display(), err),
+        }
+    }
+}
+
+/// Ilshenar, a generated island whose towns are linked by roads. The
+/// moongate from Britain arrives in the first town, and every biome in
+/// `ISLAND_SPAWNERS` gets a spawner for its monsters.
+pub fn island() -> MapFile {
+    let (width, height) = ISLAND_SIZE;
+    let overworld = generate_overworld(&OverworldConfig::new(width, height, ISLAND_SEED));
+    let gate = overworld.generated.player_spawn;
+
+    let mut rng = StdRng::seed_from_u64(ISLAND_SEED);
+    let mut spawners = Vec::new();
+    for (biome, template) in ISLAND_SPAWNERS {
+        match overworld.positions_in(biome).choose(&mut rng) {
+            Some(&center) => {
+                spawners.push(Spawner::new(vec![template.to_string()], center, 5, 3, 60))
+            }
+            None => eprintln!(
+                "Ilshenar has no {} for its {} spawner",
+                biome.name(),
+                template
+            ),
+        }
+    }
+
+    let mut map = overworld.generated.map;
+    let town_flags = RegionFlags {
+        guarded: true,
+        safe_logout: true,
+        ..RegionFlags::default()
+    };
+    for town in &overworld.towns {
+        let (x, y, radius) = (town.center.x, town.center.y, town.radius);
+        let area = Area::Rect {
+            top_left: Position::new(x - radius, y - radius),
+            bottom_right: Position::new(x + radius, y + radius),
+        };
+        map.add_region(Region::new(&town.name, area, town_flags.clone()));
+    }
+
+    MapFile {
+        name: "Ilshenar".to_string(),
+        spawn: gate,
+        climate: Climate::Temperate,
+        map,
+        npcs: EntityList::new(),
+        items: EntityList::new(),
+        portals: vec![Portal::new(gate, "Britain", BRITAIN_ISLAND_GATE)],
+        placements: Vec::new(),
+        spawners,
+        path: None,
+    }
+}
+
+/// Sosaria, an overworld too large to keep in memory: its chunks are
+/// generated around the player as they explore and stored on disk once
+/// they are left behind.
+pub fn wilderness() -> MapFile {
+    let mut config = OverworldConfig::new(WILDERNESS_SIZE, WILDERNESS_SIZE, WILDERNESS_SEED);
+    config.scale = 64.0;
+    let generate = move |x: usize, y: usize| {
+        // A clearing around the gate, so the player never arrives in a wall.
+        if x.abs_diff(WILDERNESS_GATE.x) <= 2 && y.abs_diff(WILDERNESS_GATE.y) <= 2 {
+            Terrain::Floor
+        } else {
+            streamed_terrain(&config, x, y)
+        }
+    };
+    let source = DiskChunks::new(WILDERNESS_DIR, GeneratedChunks::new(generate));
+    let mut map = Maps::streamed(WILDERNESS_SIZE, WILDERNESS_SIZE, Box::new(source));
+    if let Err(err) = map.stream_around(WILDERNESS_GATE, STREAM_RADIUS) {
+        eprintln!("Could not load the wilderness: {}", err);
+    }
+    MapFile {
+        name: "Sosaria".to_string(),
+        spawn: WILDERNESS_GATE,
+        climate: Climate::Temperate,
+        map,
+        npcs: EntityList::new(),
+        items: EntityList::new(),
+        portals: vec![Portal::new(WILDERNESS_GATE, "Britain", BRITAIN_GATE)],
+        placements: Vec::new(),
+        spawners: Vec::new(),
+        path: None,
+    }
+}
+
+fn place_templates(map_file: &mut MapFile, templates: &TemplateRegistry) {
+    for (position, err) in map_file.place_templates(templates) {
+        eprintln!(
+            "Could not place an NPC on {} at {}: {}",
+            map_file.name, position, err
+        );
+    }
+}
+
+/// World used when the start map cannot be loaded: a freshly generated
+/// dungeon, with templates picked by spawn weight placed on it.
+fn default_world(templates: &TemplateRegistry) -> MapFile {
+    let mut config = DungeonConfig::new(
+        200,
+        100,
+        rand::thread_rng().gen(),
+        DungeonStyle::RoomsAndCorridors,
+    );
+    config.npc_count = 3;
+    config.item_count = 2;
+    let generated = generate_dungeon(&config);
+
+    let mut rng = rand::thread_rng();
+    let placements: Vec<Placement> = generated
+        .npc_spawns
+        .iter()
+        .filter_map(|&position| {
+            let template = templates.choose(&mut rng)?;
+            Some(Placement {
+                template: template.id.clone(),
+                position,
+            })
+        })
+        .collect();
+
+    let potions = ["Mana Potion", "Health Potion"];
+    let items: Vec<Item> = generated
+        .item_spawns
+        .iter()
+        .zip(potions.iter().cycle())
+        .map(|(&position, name)| Item::new(name, position))
+        .collect();
+
+    MapFile {
+        name: "Dungeon".to_string(),
+        spawn: generated.player_spawn,
+        climate: Climate::None,
+        map: generated.map,
+        npcs: EntityList::new(),
+        items: EntityList::from(items),
+        portals: Vec::new(),
+        placements,
+        spawners: Vec::new(),
+        path: None,
+    }
+}
@@ -0,0 +1,346 @@
+//! Procedural dungeons built either from rooms joined by corridors or from
+//! cellular automata caves. The same seed always yields the same dungeon.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::generator::spawn::{pick_spawns, reachable_from, GeneratedMap};
+use crate::maps::maps::Maps;
+use crate::maps::position::Position;
+use crate::maps::terrain::Terrain;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DungeonStyle {
+    RoomsAndCorridors,
+    // No bundled dungeon is a cave yet.
+    #[allow(dead_code)]
+    Caves,
+}
+
+#[derive(Debug, Clone)]
+pub struct DungeonConfig {
+    pub width: usize,
+    pub height: usize,
+    pub seed: u64,
+    pub style: DungeonStyle,
+    pub npc_count: usize,
+    pub item_count: usize,
+    pub max_rooms: usize,
+    pub room_size: (usize, usize),
+    /// Chance for a cave cell to start as a wall, before smoothing.
+    pub cave_fill: f64,
+    pub cave_iterations: usize,
+}
+
+impl DungeonConfig {
+    pub fn new(width: usize, height: usize, seed: u64, style: DungeonStyle) -> Self {
+        Self {
+            width,
+            height,
+            seed,
+            style,
+            npc_count: 0,
+            item_count: 0,
+            max_rooms: 12,
+            room_size: (4, 10),
+            cave_fill: 0.45,
+            cave_iterations: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Room {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Room {
+    fn center(&self) -> (usize, usize) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    /// Overlap test that also keeps a one tile wall between rooms.
+    fn intersects(&self, other: &Room) -> bool {
+        self.x <= other.x + other.width
+            && other.x <= self.x + self.width
+            && self.y <= other.y + other.height
+            && other.y <= self.y + self.height
+    }
+}
+
+pub fn generate_dungeon(config: &DungeonConfig) -> GeneratedMap {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut map = filled(config.width, config.height);
+
+    let player_spawn = match config.style {
+        DungeonStyle::RoomsAndCorridors => carve_rooms(&mut map, config, &mut rng),
+        DungeonStyle::Caves => carve_caves(&mut map, config, &mut rng),
+    };
+
+    let reachable = reachable_from(&map, player_spawn);
+    let mut npc_spawns = pick_spawns(
+        &mut rng,
+        reachable,
+        player_spawn,
+        config.npc_count + config.item_count,
+    );
+    let item_spawns = npc_spawns.split_off(config.npc_count.min(npc_spawns.len()));
+
+    GeneratedMap {
+        map,
+        player_spawn,
+        npc_spawns,
+        item_spawns,
+    }
+}
+
+fn filled(width: usize, height: usize) -> Maps {
+    let mut map = Maps::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            map.set_tile(Position::new(x, y), Terrain::Wall);
+        }
+    }
+    map
+}
+
+/// Carves rooms and links each one to the previous, so all rooms are connected.
+/// Returns the center of the first room.
+fn carve_rooms(map: &mut Maps, config: &DungeonConfig, rng: &mut StdRng) -> Position {
+    let (min_size, max_size) = config.room_size;
+    let mut rooms: Vec<Room> = Vec::new();
+
+    // The outer ring always stays solid.
+    if config.width > min_size + 2 && config.height > min_size + 2 {
+        for _ in 0..config.max_rooms * 4 {
+            if rooms.len() >= config.max_rooms {
+                break;
+            }
+            let width = rng.gen_range(min_size..=max_size.min(config.width - 2));
+            let height = rng.gen_range(min_size..=max_size.min(config.height - 2));
+            let x = rng.gen_range(1..=config.width - width - 1);
+            let y = rng.gen_range(1..=config.height - height - 1);
+            let room = Room {
+                x,
+                y,
+                width,
+                height,
+            };
+            if rooms.iter().any(|other| room.intersects(other)) {
+                continue;
+            }
+
+            for ry in room.y..room.y + room.height {
+                for rx in room.x..room.x + room.width {
+                    map.set_tile(Position::new(rx, ry), Terrain::Floor);
+                }
+            }
+            if let Some(previous) = rooms.last() {
+                carve_corridor(map, previous.center(), room.center(), rng.gen_bool(0.5));
+            }
+            rooms.push(room);
+        }
+    }
+
+    match rooms.first() {
+        Some(room) => room.center().into(),
+        None => {
+            // Too small for any room: open the interior so the player can still spawn.
+            let center = Position::new(config.width / 2, config.height / 2);
+            map.set_tile(center, Terrain::Floor);
+            center
+        }
+    }
+}
+
+fn carve_corridor(
+    map: &mut Maps,
+    from: (usize, usize),
+    to: (usize, usize),
+    horizontal_first: bool,
+) {
+    let corner = if horizontal_first {
+        (to.0, from.1)
+    } else {
+        (from.0, to.1)
+    };
+    for (start, end) in [(from, corner), (corner, to)] {
+        for x in start.0.min(end.0)..=start.0.max(end.0) {
+            for y in start.1.min(end.1)..=start.1.max(end.1) {
+                map.set_tile(Position::new(x, y), Terrain::Floor);
+            }
+        }
+    }
+}
+
+/// Runs a cellular automaton and keeps only the largest open area, walling
+/// off every pocket that is not connected to it. Returns a cell of that area.
+fn carve_caves(map: &mut Maps, config: &DungeonConfig, rng: &mut StdRng) -> Position {
+    let (width, height) = (config.width, config.height);
+    let border = |x: usize, y: usize| x == 0 || y == 0 || x + 1 >= width || y + 1 >= height;
+
+    let mut walls: Vec<Vec<bool>> = (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| border(x, y) || rng.gen_bool(config.cave_fill))
+                .collect()
+        })
+        .collect();
+
+    for _ in 0..config.cave_iterations {
+        let mut next = walls.clone();
+        for y in 0..height {
+            for x in 0..width {
+                if border(x, y) {
+                    continue;
+                }
+                let neighbours = (y - 1..=y + 1)
+                    .flat_map(|ny| (x - 1..=x + 1).map(move |nx| (nx, ny)))
+                    .filter(|&(nx, ny)| (nx, ny) != (x, y) && walls[ny][nx])
+                    .count();
+                next[y][x] = neighbours >= 5 || (neighbours == 4 && walls[y][x]);
+            }
+        }
+        walls = next;
+    }
+
+    for (y, row) in walls.iter().enumerate() {
+        for (x, &wall) in row.iter().enumerate() {
+            if !wall {
+                map.set_tile(Position::new(x, y), Terrain::Floor);
+            }
+        }
+    }
+
+    let mut largest: Vec<Position> = Vec::new();
+    let mut seen = vec![vec![false; width]; height];
+    for y in 0..height {
+        for x in 0..width {
+            if seen[y][x] || !map.is_walkable(Position::new(x, y)) {
+                continue;
+            }
+            let region = reachable_from(map, Position::new(x, y));
+            for position in &region {
+                seen[position.y][position.x] = true;
+            }
+            if region.len() > largest.len() {
+                largest = region;
+            }
+        }
+    }
+
+    if largest.is_empty() {
+        let center = Position::new(width / 2, height / 2);
+        map.set_tile(center, Terrain::Floor);
+        return center;
+    }
+
+    let mut keep = vec![vec![false; width]; height];
+    for position in &largest {
+        keep[position.y][position.x] = true;
+    }
+    for (y, row) in keep.iter().enumerate() {
+        for (x, &kept) in row.iter().enumerate() {
+            if !kept {
+                map.set_tile(Position::new(x, y), Terrain::Wall);
+            }
+        }
+    }
+    largest[rng.gen_range(0..largest.len())]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(style: DungeonStyle, seed: u64) -> DungeonConfig {
+        let mut config = DungeonConfig::new(60, 30, seed, style);
+        config.npc_count = 8;
+        config.item_count = 5;
+        config
+    }
+
+    fn floor_count(map: &Maps) -> usize {
+        map.tiles()
+            .filter(|tile| tile.terrain.is_walkable())
+            .count()
+    }
+
+    fn assert_valid(generated: &GeneratedMap) {
+        let reachable = reachable_from(&generated.map, generated.player_spawn);
+        assert_eq!(
+            reachable.len(),
+            floor_count(&generated.map),
+            "every floor tile must be connected"
+        );
+
+        let mut all = generated.npc_spawns.clone();
+        all.extend(&generated.item_spawns);
+        all.push(generated.player_spawn);
+        for position in &all {
+            assert!(reachable.contains(position));
+        }
+        let mut unique = all.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), all.len(), "spawn points must not overlap");
+    }
+
+    #[test]
+    fn test_rooms_and_corridors() {
+        for seed in 0..10 {
+            let generated = generate_dungeon(&config(DungeonStyle::RoomsAndCorridors, seed));
+            assert_eq!(generated.npc_spawns.len(), 8);
+            assert_eq!(generated.item_spawns.len(), 5);
+            assert_valid(&generated);
+        }
+    }
+
+    #[test]
+    fn test_caves() {
+        for seed in 0..10 {
+            let generated = generate_dungeon(&config(DungeonStyle::Caves, seed));
+            assert_valid(&generated);
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_dungeon() {
+        for style in [DungeonStyle::RoomsAndCorridors, DungeonStyle::Caves] {
+            let first = generate_dungeon(&config(style, 42));
+            let second = generate_dungeon(&config(style, 42));
+            assert!(first.map.tiles().eq(second.map.tiles()));
+            assert_eq!(first.player_spawn, second.player_spawn);
+            assert_eq!(first.npc_spawns, second.npc_spawns);
+            assert_eq!(first.item_spawns, second.item_spawns);
+
+            let other = generate_dungeon(&config(style, 43));
+            assert!(!first.map.tiles().eq(other.map.tiles()));
+        }
+    }
+
+    #[test]
+    fn test_border_stays_solid() {
+        let generated = generate_dungeon(&config(DungeonStyle::Caves, 7));
+        let map = &generated.map;
+        for x in 0..map.width {
+            assert_eq!(map.terrain(Position::new(x, 0)), Some(Terrain::Wall));
+            assert_eq!(
+                map.terrain(Position::new(x, map.height - 1)),
+                Some(Terrain::Wall)
+            );
+        }
+    }
+
+    #[test]
+    fn test_tiny_map_still_has_a_spawn() {
+        let mut config = DungeonConfig::new(4, 4, 1, DungeonStyle::RoomsAndCorridors);
+        config.npc_count = 3;
+        let generated = generate_dungeon(&config);
+        assert!(generated.map.is_walkable(generated.player_spawn));
+        assert!(generated.npc_spawns.is_empty());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod dungeon;
+pub mod noise;
+pub mod overworld;
+pub mod spawn;
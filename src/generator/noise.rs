@@ -0,0 +1,74 @@
+//! Seeded value noise, used to build height and moisture fields.
+
+/// Deterministic pseudo random value in `[0, 1)` for a lattice point.
+fn lattice(seed: u64, x: i64, y: i64) -> f64 {
+    let mut h = seed
+        ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^= h >> 31;
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn smoothstep(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+/// Smoothly interpolated noise in `[0, 1)`.
+pub fn value_noise(seed: u64, x: f64, y: f64) -> f64 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (smoothstep(x - x0), smoothstep(y - y0));
+    let (ix, iy) = (x0 as i64, y0 as i64);
+
+    let top = lerp(lattice(seed, ix, iy), lattice(seed, ix + 1, iy), tx);
+    let bottom = lerp(lattice(seed, ix, iy + 1), lattice(seed, ix + 1, iy + 1), tx);
+    lerp(top, bottom, ty)
+}
+
+/// Sum of `octaves` layers of noise, each twice as detailed and half as
+/// strong as the previous one, normalised back to `[0, 1)`.
+pub fn fractal_noise(seed: u64, x: f64, y: f64, octaves: u32) -> f64 {
+    let mut total = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    let mut max = 0.0;
+    for octave in 0..octaves {
+        total += value_noise(
+            seed.wrapping_add(octave as u64),
+            x * frequency,
+            y * frequency,
+        ) * amplitude;
+        max += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    total / max
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_is_deterministic_and_in_range() {
+        for i in 0..200 {
+            let (x, y) = (i as f64 * 0.37, i as f64 * 0.11);
+            let value = fractal_noise(9, x, y, 4);
+            assert!((0.0..1.0).contains(&value));
+            assert_eq!(value, fractal_noise(9, x, y, 4));
+        }
+        assert_ne!(value_noise(1, 0.5, 0.5), value_noise(2, 0.5, 0.5));
+    }
+
+    #[test]
+    fn test_noise_is_continuous() {
+        let a = value_noise(3, 10.0, 10.0);
+        let b = value_noise(3, 10.001, 10.0);
+        assert!((a - b).abs() < 0.01);
+    }
+}
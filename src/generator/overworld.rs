@@ -0,0 +1,516 @@
+//! Noise based overworld: an island with coastlines, plains, forests,
+//! mountains and rivers, plus towns joined by roads. The same seed always
+//! yields the same world.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::generator::noise::{fractal_noise, value_noise};
+use crate::generator::spawn::{pick_spawns, reachable_from, GeneratedMap};
+use crate::maps::maps::Maps;
+use crate::maps::pathfinding::Movement;
+use crate::maps::position::Position;
+use crate::maps::terrain::Terrain;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Ocean,
+    Coast,
+    Beach,
+    Plains,
+    Forest,
+    Mountains,
+    River,
+    Town,
+}
+
+impl Biome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Biome::Ocean => "ocean",
+            Biome::Coast => "coast",
+            Biome::Beach => "beach",
+            Biome::Plains => "plains",
+            Biome::Forest => "forest",
+            Biome::Mountains => "mountains",
+            Biome::River => "river",
+            Biome::Town => "town",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OverworldConfig {
+    pub width: usize,
+    pub height: usize,
+    pub seed: u64,
+    pub town_count: usize,
+    pub river_count: usize,
+    pub npc_count: usize,
+    pub item_count: usize,
+    /// Noise zoom: larger values give bigger continents and forests.
+    pub scale: f64,
+    pub sea_level: f64,
+    pub mountain_level: f64,
+}
+
+impl OverworldConfig {
+    pub fn new(width: usize, height: usize, seed: u64) -> Self {
+        Self {
+            width,
+            height,
+            seed,
+            town_count: 4,
+            river_count: 3,
+            npc_count: 0,
+            item_count: 0,
+            scale: 24.0,
+            sea_level: 0.38,
+            mountain_level: 0.72,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Town {
+    pub name: String,
+    pub center: Position,
+    pub radius: usize,
+}
+
+pub struct Overworld {
+    pub generated: GeneratedMap,
+    pub biomes: Vec<Vec<Biome>>,
+    pub towns: Vec<Town>,
+}
+
+impl Overworld {
+    pub fn biome_at(&self, x: usize, y: usize) -> Option<Biome> {
+        self.biomes.get(y).and_then(|row| row.get(x)).copied()
+    }
+
+    /// Walkable positions of a biome, e.g. to spawn wolves only in forests.
+    pub fn positions_in(&self, biome: Biome) -> Vec<Position> {
+        let map = &self.generated.map;
+        (0..map.height)
+            .flat_map(|y| (0..map.width).map(move |x| Position::new(x, y)))
+            .filter(|&position| {
+                self.biome_at(position.x, position.y) == Some(biome) && map.is_walkable(position)
+            })
+            .collect()
+    }
+}
+
+const TREE_CHANCE: f64 = 0.6;
+/// Seed offset for the trees of streamed forests.
+const TREE_SEED: u64 = 0x7EE5;
+
+const TOWN_NAMES: [&str; 8] = [
+    "Britain",
+    "Trinsic",
+    "Minoc",
+    "Yew",
+    "Vesper",
+    "Moonglow",
+    "Jhelom",
+    "Skara Brae",
+];
+
+pub fn generate_overworld(config: &OverworldConfig) -> Overworld {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let (width, height) = (config.width, config.height);
+
+    let elevation = elevation_field(config);
+    let moisture: Vec<Vec<f64>> = (0..height)
+        .map(|y| (0..width).map(|x| moisture_at(config, x, y)).collect())
+        .collect();
+
+    let mut biomes: Vec<Vec<Biome>> = (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| classify(config, elevation[y][x], moisture[y][x]))
+                .collect()
+        })
+        .collect();
+
+    carve_rivers(config, &elevation, &mut biomes, &mut rng);
+
+    let mut map = Maps::new(width, height);
+    for (y, row) in biomes.iter().enumerate() {
+        for (x, biome) in row.iter().enumerate() {
+            map.set_tile(
+                Position::new(x, y),
+                biome_terrain(*biome, || rng.gen_bool(TREE_CHANCE)),
+            );
+        }
+    }
+
+    let towns = place_towns(config, &mut map, &mut biomes, &mut rng);
+    for pair in towns.windows(2) {
+        lay_road(&mut map, &mut biomes, pair[0].center, pair[1].center);
+    }
+
+    let player_spawn = match towns.first() {
+        Some(town) => town.center,
+        None => first_walkable(&map).unwrap_or(Position::new(width / 2, height / 2)),
+    };
+    let reachable = reachable_from(&map, player_spawn);
+    let mut npc_spawns = pick_spawns(
+        &mut rng,
+        reachable,
+        player_spawn,
+        config.npc_count + config.item_count,
+    );
+    let item_spawns = npc_spawns.split_off(config.npc_count.min(npc_spawns.len()));
+
+    Overworld {
+        generated: GeneratedMap {
+            map,
+            player_spawn,
+            npc_spawns,
+            item_spawns,
+        },
+        biomes,
+        towns,
+    }
+}
+
+/// Terrain of a single tile, without rivers, towns or roads, so that a
+/// streamed map can generate any chunk on its own.
+pub fn streamed_terrain(config: &OverworldConfig, x: usize, y: usize) -> Terrain {
+    let biome = classify(
+        config,
+        elevation_at(config, x, y),
+        moisture_at(config, x, y),
+    );
+    biome_terrain(biome, || {
+        value_noise(config.seed ^ TREE_SEED, x as f64, y as f64) < TREE_CHANCE
+    })
+}
+
+fn elevation_field(config: &OverworldConfig) -> Vec<Vec<f64>> {
+    (0..config.height)
+        .map(|y| {
+            (0..config.width)
+                .map(|x| elevation_at(config, x, y))
+                .collect()
+        })
+        .collect()
+}
+
+/// Fractal noise pulled down towards the edges so the land forms an island.
+fn elevation_at(config: &OverworldConfig, x: usize, y: usize) -> f64 {
+    let (width, height) = (config.width as f64, config.height as f64);
+    let noise = fractal_noise(
+        config.seed,
+        x as f64 / config.scale,
+        y as f64 / config.scale,
+        5,
+    );
+    let dx = (x as f64 + 0.5) / width * 2.0 - 1.0;
+    let dy = (y as f64 + 0.5) / height * 2.0 - 1.0;
+    let edge = dx.abs().max(dy.abs());
+    noise * (1.0 - edge.powi(3)) + 0.15
+}
+
+fn moisture_at(config: &OverworldConfig, x: usize, y: usize) -> f64 {
+    fractal_noise(
+        config.seed ^ 0xA5A5,
+        x as f64 / config.scale,
+        y as f64 / config.scale,
+        4,
+    )
+}
+
+fn classify(config: &OverworldConfig, elevation: f64, moisture: f64) -> Biome {
+    if elevation < config.sea_level - 0.05 {
+        Biome::Ocean
+    } else if elevation < config.sea_level {
+        Biome::Coast
+    } else if elevation < config.sea_level + 0.02 {
+        Biome::Beach
+    } else if elevation >= config.mountain_level {
+        Biome::Mountains
+    } else if moisture > 0.55 {
+        Biome::Forest
+    } else {
+        Biome::Plains
+    }
+}
+
+/// `tree` decides whether a forest tile holds a tree.
+fn biome_terrain(biome: Biome, tree: impl FnOnce() -> bool) -> Terrain {
+    match biome {
+        Biome::Ocean => Terrain::DeepWater,
+        Biome::Coast | Biome::River => Terrain::Water,
+        Biome::Beach | Biome::Town => Terrain::Floor,
+        Biome::Plains => Terrain::Grass,
+        Biome::Forest => {
+            if tree() {
+                Terrain::Tree
+            } else {
+                Terrain::Grass
+            }
+        }
+        Biome::Mountains => Terrain::Wall,
+    }
+}
+
+/// Rivers start on the mountain slopes and flow downhill until they reach the sea.
+fn carve_rivers(
+    config: &OverworldConfig,
+    elevation: &[Vec<f64>],
+    biomes: &mut [Vec<Biome>],
+    rng: &mut StdRng,
+) {
+    let mut sources: Vec<(usize, usize)> = Vec::new();
+    for (y, row) in biomes.iter().enumerate() {
+        for (x, biome) in row.iter().enumerate() {
+            let high = elevation[y][x] > config.mountain_level - 0.08;
+            if *biome != Biome::Mountains && *biome != Biome::Ocean && high {
+                sources.push((x, y));
+            }
+        }
+    }
+    sources.shuffle(rng);
+
+    for &source in sources.iter().take(config.river_count) {
+        let mut current = source;
+        let mut visited = vec![current];
+        loop {
+            let (x, y) = current;
+            if matches!(biomes[y][x], Biome::Ocean | Biome::Coast) {
+                break;
+            }
+            biomes[y][x] = Biome::River;
+            let next = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ]
+            .into_iter()
+            .filter(|&(nx, ny)| nx < config.width && ny < config.height)
+            .filter(|position| !visited.contains(position))
+            .filter(|&(nx, ny)| biomes[ny][nx] != Biome::Mountains)
+            .min_by(|a, b| elevation[a.1][a.0].total_cmp(&elevation[b.1][b.0]));
+            match next {
+                Some(position) => {
+                    visited.push(position);
+                    current = position;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// Towns go on plains of the largest landmass, far enough from each other.
+fn place_towns(
+    config: &OverworldConfig,
+    map: &mut Maps,
+    biomes: &mut [Vec<Biome>],
+    rng: &mut StdRng,
+) -> Vec<Town> {
+    let radius = 3;
+    let min_distance = (config.width.min(config.height) / 4).max(radius * 3);
+
+    let mut mainland: Vec<Position> = Vec::new();
+    let mut seen = vec![vec![false; config.width]; config.height];
+    for y in 0..config.height {
+        for x in 0..config.width {
+            if seen[y][x] || !map.is_walkable(Position::new(x, y)) {
+                continue;
+            }
+            let region = reachable_from(map, Position::new(x, y));
+            for position in &region {
+                seen[position.y][position.x] = true;
+            }
+            if region.len() > mainland.len() {
+                mainland = region;
+            }
+        }
+    }
+
+    let fits = |&Position { x, y }: &Position| {
+        x > radius
+            && y > radius
+            && x + radius + 1 < config.width
+            && y + radius + 1 < config.height
+            && biomes[y][x] == Biome::Plains
+    };
+    let mut candidates: Vec<Position> = mainland.into_iter().filter(fits).collect();
+    candidates.shuffle(rng);
+
+    let mut towns: Vec<Town> = Vec::new();
+    for center in candidates {
+        if towns.len() >= config.town_count {
+            break;
+        }
+        let far_enough = towns
+            .iter()
+            .all(|town| town.center.distance(center) >= min_distance);
+        if !far_enough {
+            continue;
+        }
+        for (y, row) in biomes
+            .iter_mut()
+            .enumerate()
+            .skip(center.y - radius)
+            .take(radius * 2 + 1)
+        {
+            for (x, biome) in row
+                .iter_mut()
+                .enumerate()
+                .skip(center.x - radius)
+                .take(radius * 2 + 1)
+            {
+                *biome = Biome::Town;
+                map.set_tile(Position::new(x, y), Terrain::Floor);
+            }
+        }
+        towns.push(Town {
+            name: TOWN_NAMES[towns.len() % TOWN_NAMES.len()].to_string(),
+            center,
+            radius,
+        });
+    }
+    towns
+}
+
+/// Cheapest walkable route between two towns, paved with road tiles.
+fn lay_road(map: &mut Maps, biomes: &mut [Vec<Biome>], from: Position, to: Position) {
+    let Some(path) = map.find_path(from, to, Movement::FourWay) else {
+        return;
+    };
+    for step in path {
+        if biomes[step.y][step.x] != Biome::Town {
+            map.set_tile(step, Terrain::Road);
+        }
+    }
+}
+
+fn first_walkable(map: &Maps) -> Option<Position> {
+    (0..map.height)
+        .flat_map(|y| (0..map.width).map(move |x| Position::new(x, y)))
+        .find(|&position| map.is_walkable(position))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world(seed: u64) -> Overworld {
+        let mut config = OverworldConfig::new(120, 80, seed);
+        config.npc_count = 10;
+        config.item_count = 10;
+        generate_overworld(&config)
+    }
+
+    #[test]
+    fn test_same_seed_same_world() {
+        let first = world(11);
+        let second = world(11);
+        assert!(first.generated.map.tiles().eq(second.generated.map.tiles()));
+        assert_eq!(first.biomes, second.biomes);
+        assert_eq!(first.towns, second.towns);
+        assert_eq!(first.generated.npc_spawns, second.generated.npc_spawns);
+        assert!(!first
+            .generated
+            .map
+            .tiles()
+            .eq(world(12).generated.map.tiles()));
+    }
+
+    #[test]
+    fn test_world_is_an_island() {
+        let overworld = world(3);
+        let map = &overworld.generated.map;
+        for x in 0..map.width {
+            assert_eq!(overworld.biome_at(x, 0), Some(Biome::Ocean));
+            assert_eq!(overworld.biome_at(x, map.height - 1), Some(Biome::Ocean));
+        }
+        assert!(!overworld.positions_in(Biome::Plains).is_empty());
+        assert!(!overworld.positions_in(Biome::Beach).is_empty());
+    }
+
+    #[test]
+    fn test_towns_are_connected_by_roads() {
+        for seed in 0..5 {
+            let overworld = world(seed);
+            let map = &overworld.generated.map;
+            assert!(
+                overworld.towns.len() >= 2,
+                "seed {} placed too few towns",
+                seed
+            );
+
+            let reachable = reachable_from(map, overworld.towns[0].center);
+            for town in &overworld.towns {
+                assert!(reachable.contains(&town.center));
+                assert_eq!(
+                    overworld.biome_at(town.center.x, town.center.y),
+                    Some(Biome::Town)
+                );
+            }
+            let roads = map
+                .tiles()
+                .filter(|tile| tile.terrain == Terrain::Road)
+                .count();
+            assert!(roads > 0);
+        }
+    }
+
+    #[test]
+    fn test_biome_terrain_matches() {
+        let overworld = world(5);
+        let map = &overworld.generated.map;
+        for y in 0..map.height {
+            for x in 0..map.width {
+                let terrain = map.terrain(Position::new(x, y)).unwrap();
+                match overworld.biome_at(x, y).unwrap() {
+                    Biome::Ocean => assert_eq!(terrain, Terrain::DeepWater),
+                    Biome::Mountains => assert_eq!(terrain, Terrain::Wall),
+                    Biome::Town => assert_eq!(terrain, Terrain::Floor),
+                    _ => assert!(terrain.is_walkable()),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_spawns_are_reachable_and_distinct() {
+        let overworld = world(8);
+        let generated = &overworld.generated;
+        let reachable = reachable_from(&generated.map, generated.player_spawn);
+        let mut all = generated.npc_spawns.clone();
+        all.extend(&generated.item_spawns);
+        assert_eq!(all.len(), 20);
+        assert!(all.iter().all(|position| reachable.contains(position)));
+        all.push(generated.player_spawn);
+        all.sort();
+        all.dedup();
+        assert_eq!(all.len(), 21);
+    }
+
+    #[test]
+    fn test_streamed_terrain_matches_the_biomes() {
+        let config = OverworldConfig::new(120, 80, 5);
+        let overworld = generate_overworld(&config);
+        let mut trees = 0;
+        for y in 0..config.height {
+            for x in 0..config.width {
+                let terrain = streamed_terrain(&config, x, y);
+                assert_eq!(terrain, streamed_terrain(&config, x, y));
+                match overworld.biome_at(x, y).unwrap() {
+                    Biome::Ocean => assert_eq!(terrain, Terrain::DeepWater),
+                    Biome::Mountains => assert_eq!(terrain, Terrain::Wall),
+                    Biome::Forest => trees += (terrain == Terrain::Tree) as usize,
+                    _ => {}
+                }
+            }
+        }
+        assert!(trees > 0);
+    }
+}
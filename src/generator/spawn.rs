@@ -0,0 +1,58 @@
+use std::collections::VecDeque;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::maps::maps::Maps;
+use crate::maps::position::{Direction, Position};
+
+/// A generated map together with free positions to place entities on.
+/// Every spawn point is walkable, reachable from the player spawn and used
+/// only once.
+pub struct GeneratedMap {
+    pub map: Maps,
+    pub player_spawn: Position,
+    pub npc_spawns: Vec<Position>,
+    pub item_spawns: Vec<Position>,
+}
+
+/// Walkable positions reachable from `start`, moving in the four directions.
+pub fn reachable_from(map: &Maps, start: Position) -> Vec<Position> {
+    let mut visited = vec![vec![false; map.width]; map.height];
+    let mut reachable = Vec::new();
+    let mut queue = VecDeque::new();
+
+    if !map.is_walkable(start) {
+        return reachable;
+    }
+    visited[start.y][start.x] = true;
+    queue.push_back(start);
+
+    while let Some(position) = queue.pop_front() {
+        reachable.push(position);
+        for direction in Direction::CARDINAL {
+            let Some(next) = map.neighbour(position, direction) else {
+                continue;
+            };
+            if map.is_walkable(next) && !visited[next.y][next.x] {
+                visited[next.y][next.x] = true;
+                queue.push_back(next);
+            }
+        }
+    }
+    reachable
+}
+
+/// Picks up to `count` distinct spawn points out of `candidates`, never
+/// reusing `player_spawn`. Returns fewer points when there is not enough room.
+pub fn pick_spawns<R: Rng>(
+    rng: &mut R,
+    mut candidates: Vec<Position>,
+    player_spawn: Position,
+    count: usize,
+) -> Vec<Position> {
+    candidates.retain(|&position| position != player_spawn);
+    candidates.shuffle(rng);
+    candidates.truncate(count);
+    candidates
+}
@@ -1,66 +1,83 @@
-use ratatui::layout::Positions;
 use crate::entities::player::Player;
+use crate::maps::position::Position;
+use crate::maps::spatial::Positioned;
+
+pub const HEALTH_POTION: &str = "Health Potion";
+pub const MANA_POTION: &str = "Mana Potion";
 
 #[derive(Debug, Clone)]
 pub struct Item {
     pub name: String,
-    pub position: (usize, usize)
+    pub position: Position,
 }
 
 impl Item {
-    pub fn new(name: &str, position: (usize, usize)) -> Self {
+    pub fn new(name: &str, position: Position) -> Self {
         Self {
             name: name.to_string(),
-            position
+            position,
         }
     }
 
-    pub fn add_item(player: &mut Player, item: Item) -> String{
+    pub fn add_item(player: &mut Player, item: Item) -> String {
         player.inventory.push(item);
         "You found a new item!".to_string()
     }
 
     pub fn use_item(player: &mut Player, item_name: &str) -> String {
-        if let Some(index) = player.inventory.iter().position(|item| item.name == item_name) {
+        if let Some(index) = player
+            .inventory
+            .iter()
+            .position(|item| item.name == item_name)
+        {
             let item = player.inventory.remove(index);
-            apply_item_effect(player, &item);
-            "You used item".to_string()
+            apply_item_effect(player, &item)
         } else {
             "Object not found on inventary.".to_string()
         }
     }
+}
 
+impl Positioned for Item {
+    fn position(&self) -> Position {
+        self.position
+    }
 }
 
-fn apply_item_effect(player: &mut Player, item: &Item) {
+fn apply_item_effect(player: &mut Player, item: &Item) -> String {
     match item.name.as_str() {
-        "Health Potion" => {
+        HEALTH_POTION => {
             player.health += 20;
-            println!("Your health is increased by 20 points. Current health: {}.", player.health);
-        },
-        "Mana Potion" => {
+            format!(
+                "Your health is increased by 20 points. Current health: {}.",
+                player.health
+            )
+        }
+        MANA_POTION => {
             player.mana += 15;
-            println!("Your mana increased by 15 points. Current Mana: {}.", player.mana);
-        },
-        _ => println!("Object not useful")
+            format!(
+                "Your mana increased by 15 points. Current Mana: {}.",
+                player.mana
+            )
+        }
+        _ => "Object not useful".to_string(),
     }
 }
 
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_new_item() {
-        let item = Item::new("Health Potion", (10, 10));
+        let item = Item::new("Health Potion", Position::new(10, 10));
         assert_eq!(item.name, "Health Potion");
     }
 
     #[test]
     fn test_add_item() {
         let mut player = Player::new("Test Player");
-        let item = Item::new("Health Potion", (10, 10));
+        let item = Item::new("Health Potion", Position::new(10, 10));
         Item::add_item(&mut player, item);
         assert_eq!(player.inventory.len(), 1);
         assert_eq!(player.inventory[0].name, "Health Potion");
@@ -69,14 +86,17 @@ mod tests {
     #[test]
     fn test_use_item() {
         let mut player = Player::new("Test Player");
-        let potion = Item::new("Health Potion", (10, 10));
+        let potion = Item::new("Health Potion", Position::new(10, 10));
         Item::add_item(&mut player, potion);
 
         Item::use_item(&mut player, "Health Potion");
         assert_eq!(player.inventory.len(), 0);
         assert_eq!(player.health, 120);
 
-        Item::use_item(&mut player, "Mana Potion");
+        assert_eq!(
+            Item::use_item(&mut player, "Mana Potion"),
+            "Object not found on inventary."
+        );
         assert_eq!(player.inventory.len(), 0);
         assert_eq!(player.mana, 50);
     }
@@ -84,13 +104,12 @@ mod tests {
     #[test]
     fn test_apply_item_effect() {
         let mut player = Player::new("Test Player");
-        let health_potion = Item::new("Health Potion", (10, 10));
-        let mana_potion = Item::new("Mana Potion", (5, 5));
+        let health_potion = Item::new("Health Potion", Position::new(10, 10));
+        let mana_potion = Item::new("Mana Potion", Position::new(5, 5));
 
         apply_item_effect(&mut player, &health_potion);
         assert_eq!(player.health, 120);
         apply_item_effect(&mut player, &mana_potion);
         assert_eq!(player.mana, 65);
     }
-
-}
\ No newline at end of file
+}
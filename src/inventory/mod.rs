@@ -1 +1 @@
-pub mod item;
\ No newline at end of file
+pub mod item;
@@ -1,9 +1,14 @@
-mod game;
+mod dialogue;
+mod editor;
 mod entities;
+mod game;
+mod generator;
 mod inventory;
 mod maps;
 mod quest;
+mod save;
 mod ui;
+mod world;
 
 fn main() {
     game::start();
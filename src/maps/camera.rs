@@ -0,0 +1,96 @@
+use crate::maps::position::Position;
+
+/// Visible window over the world map, expressed in world coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Camera {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Centers a `width` x `height` view on `target`, clamping it so it never
+    /// shows anything outside a `map_width` x `map_height` world. When the
+    /// world is smaller than the view, the view shrinks to the world size.
+    pub fn centered_on(
+        target: Position,
+        width: usize,
+        height: usize,
+        map_width: usize,
+        map_height: usize,
+    ) -> Self {
+        let width = width.min(map_width);
+        let height = height.min(map_height);
+        let x = Self::clamp_axis(target.x, width, map_width);
+        let y = Self::clamp_axis(target.y, height, map_height);
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn clamp_axis(target: usize, view: usize, world: usize) -> usize {
+        let start = target.saturating_sub(view / 2);
+        start.min(world - view)
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    /// Converts world coordinates into coordinates relative to the view.
+    pub fn screen_position(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        if self.contains(x, y) {
+            Some((x - self.x, y - self.y))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_centered_on() {
+        let camera = Camera::centered_on(Position::new(50, 50), 20, 10, 100, 100);
+        assert_eq!(camera, Camera::new(40, 45, 20, 10));
+    }
+
+    #[test]
+    fn test_centered_on_clamps_to_edges() {
+        let top_left = Camera::centered_on(Position::new(2, 1), 20, 10, 100, 100);
+        assert_eq!((top_left.x, top_left.y), (0, 0));
+
+        let bottom_right = Camera::centered_on(Position::new(99, 99), 20, 10, 100, 100);
+        assert_eq!((bottom_right.x, bottom_right.y), (80, 90));
+    }
+
+    #[test]
+    fn test_view_larger_than_world() {
+        let camera = Camera::centered_on(Position::new(3, 3), 80, 40, 10, 8);
+        assert_eq!(camera, Camera::new(0, 0, 10, 8));
+    }
+
+    #[test]
+    fn test_screen_position() {
+        let camera = Camera::new(10, 5, 20, 10);
+        assert_eq!(camera.screen_position(10, 5), Some((0, 0)));
+        assert_eq!(camera.screen_position(29, 14), Some((19, 9)));
+        assert_eq!(camera.screen_position(30, 14), None);
+        assert_eq!(camera.screen_position(9, 5), None);
+    }
+}
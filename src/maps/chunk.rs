@@ -0,0 +1,241 @@
+//! Chunked terrain storage. The world is split into square chunks that can
+//! be kept in memory all the time (small maps) or streamed in from a
+//! `ChunkSource` around the player and written back when evicted.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::maps::terrain::Terrain;
+use crate::maps::tile::Tile;
+
+/// Side of a chunk, in tiles.
+pub const CHUNK_SIZE: usize = 32;
+
+/// How many chunks around the player stay loaded on streamed maps.
+pub const STREAM_RADIUS: usize = 2;
+
+/// Chunk coordinates of a tile and its position inside that chunk.
+pub fn chunk_of(x: usize, y: usize) -> ((usize, usize), (usize, usize)) {
+    (
+        (x / CHUNK_SIZE, y / CHUNK_SIZE),
+        (x % CHUNK_SIZE, y % CHUNK_SIZE),
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    tiles: Vec<Tile>,
+}
+
+impl Chunk {
+    pub fn filled(terrain: Terrain) -> Self {
+        Self {
+            tiles: vec![Tile::new(terrain); CHUNK_SIZE * CHUNK_SIZE],
+        }
+    }
+
+    /// Tile at a position local to the chunk.
+    pub fn get(&self, x: usize, y: usize) -> &Tile {
+        &self.tiles[y * CHUNK_SIZE + x]
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> &mut Tile {
+        &mut self.tiles[y * CHUNK_SIZE + x]
+    }
+}
+
+/// Where streamed chunks come from and go back to.
+pub trait ChunkSource {
+    /// Produces the chunk at the given chunk coordinates.
+    fn load(&mut self, chunk: (usize, usize)) -> io::Result<Chunk>;
+
+    /// Receives a chunk that is leaving memory. By default it is dropped.
+    fn unload(&mut self, _chunk: (usize, usize), _data: &Chunk) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Builds chunks from a function of world coordinates, e.g. a noise generator.
+pub struct GeneratedChunks<F: FnMut(usize, usize) -> Terrain> {
+    generate: F,
+}
+
+impl<F: FnMut(usize, usize) -> Terrain> GeneratedChunks<F> {
+    pub fn new(generate: F) -> Self {
+        Self { generate }
+    }
+}
+
+impl<F: FnMut(usize, usize) -> Terrain> ChunkSource for GeneratedChunks<F> {
+    fn load(&mut self, chunk: (usize, usize)) -> io::Result<Chunk> {
+        let mut data = Chunk::filled(Terrain::Floor);
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let terrain = (self.generate)(chunk.0 * CHUNK_SIZE + x, chunk.1 * CHUNK_SIZE + y);
+                data.get_mut(x, y).terrain = terrain;
+            }
+        }
+        Ok(data)
+    }
+}
+
+/// Keeps chunks as files in a folder, one file per chunk. Chunks that were
+/// never saved come from `fallback`; evicted chunks are written back, so
+/// changes to the world survive streaming.
+pub struct DiskChunks<S: ChunkSource> {
+    folder: PathBuf,
+    fallback: S,
+}
+
+impl<S: ChunkSource> DiskChunks<S> {
+    pub fn new<P: Into<PathBuf>>(folder: P, fallback: S) -> Self {
+        Self {
+            folder: folder.into(),
+            fallback,
+        }
+    }
+
+    fn path(&self, chunk: (usize, usize)) -> PathBuf {
+        self.folder.join(format!("{}_{}.chunk", chunk.0, chunk.1))
+    }
+}
+
+impl<S: ChunkSource> ChunkSource for DiskChunks<S> {
+    fn load(&mut self, chunk: (usize, usize)) -> io::Result<Chunk> {
+        let path = self.path(chunk);
+        if !path.exists() {
+            return self.fallback.load(chunk);
+        }
+        parse_chunk(&fs::read_to_string(&path)?).map_err(|reason| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), reason),
+            )
+        })
+    }
+
+    fn unload(&mut self, chunk: (usize, usize), data: &Chunk) -> io::Result<()> {
+        fs::create_dir_all(&self.folder)?;
+        fs::write(self.path(chunk), format_chunk(data))
+    }
+}
+
+/// One line of terrain glyphs per row, then `x,y | text` lines for custom
+/// descriptions.
+fn format_chunk(chunk: &Chunk) -> String {
+    let mut out = String::new();
+    for y in 0..CHUNK_SIZE {
+        for x in 0..CHUNK_SIZE {
+            out.push(chunk.get(x, y).terrain.glyph());
+        }
+        out.push('\n');
+    }
+    for y in 0..CHUNK_SIZE {
+        for x in 0..CHUNK_SIZE {
+            let tile = chunk.get(x, y);
+            if !tile.description.is_empty() {
+                out.push_str(&format!("{},{} | {}\n", x, y, tile.description));
+            }
+        }
+    }
+    out
+}
+
+fn parse_chunk(contents: &str) -> Result<Chunk, String> {
+    let mut chunk = Chunk::filled(Terrain::Floor);
+    let mut lines = contents.lines();
+    for y in 0..CHUNK_SIZE {
+        let row = lines
+            .next()
+            .ok_or_else(|| format!("expected {} rows, found {}", CHUNK_SIZE, y))?;
+        if row.chars().count() != CHUNK_SIZE {
+            return Err(format!(
+                "row {} has {} tiles, expected {}",
+                y + 1,
+                row.chars().count(),
+                CHUNK_SIZE
+            ));
+        }
+        for (x, glyph) in row.chars().enumerate() {
+            chunk.get_mut(x, y).terrain = Terrain::from_glyph(glyph)
+                .ok_or_else(|| format!("unknown glyph '{}' in row {}", glyph, y + 1))?;
+        }
+    }
+    for line in lines.filter(|line| !line.trim().is_empty()) {
+        let parsed = line.split_once('|').and_then(|(position, text)| {
+            let (x, y) = position.trim().split_once(',')?;
+            let (x, y): (usize, usize) = (x.parse().ok()?, y.parse().ok()?);
+            (x < CHUNK_SIZE && y < CHUNK_SIZE).then(|| (x, y, text.trim().to_string()))
+        });
+        let (x, y, text) = parsed.ok_or_else(|| format!("invalid description '{}'", line))?;
+        chunk.get_mut(x, y).description = text;
+    }
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_of() {
+        assert_eq!(chunk_of(0, 0), ((0, 0), (0, 0)));
+        assert_eq!(
+            chunk_of(CHUNK_SIZE + 3, 2 * CHUNK_SIZE - 1),
+            ((1, 1), (3, CHUNK_SIZE - 1))
+        );
+    }
+
+    #[test]
+    fn test_generated_chunks() {
+        let mut source = GeneratedChunks::new(|x, _| {
+            if x % 2 == 0 {
+                Terrain::Grass
+            } else {
+                Terrain::Road
+            }
+        });
+        let chunk = source.load((1, 0)).unwrap();
+        assert_eq!(chunk.get(0, 0).terrain, Terrain::Grass);
+        assert_eq!(chunk.get(1, 5).terrain, Terrain::Road);
+    }
+
+    #[test]
+    fn test_chunk_format_round_trip() {
+        let mut chunk = Chunk::filled(Terrain::Grass);
+        chunk.get_mut(3, 4).terrain = Terrain::Wall;
+        chunk.get_mut(5, 6).description = "An old well.".to_string();
+        assert_eq!(parse_chunk(&format_chunk(&chunk)), Ok(chunk));
+    }
+
+    #[test]
+    fn test_chunk_format_errors() {
+        assert!(parse_chunk("...\n").is_err());
+        let mut rows = format_chunk(&Chunk::filled(Terrain::Floor));
+        rows.push_str("99,1 | Out of the chunk\n");
+        assert!(parse_chunk(&rows).is_err());
+    }
+
+    #[test]
+    fn test_disk_chunks() {
+        let folder = std::env::temp_dir().join(format!("uo_chunks_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        let mut source = DiskChunks::new(&folder, GeneratedChunks::new(|_, _| Terrain::Grass));
+
+        let mut chunk = source.load((2, 3)).unwrap();
+        assert_eq!(chunk.get(0, 0).terrain, Terrain::Grass);
+        chunk.get_mut(0, 0).terrain = Terrain::Wall;
+        source.unload((2, 3), &chunk).unwrap();
+
+        assert_eq!(
+            source.load((2, 3)).unwrap().get(0, 0).terrain,
+            Terrain::Wall
+        );
+        assert_eq!(
+            source.load((0, 0)).unwrap().get(0, 0).terrain,
+            Terrain::Grass
+        );
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
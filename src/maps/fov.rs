@@ -0,0 +1,312 @@
+//! Field of view (recursive shadowcasting) and line of sight queries.
+//! Opaque terrain blocks sight; positions outside the map are never visible.
+
+use crate::maps::camera::Camera;
+use crate::maps::maps::Maps;
+use crate::maps::position::Position;
+
+pub const DEFAULT_SIGHT_RADIUS: usize = 12;
+
+/// Multipliers that map the first octant onto each of the eight octants.
+const OCTANTS: [(isize, isize, isize, isize); 8] = [
+    (1, 0, 0, 1),
+    (0, 1, 1, 0),
+    (0, -1, 1, 0),
+    (-1, 0, 0, 1),
+    (-1, 0, 0, -1),
+    (0, -1, -1, 0),
+    (0, 1, -1, 0),
+    (1, 0, 0, -1),
+];
+
+/// The set of positions visible from an origin. Only the box that the sight
+/// radius can reach is stored, so large streamed worlds stay cheap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldOfView {
+    left: usize,
+    top: usize,
+    width: usize,
+    height: usize,
+    visible: Vec<bool>,
+    /// In line of sight but too dark to make out more than the terrain.
+    dark: Vec<bool>,
+}
+
+impl FieldOfView {
+    fn empty(left: usize, top: usize, width: usize, height: usize) -> Self {
+        Self {
+            left,
+            top,
+            width,
+            height,
+            visible: vec![false; width * height],
+            dark: vec![false; width * height],
+        }
+    }
+
+    /// Everything inside the camera is visible (map editor, debugging).
+    pub fn all(camera: &Camera) -> Self {
+        let mut fov = Self::empty(camera.x, camera.y, camera.width, camera.height);
+        fov.visible.fill(true);
+        fov
+    }
+
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        let (x, y) = (x.checked_sub(self.left)?, y.checked_sub(self.top)?);
+        (x < self.width && y < self.height).then_some(y * self.width + x)
+    }
+
+    pub fn is_visible(&self, x: usize, y: usize) -> bool {
+        self.index(x, y).is_some_and(|index| self.visible[index])
+    }
+
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.index(x, y).is_some_and(|index| self.dark[index])
+    }
+
+    /// Keeps a visible position in sight, but only as a dark silhouette.
+    pub fn darken(&mut self, x: usize, y: usize) {
+        if let Some(index) = self.index(x, y).filter(|&index| self.visible[index]) {
+            self.visible[index] = false;
+            self.dark[index] = true;
+        }
+    }
+
+    pub fn visible_positions(&self) -> impl Iterator<Item = Position> + '_ {
+        self.visible
+            .iter()
+            .enumerate()
+            .filter(|(_, visible)| **visible)
+            .map(|(index, _)| {
+                Position::new(
+                    self.left + index % self.width,
+                    self.top + index / self.width,
+                )
+            })
+    }
+
+    fn mark(&mut self, x: usize, y: usize) {
+        if let Some(index) = self.index(x, y) {
+            self.visible[index] = true;
+        }
+    }
+}
+
+impl Maps {
+    fn blocks_sight(&self, x: usize, y: usize) -> bool {
+        self.terrain(Position::new(x, y))
+            .is_none_or(|terrain| terrain.is_opaque())
+    }
+
+    /// Positions visible from `origin` within `radius` tiles.
+    pub fn field_of_view(&self, origin: Position, radius: usize) -> FieldOfView {
+        if !self.contains(origin) {
+            return FieldOfView::empty(0, 0, 0, 0);
+        }
+        let (left, top) = (
+            origin.x.saturating_sub(radius),
+            origin.y.saturating_sub(radius),
+        );
+        let right = (origin.x + radius + 1).min(self.width);
+        let bottom = (origin.y + radius + 1).min(self.height);
+        let mut fov = FieldOfView::empty(left, top, right - left, bottom - top);
+        fov.mark(origin.x, origin.y);
+        for octant in OCTANTS {
+            self.cast_light(&mut fov, origin, 1, 1.0, 0.0, radius, octant);
+        }
+        fov
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn cast_light(
+        &self,
+        fov: &mut FieldOfView,
+        origin: Position,
+        row: usize,
+        mut start: f64,
+        end: f64,
+        radius: usize,
+        (xx, xy, yx, yy): (isize, isize, isize, isize),
+    ) {
+        if start < end {
+            return;
+        }
+        let radius_squared = (radius * radius) as isize;
+        let mut new_start = 0.0;
+
+        for distance in row..=radius {
+            let dy = -(distance as isize);
+            let mut dx = dy - 1;
+            let mut blocked = false;
+
+            while dx <= 0 {
+                dx += 1;
+                let x = origin.x as isize + dx * xx + dy * xy;
+                let y = origin.y as isize + dx * yx + dy * yy;
+                let left_slope = (dx as f64 - 0.5) / (dy as f64 + 0.5);
+                let right_slope = (dx as f64 + 0.5) / (dy as f64 - 0.5);
+
+                if start < right_slope {
+                    continue;
+                } else if end > left_slope {
+                    break;
+                }
+
+                let inside = x >= 0 && y >= 0;
+                let (ux, uy) = (x.max(0) as usize, y.max(0) as usize);
+                if inside && dx * dx + dy * dy <= radius_squared {
+                    fov.mark(ux, uy);
+                }
+
+                let opaque = !inside || self.blocks_sight(ux, uy);
+                if blocked {
+                    if opaque {
+                        new_start = right_slope;
+                    } else {
+                        blocked = false;
+                        start = new_start;
+                    }
+                } else if opaque && distance < radius {
+                    blocked = true;
+                    self.cast_light(
+                        fov,
+                        origin,
+                        distance + 1,
+                        start,
+                        left_slope,
+                        radius,
+                        (xx, xy, yx, yy),
+                    );
+                    new_start = right_slope;
+                }
+            }
+
+            if blocked {
+                break;
+            }
+        }
+    }
+
+    /// Whether nothing opaque stands on the straight line between two positions.
+    /// The end points themselves never block, so a wall can be seen.
+    pub fn has_line_of_sight(&self, from: Position, to: Position) -> bool {
+        if !self.contains(from) || !self.contains(to) {
+            return false;
+        }
+        let (mut x, mut y) = (from.x as isize, from.y as isize);
+        let (target_x, target_y) = (to.x as isize, to.y as isize);
+        let dx = (target_x - x).abs();
+        let dy = -(target_y - y).abs();
+        let step_x = if x < target_x { 1 } else { -1 };
+        let step_y = if y < target_y { 1 } else { -1 };
+        let mut error = dx + dy;
+
+        loop {
+            if (x, y) == (target_x, target_y) {
+                return true;
+            }
+            if (x, y) != (from.x as isize, from.y as isize)
+                && self.blocks_sight(x as usize, y as usize)
+            {
+                return false;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Line of sight limited to `radius` tiles, e.g. for NPC awareness or ranged attacks.
+    pub fn can_see(&self, from: Position, to: Position, radius: usize) -> bool {
+        let dx = from.x.abs_diff(to.x);
+        let dy = from.y.abs_diff(to.y);
+        dx * dx + dy * dy <= radius * radius && self.has_line_of_sight(from, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maps::terrain::Terrain;
+
+    #[test]
+    fn test_open_field() {
+        let map = Maps::new(21, 21);
+        let fov = map.field_of_view(Position::new(10, 10), 5);
+        assert!(fov.is_visible(10, 10));
+        assert!(fov.is_visible(15, 10));
+        assert!(fov.is_visible(10, 5));
+        assert!(fov.is_visible(13, 13));
+        assert!(!fov.is_visible(16, 10));
+        assert!(!fov.is_visible(15, 15));
+    }
+
+    #[test]
+    fn test_walls_block_sight() {
+        let mut map = Maps::new(20, 9);
+        for y in 0..9 {
+            map.set_tile(Position::new(10, y), Terrain::Wall);
+        }
+        let fov = map.field_of_view(Position::new(5, 4), 15);
+        assert!(fov.is_visible(10, 4), "the wall itself is visible");
+        assert!(!fov.is_visible(11, 4));
+        assert!(!fov.is_visible(15, 2));
+        assert!(fov.visible_positions().all(|position| position.x <= 10));
+    }
+
+    #[test]
+    fn test_room_hides_outside() {
+        // A walled 7x7 room on the west side of the map.
+        let mut map = Maps::new(15, 7);
+        for y in 0..7 {
+            for x in 0..7 {
+                if x == 0 || y == 0 || x == 6 || y == 6 {
+                    map.set_tile(Position::new(x, y), Terrain::Wall);
+                }
+            }
+        }
+        let fov = map.field_of_view(Position::new(3, 3), 20);
+        assert!(fov.is_visible(1, 1));
+        assert!(fov.is_visible(6, 3));
+        assert!(!fov.is_visible(8, 3));
+
+        map.set_tile(
+            Position::new(6, 3),
+            Terrain::Door(crate::maps::terrain::DoorState::Open),
+        );
+        let fov = map.field_of_view(Position::new(3, 3), 20);
+        assert!(fov.is_visible(8, 3));
+    }
+
+    #[test]
+    fn test_origin_out_of_bounds() {
+        let map = Maps::new(5, 5);
+        let fov = map.field_of_view(Position::new(9, 9), 5);
+        assert_eq!(fov.visible_positions().count(), 0);
+    }
+
+    #[test]
+    fn test_line_of_sight() {
+        let mut map = Maps::new(10, 10);
+        assert!(map.has_line_of_sight(Position::new(0, 0), Position::new(9, 9)));
+        map.set_tile(Position::new(5, 5), Terrain::Tree);
+        assert!(!map.has_line_of_sight(Position::new(0, 0), Position::new(9, 9)));
+        assert!(map.has_line_of_sight(Position::new(0, 0), Position::new(5, 5)));
+        assert!(map.has_line_of_sight(Position::new(5, 5), Position::new(9, 9)));
+        assert!(map.has_line_of_sight(Position::new(0, 9), Position::new(9, 0)));
+        assert!(!map.has_line_of_sight(Position::new(0, 0), Position::new(10, 10)));
+    }
+
+    #[test]
+    fn test_can_see() {
+        let map = Maps::new(20, 20);
+        assert!(map.can_see(Position::new(0, 0), Position::new(3, 4), 5));
+        assert!(!map.can_see(Position::new(0, 0), Position::new(4, 4), 5));
+    }
+}
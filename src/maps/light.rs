@@ -0,0 +1,197 @@
+//! Light and darkness. As daylight fades the player sees less far; tiles in
+//! line of sight but beyond that range are only dark silhouettes unless a
+//! light source (lamp post, lava) shines on them. Bad weather limits how far
+//! anyone sees and storms dampen flames.
+
+use crate::maps::fov::{FieldOfView, DEFAULT_SIGHT_RADIUS};
+use crate::maps::maps::Maps;
+use crate::maps::position::Position;
+use crate::maps::terrain::{Terrain, ALL_TERRAIN};
+
+/// How far the player sees on a moonless night.
+pub const NIGHT_SIGHT_RADIUS: usize = 3;
+/// How far the player sees at night with a torch in their pack.
+pub const TORCH_SIGHT_RADIUS: usize = 7;
+/// The item that counts as a carried light.
+pub const TORCH: &str = "Torch";
+
+/// What decides how far the player sees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SightConditions {
+    /// Daylight from 0 (night) to 100 (day).
+    pub ambient_light: u8,
+    pub carries_light: bool,
+    /// Farthest anything can be seen, e.g. in fog.
+    pub visibility: usize,
+    /// Storms halve the reach of flames and put out torches.
+    pub fire_dampened: bool,
+}
+
+impl SightConditions {
+    /// Clear daylight.
+    #[cfg(test)]
+    pub fn daylight() -> Self {
+        Self {
+            ambient_light: 100,
+            carries_light: false,
+            visibility: DEFAULT_SIGHT_RADIUS,
+            fire_dampened: false,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn at_light(ambient_light: u8, carries_light: bool) -> Self {
+        Self {
+            ambient_light,
+            carries_light,
+            ..Self::daylight()
+        }
+    }
+
+    fn light_radius(&self, terrain: Terrain) -> usize {
+        if self.fire_dampened && terrain.is_flame() {
+            terrain.light_radius() / 2
+        } else {
+            terrain.light_radius()
+        }
+    }
+}
+
+fn max_light_radius() -> usize {
+    ALL_TERRAIN
+        .iter()
+        .map(|terrain| terrain.light_radius())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Sight radius for an ambient light level from 0 (night) to 100 (day).
+pub fn sight_radius(ambient_light: u8, carries_light: bool) -> usize {
+    let ambient_light = ambient_light.min(100) as usize;
+    let radius =
+        NIGHT_SIGHT_RADIUS + (DEFAULT_SIGHT_RADIUS - NIGHT_SIGHT_RADIUS) * ambient_light / 100;
+    if carries_light {
+        radius.max(TORCH_SIGHT_RADIUS)
+    } else {
+        radius
+    }
+}
+
+impl Maps {
+    /// Whether a light source on the map shines on `position`.
+    pub fn is_lit(&self, position: Position, conditions: &SightConditions) -> bool {
+        let reach = max_light_radius();
+        for y in position.y.saturating_sub(reach)..=position.y + reach {
+            for x in position.x.saturating_sub(reach)..=position.x + reach {
+                let radius = self
+                    .terrain(Position::new(x, y))
+                    .map_or(0, |terrain| conditions.light_radius(terrain));
+                if radius > 0 && self.can_see(Position::new(x, y), position, radius) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Field of view under the given conditions. Standing in the light of a
+    /// lamp lets the player see as far as by day; otherwise tiles past their
+    /// sight radius stay dark unless something lights them.
+    pub fn lit_field_of_view(&self, origin: Position, conditions: &SightConditions) -> FieldOfView {
+        let mut fov = self.field_of_view(origin, conditions.visibility.min(DEFAULT_SIGHT_RADIUS));
+        if self.is_lit(origin, conditions) {
+            return fov;
+        }
+        let carries_light = conditions.carries_light && !conditions.fire_dampened;
+        let radius = sight_radius(conditions.ambient_light, carries_light);
+        let unlit: Vec<Position> = fov
+            .visible_positions()
+            .filter(|position| {
+                !self.can_see(origin, *position, radius) && !self.is_lit(*position, conditions)
+            })
+            .collect();
+        for position in unlit {
+            fov.darken(position.x, position.y);
+        }
+        fov
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maps::terrain::Terrain;
+
+    #[test]
+    fn test_sight_radius() {
+        assert_eq!(sight_radius(100, false), DEFAULT_SIGHT_RADIUS);
+        assert_eq!(sight_radius(0, false), NIGHT_SIGHT_RADIUS);
+        assert_eq!(sight_radius(0, true), TORCH_SIGHT_RADIUS);
+        assert!(sight_radius(50, false) > NIGHT_SIGHT_RADIUS);
+    }
+
+    #[test]
+    fn test_lamp_posts_light_their_surroundings() {
+        let mut map = Maps::new(30, 10);
+        map.set_tile(Position::new(20, 5), Terrain::LampPost);
+        let night = SightConditions::at_light(0, false);
+        assert!(map.is_lit(Position::new(23, 5), &night));
+        assert!(!map.is_lit(Position::new(10, 5), &night));
+        let storm = SightConditions {
+            fire_dampened: true,
+            ..night
+        };
+        assert!(
+            !map.is_lit(Position::new(23, 5), &storm),
+            "storms dampen the flame"
+        );
+        map.set_tile(Position::new(21, 5), Terrain::Wall);
+        assert!(
+            !map.is_lit(Position::new(23, 5), &night),
+            "walls block the light"
+        );
+    }
+
+    #[test]
+    fn test_night_shrinks_the_view() {
+        let mut map = Maps::new(30, 10);
+        map.set_tile(Position::new(14, 5), Terrain::LampPost);
+        let origin = Position::new(2, 5);
+
+        let day = map.lit_field_of_view(origin, &SightConditions::daylight());
+        assert!(day.is_visible(9, 5));
+
+        let night = map.lit_field_of_view(origin, &SightConditions::at_light(0, false));
+        assert!(night.is_visible(4, 5));
+        assert!(!night.is_visible(9, 5));
+        assert!(night.is_dark(9, 5));
+        assert!(night.is_visible(10, 5), "the lamp post lights the street");
+
+        let torch = SightConditions::at_light(0, true);
+        assert!(map.lit_field_of_view(origin, &torch).is_visible(9, 5));
+        let storm = SightConditions {
+            fire_dampened: true,
+            ..torch
+        };
+        assert!(
+            !map.lit_field_of_view(origin, &storm).is_visible(9, 5),
+            "storms put torches out"
+        );
+
+        let under_lamp =
+            map.lit_field_of_view(Position::new(15, 5), &SightConditions::at_light(0, false));
+        assert!(under_lamp.is_visible(26, 5));
+    }
+
+    #[test]
+    fn test_fog_limits_visibility() {
+        let map = Maps::new(30, 10);
+        let fog = SightConditions {
+            visibility: 4,
+            ..SightConditions::daylight()
+        };
+        let fov = map.lit_field_of_view(Position::new(2, 5), &fog);
+        assert!(fov.is_visible(6, 5));
+        assert!(!fov.is_visible(8, 5) && !fov.is_dark(8, 5));
+    }
+}
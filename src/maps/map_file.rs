@@ -0,0 +1,1015 @@
+//! Plain text map files.
+//!
+//! A map file has a header of `key = value` lines followed by a `[map]`
+//! section with one character per tile:
+//!
+//! ```text
+//! ; comment
+//! name = Britain
+//! spawn = 2,2
+//! climate = temperate
+//! npc = Guard | You shall not pass! | 5,2 | 50 | 10 | patrol 5,1 5,2
+//! place = orc | 4,2
+//! spawner = orc, skeleton | 4,1 | 3 | 2 | 30
+//! item = Health Potion | 3,3
+//! describe = 1,1 | A faded mosaic of the Virtues.
+//! portal = 6,1 | Despise | 3,3
+//! region = Britain | 1,1 6,1 | guarded, safe-logout, music:britain
+//! [map]
+//! ########
+//! #......#
+//! ########
+//! ```
+//!
+//! Positions are `x,y`. The climate (`temperate`, `cold` or `none` for
+//! dungeons) decides the weather and defaults to temperate. A portal sends
+//! the player from its tile to a position on the named map. A region is a
+//! named rectangle (two opposite corners) or polygon (three or more corners)
+//! with optional flags: `guarded`, `no-combat`, `no-teleport`,
+//! `safe-logout` and `music:<tag>`.
+//!
+//! An NPC may end with a behaviour such as `wander 3`, `patrol 5,1 5,2` or
+//! `hostile 8 10 flee 10` (see `entities::behaviour`); without one it stands
+//! still. A last field sets its notoriety (`innocent` by default, `ally`,
+//! `enemy`, `criminal`, `murderer` or `invulnerable`). A `place = orc | 4,2`
+//! line puts an NPC built from a template (see `entities::template`) there
+//! instead, and a `spawner` keeps some alive around a point (see
+//! `world::spawner`). Fields are separated by `|`, so names, dialogue and
+//! descriptions cannot contain that character; `check_field` rejects such
+//! text before it reaches a map file.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::entities::behaviour::Behaviour;
+use crate::entities::notoriety::Notoriety;
+use crate::entities::npc::NPC;
+use crate::entities::template::{TemplateError, TemplateRegistry};
+use crate::inventory::item::Item;
+use crate::maps::maps::Maps;
+use crate::maps::portal::Portal;
+use crate::maps::position::Position;
+use crate::maps::region::{Area, Region, RegionFlags};
+use crate::maps::spatial::EntityList;
+use crate::maps::terrain::Terrain;
+use crate::world::spawner::Spawner;
+use crate::world::weather::Climate;
+
+const MAP_SECTION: &str = "[map]";
+
+/// Separates the fields of a line.
+const SEPARATOR: char = '|';
+
+/// Refuses text that could not be read back from a map file.
+pub fn check_field(what: &str, text: &str) -> Result<(), String> {
+    if text.contains(SEPARATOR) {
+        return Err(format!(
+            "{} '{}' cannot contain '{}'.",
+            what, text, SEPARATOR
+        ));
+    }
+    Ok(())
+}
+
+/// A map together with everything placed on it.
+pub struct MapFile {
+    pub name: String,
+    pub spawn: Position,
+    pub climate: Climate,
+    pub map: Maps,
+    pub npcs: EntityList<NPC>,
+    pub items: EntityList<Item>,
+    pub portals: Vec<Portal>,
+    /// Template NPCs as authored; `place_templates` spawns them and saving
+    /// writes these back rather than the NPCs they became.
+    pub placements: Vec<Placement>,
+    pub spawners: Vec<Spawner>,
+    /// File the map was loaded from or last saved to; `None` for generated maps.
+    pub path: Option<PathBuf>,
+}
+
+/// An NPC placed by template id, as in `place = orc | 48,6`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    pub template: String,
+    pub position: Position,
+}
+
+impl MapFile {
+    /// Spawns every placement from `templates`; call it once, after
+    /// loading. Placements naming an unknown template are skipped and
+    /// spawners never pick one. Returns each of them with where it is.
+    pub fn place_templates(
+        &mut self,
+        templates: &TemplateRegistry,
+    ) -> Vec<(Position, TemplateError)> {
+        let mut errors = Vec::new();
+        for placement in &self.placements {
+            match templates.spawn(&placement.template, placement.position) {
+                Ok(npc) => self.npcs.push(npc),
+                Err(err) => errors.push((placement.position, err)),
+            }
+        }
+        for spawner in &self.spawners {
+            for id in spawner
+                .templates
+                .iter()
+                .filter(|id| templates.get(id).is_none())
+            {
+                errors.push((spawner.center, TemplateError::UnknownTemplate(id.clone())));
+            }
+        }
+        errors
+    }
+}
+
+#[derive(Debug)]
+pub enum MapFileError {
+    Io(io::Error),
+    MissingMapSection,
+    EmptyMap,
+    MalformedLine {
+        line: usize,
+        reason: String,
+    },
+    UnknownKey {
+        line: usize,
+        key: String,
+    },
+    UnknownGlyph {
+        line: usize,
+        column: usize,
+        glyph: char,
+    },
+    RaggedRow {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    OutOfBounds {
+        line: usize,
+        position: Position,
+    },
+}
+
+impl fmt::Display for MapFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapFileError::Io(err) => write!(f, "cannot read map file: {}", err),
+            MapFileError::MissingMapSection => write!(f, "missing {} section", MAP_SECTION),
+            MapFileError::EmptyMap => write!(f, "the {} section has no rows", MAP_SECTION),
+            MapFileError::MalformedLine { line, reason } => write!(f, "line {}: {}", line, reason),
+            MapFileError::UnknownKey { line, key } => {
+                write!(f, "line {}: unknown key '{}'", line, key)
+            }
+            MapFileError::UnknownGlyph {
+                line,
+                column,
+                glyph,
+            } => {
+                write!(
+                    f,
+                    "line {}, column {}: unknown glyph '{}'",
+                    line, column, glyph
+                )
+            }
+            MapFileError::RaggedRow {
+                line,
+                expected,
+                found,
+            } => {
+                write!(
+                    f,
+                    "line {}: row has {} tiles, expected {}",
+                    line, found, expected
+                )
+            }
+            MapFileError::OutOfBounds { line, position } => {
+                write!(f, "line {}: position {} is outside the map", line, position)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MapFileError {}
+
+impl From<io::Error> for MapFileError {
+    fn from(err: io::Error) -> Self {
+        MapFileError::Io(err)
+    }
+}
+
+pub fn load_map<P: AsRef<Path>>(path: P) -> Result<MapFile, MapFileError> {
+    let contents = fs::read_to_string(&path)?;
+    let mut map_file = parse_map(&contents)?;
+    map_file.path = Some(path.as_ref().to_path_buf());
+    Ok(map_file)
+}
+
+pub fn save_map<P: AsRef<Path>>(path: P, map_file: &MapFile) -> io::Result<()> {
+    fs::write(path, format_map(map_file))
+}
+
+pub fn parse_map(contents: &str) -> Result<MapFile, MapFileError> {
+    let mut name = String::new();
+    let mut spawn = None;
+    let mut climate = Climate::default();
+    // Placements are validated against the map size once the grid is known.
+    let mut npcs: Vec<(usize, NPC)> = Vec::new();
+    let mut items: Vec<(usize, Item)> = Vec::new();
+    let mut descriptions: Vec<(usize, Position, String)> = Vec::new();
+    let mut portals: Vec<(usize, Portal)> = Vec::new();
+    let mut placements: Vec<(usize, Placement)> = Vec::new();
+    let mut spawners: Vec<(usize, Spawner)> = Vec::new();
+    let mut regions: Vec<(usize, Region)> = Vec::new();
+    let mut rows: Vec<(usize, Vec<Terrain>)> = Vec::new();
+    let mut in_map = false;
+
+    for (index, raw_line) in contents.lines().enumerate() {
+        let line = index + 1;
+        // Lone carriage returns are left behind by some editors.
+        let raw_line = raw_line.trim_end_matches('\r');
+
+        if in_map {
+            if raw_line.is_empty() {
+                continue;
+            }
+            let row = parse_row(line, raw_line)?;
+            if let Some((_, first)) = rows.first() {
+                if first.len() != row.len() {
+                    return Err(MapFileError::RaggedRow {
+                        line,
+                        expected: first.len(),
+                        found: row.len(),
+                    });
+                }
+            }
+            rows.push((line, row));
+            continue;
+        }
+
+        let trimmed = raw_line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(';') {
+            continue;
+        }
+        if trimmed == MAP_SECTION {
+            in_map = true;
+            continue;
+        }
+
+        let (key, value) = trimmed
+            .split_once('=')
+            .ok_or_else(|| MapFileError::MalformedLine {
+                line,
+                reason: "expected 'key = value'".to_string(),
+            })?;
+        let key = key.trim();
+        let value = value.trim();
+        match key {
+            "name" => name = value.to_string(),
+            "spawn" => spawn = Some((line, parse_position(line, value)?)),
+            "climate" => {
+                climate = Climate::from_name(value).ok_or_else(|| MapFileError::MalformedLine {
+                    line,
+                    reason: format!(
+                        "unknown climate '{}', expected temperate, cold or none",
+                        value
+                    ),
+                })?
+            }
+            "npc" => npcs.push((line, parse_npc(line, value)?)),
+            "place" => placements.push((line, parse_placement(line, value)?)),
+            "spawner" => spawners.push((line, parse_spawner(line, value)?)),
+            "item" => items.push((line, parse_item(line, value)?)),
+            "describe" => {
+                let (position, text) = parse_description(line, value)?;
+                descriptions.push((line, position, text));
+            }
+            "portal" => portals.push((line, parse_portal(line, value)?)),
+            "region" => regions.push((line, parse_region(line, value)?)),
+            _ => {
+                return Err(MapFileError::UnknownKey {
+                    line,
+                    key: key.to_string(),
+                })
+            }
+        }
+    }
+
+    if !in_map {
+        return Err(MapFileError::MissingMapSection);
+    }
+    if rows.is_empty() || rows[0].1.is_empty() {
+        return Err(MapFileError::EmptyMap);
+    }
+
+    let mut map = Maps::new(rows[0].1.len(), rows.len());
+    for (y, (_, row)) in rows.into_iter().enumerate() {
+        for (x, terrain) in row.into_iter().enumerate() {
+            map.set_tile(Position::new(x, y), terrain);
+        }
+    }
+
+    let check = |line: usize, position: Position| {
+        if map.contains(position) {
+            Ok(())
+        } else {
+            Err(MapFileError::OutOfBounds { line, position })
+        }
+    };
+    let spawn = match spawn {
+        Some((line, position)) => {
+            check(line, position)?;
+            position
+        }
+        None => Position::default(),
+    };
+    for (line, npc) in &npcs {
+        check(*line, npc.position)?;
+        for waypoint in npc.behaviour.waypoints() {
+            check(*line, *waypoint)?;
+        }
+    }
+    for (line, placement) in &placements {
+        check(*line, placement.position)?;
+    }
+    for (line, spawner) in &spawners {
+        check(*line, spawner.center)?;
+    }
+    for (line, item) in &items {
+        check(*line, item.position)?;
+    }
+    for (line, position, _) in &descriptions {
+        check(*line, *position)?;
+    }
+    for (line, portal) in &portals {
+        check(*line, portal.position)?;
+    }
+    for (line, region) in &regions {
+        for corner in region.area.corners() {
+            check(*line, corner)?;
+        }
+    }
+    for (_, position, text) in &descriptions {
+        map.set_description(*position, text);
+    }
+    for (_, region) in regions {
+        map.add_region(region);
+    }
+
+    Ok(MapFile {
+        name,
+        spawn,
+        climate,
+        map,
+        npcs: EntityList::from(npcs.into_iter().map(|(_, npc)| npc).collect::<Vec<NPC>>()),
+        items: EntityList::from(
+            items
+                .into_iter()
+                .map(|(_, item)| item)
+                .collect::<Vec<Item>>(),
+        ),
+        portals: portals.into_iter().map(|(_, portal)| portal).collect(),
+        placements: placements
+            .into_iter()
+            .map(|(_, placement)| placement)
+            .collect(),
+        spawners: spawners.into_iter().map(|(_, spawner)| spawner).collect(),
+        path: None,
+    })
+}
+
+pub fn format_map(map_file: &MapFile) -> String {
+    let mut out = String::new();
+    if !map_file.name.is_empty() {
+        out.push_str(&format!("name = {}\n", map_file.name));
+    }
+    out.push_str(&format!("spawn = {}\n", map_file.spawn));
+    if map_file.climate != Climate::default() {
+        out.push_str(&format!("climate = {}\n", map_file.climate.name()));
+    }
+    for spawner in &map_file.spawners {
+        out.push_str(&format!(
+            "spawner = {} | {} | {} | {} | {}\n",
+            spawner.templates.join(", "),
+            spawner.center,
+            spawner.radius,
+            spawner.population,
+            spawner.delay
+        ));
+    }
+    // NPCs spawned from templates (placed, by spawners or summoned) come
+    // back from the placements and spawners, not from how they are now.
+    for placement in &map_file.placements {
+        out.push_str(&format!(
+            "place = {} | {}\n",
+            placement.template, placement.position
+        ));
+    }
+    for npc in map_file.npcs.iter().filter(|npc| npc.template.is_none()) {
+        out.push_str(&format!(
+            "npc = {} | {} | {} | {} | {}",
+            npc.name, npc.dialogue, npc.home, npc.health, npc.strength
+        ));
+        let notoriety = match npc.notoriety {
+            Notoriety::Innocent => String::new(),
+            notoriety => format!(" | {}", notoriety.name()),
+        };
+        match (&npc.behaviour, npc.flee_below) {
+            (Behaviour::Idle, 0) if notoriety.is_empty() => (),
+            (behaviour, 0) => out.push_str(&format!(" | {}", behaviour)),
+            (behaviour, flee_below) => {
+                out.push_str(&format!(" | {} flee {}", behaviour, flee_below))
+            }
+        }
+        out.push_str(&notoriety);
+        out.push('\n');
+    }
+    for item in &map_file.items {
+        out.push_str(&format!("item = {} | {}\n", item.name, item.position));
+    }
+    let map = &map_file.map;
+    for y in 0..map.height {
+        for (x, tile) in
+            (0..map.width).filter_map(|x| Some((x, map.get_tile(Position::new(x, y))?)))
+        {
+            if !tile.description.is_empty() {
+                out.push_str(&format!("describe = {},{} | {}\n", x, y, tile.description));
+            }
+        }
+    }
+    for portal in &map_file.portals {
+        out.push_str(&format!(
+            "portal = {} | {} | {}\n",
+            portal.position, portal.target_map, portal.target_position
+        ));
+    }
+    for region in map.regions() {
+        let corners: Vec<String> = region
+            .area
+            .corners()
+            .iter()
+            .map(Position::to_string)
+            .collect();
+        let flags = region.flags.to_string();
+        if flags.is_empty() {
+            out.push_str(&format!(
+                "region = {} | {}\n",
+                region.name,
+                corners.join(" ")
+            ));
+        } else {
+            out.push_str(&format!(
+                "region = {} | {} | {}\n",
+                region.name,
+                corners.join(" "),
+                flags
+            ));
+        }
+    }
+    out.push_str(MAP_SECTION);
+    out.push('\n');
+    for y in 0..map.height {
+        for x in 0..map.width {
+            out.push(
+                map.terrain(Position::new(x, y))
+                    .map_or(' ', |terrain| terrain.glyph()),
+            );
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn parse_row(line: usize, raw_line: &str) -> Result<Vec<Terrain>, MapFileError> {
+    raw_line
+        .chars()
+        .enumerate()
+        .map(|(column, glyph)| {
+            Terrain::from_glyph(glyph).ok_or(MapFileError::UnknownGlyph {
+                line,
+                column: column + 1,
+                glyph,
+            })
+        })
+        .collect()
+}
+
+fn parse_position(line: usize, value: &str) -> Result<Position, MapFileError> {
+    let malformed = || MapFileError::MalformedLine {
+        line,
+        reason: format!("invalid position '{}', expected 'x,y'", value),
+    };
+    let (x, y) = value.split_once(',').ok_or_else(malformed)?;
+    let x = x.trim().parse().map_err(|_| malformed())?;
+    let y = y.trim().parse().map_err(|_| malformed())?;
+    Ok(Position::new(x, y))
+}
+
+fn parse_number<T: std::str::FromStr>(
+    line: usize,
+    field: &str,
+    value: &str,
+) -> Result<T, MapFileError> {
+    value.parse().map_err(|_| MapFileError::MalformedLine {
+        line,
+        reason: format!("invalid {} '{}'", field, value),
+    })
+}
+
+fn split_fields<'a>(
+    line: usize,
+    value: &'a str,
+    expected: usize,
+    usage: &str,
+) -> Result<Vec<&'a str>, MapFileError> {
+    let fields: Vec<&str> = value.split(SEPARATOR).map(str::trim).collect();
+    if fields.len() != expected {
+        return Err(MapFileError::MalformedLine {
+            line,
+            reason: format!(
+                "expected {} fields ({}), found {}",
+                expected,
+                usage,
+                fields.len()
+            ),
+        });
+    }
+    Ok(fields)
+}
+
+fn parse_placement(line: usize, value: &str) -> Result<Placement, MapFileError> {
+    let fields = split_fields(line, value, 2, "template | x,y")?;
+    Ok(Placement {
+        template: fields[0].to_string(),
+        position: parse_position(line, fields[1])?,
+    })
+}
+
+fn parse_spawner(line: usize, value: &str) -> Result<Spawner, MapFileError> {
+    let fields = split_fields(
+        line,
+        value,
+        5,
+        "templates | x,y | radius | population | delay",
+    )?;
+    let templates: Vec<String> = fields[0]
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect();
+    if templates.is_empty() {
+        return Err(MapFileError::MalformedLine {
+            line,
+            reason: "a spawner needs at least one template".to_string(),
+        });
+    }
+    let center = parse_position(line, fields[1])?;
+    let radius = parse_number(line, "radius", fields[2])?;
+    let population = parse_number(line, "population", fields[3])?;
+    let delay = parse_number(line, "delay", fields[4])?;
+    Ok(Spawner::new(templates, center, radius, population, delay))
+}
+
+fn parse_npc(line: usize, value: &str) -> Result<NPC, MapFileError> {
+    const USAGE: &str = "name | dialogue | x,y | health | strength | behaviour | notoriety";
+    // The behaviour and notoriety are optional.
+    let fields = match value.matches(SEPARATOR).count() {
+        4 => split_fields(line, value, 5, USAGE)?,
+        5 => split_fields(line, value, 6, USAGE)?,
+        _ => split_fields(line, value, 7, USAGE)?,
+    };
+    let position = parse_position(line, fields[2])?;
+    let health = parse_number(line, "health", fields[3])?;
+    let strength = parse_number(line, "strength", fields[4])?;
+    let mut npc = NPC::new(fields[0], fields[1], position, health, strength);
+    if let Some(spec) = fields.get(5) {
+        let (behaviour, flee_below) = Behaviour::parse(spec, position)
+            .map_err(|reason| MapFileError::MalformedLine { line, reason })?;
+        npc.behaviour = behaviour;
+        npc.flee_below = flee_below;
+    }
+    if let Some(name) = fields.get(6) {
+        npc.notoriety = Notoriety::from_name(name).ok_or_else(|| MapFileError::MalformedLine {
+            line,
+            reason: format!("unknown notoriety '{}'", name),
+        })?;
+    }
+    Ok(npc)
+}
+
+fn parse_item(line: usize, value: &str) -> Result<Item, MapFileError> {
+    let fields = split_fields(line, value, 2, "name | x,y")?;
+    let position = parse_position(line, fields[1])?;
+    Ok(Item::new(fields[0], position))
+}
+
+fn parse_portal(line: usize, value: &str) -> Result<Portal, MapFileError> {
+    let fields = split_fields(line, value, 3, "x,y | map name | x,y")?;
+    let position = parse_position(line, fields[0])?;
+    let target_position = parse_position(line, fields[2])?;
+    Ok(Portal::new(position, fields[1], target_position))
+}
+
+fn parse_region(line: usize, value: &str) -> Result<Region, MapFileError> {
+    let malformed = |reason: String| MapFileError::MalformedLine { line, reason };
+    let fields: Vec<&str> = value.split(SEPARATOR).map(str::trim).collect();
+    if !(2..=3).contains(&fields.len()) || fields[0].is_empty() {
+        return Err(malformed(
+            "expected 'name | x,y x,y ... | flags'".to_string(),
+        ));
+    }
+    let corners = fields[1]
+        .split_whitespace()
+        .map(|corner| parse_position(line, corner))
+        .collect::<Result<Vec<Position>, MapFileError>>()?;
+    let area = match corners.as_slice() {
+        [a, b] => Area::Rect {
+            top_left: Position::new(a.x.min(b.x), a.y.min(b.y)),
+            bottom_right: Position::new(a.x.max(b.x), a.y.max(b.y)),
+        },
+        [_, _, _, ..] => Area::Polygon(corners),
+        _ => {
+            return Err(malformed(
+                "a region needs two corners or at least three for a polygon".to_string(),
+            ))
+        }
+    };
+    let flags = RegionFlags::parse(fields.get(2).copied().unwrap_or(""))
+        .map_err(|flag| malformed(format!("unknown region flag '{}'", flag)))?;
+    Ok(Region::new(fields[0], area, flags))
+}
+
+fn parse_description(line: usize, value: &str) -> Result<(Position, String), MapFileError> {
+    let fields = split_fields(line, value, 2, "x,y | text")?;
+    let position = parse_position(line, fields[0])?;
+    Ok((position, fields[1].to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::template::{load_templates, NpcTemplate, TEMPLATES_DIR};
+    use crate::world::spawner::update_spawners;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const SAMPLE: &str = "\
+; test map
+name = Test
+spawn = 1,1
+climate = cold
+npc = Guard | You shall not pass! | 3,1 | 50 | 10 | idle | invulnerable
+npc = Rat | Squeak! | 1,1 | 5 | 1 | wander 1 flee 3
+place = orc | 3,1
+spawner = orc, rat | 1,1 | 1 | 2 | 30
+item = Health Potion | 2,1
+describe = 1,1 | A faded mosaic.
+portal = 1,1 | Despise | 4,2
+region = Hall | 3,2 1,0 | guarded, music:hall
+region = Corner | 0,0 2,0 0,2
+[map]
+#####
+#.+~#
+#####
+";
+
+    #[test]
+    fn test_parse_map() {
+        let map_file = parse_map(SAMPLE).unwrap();
+        assert_eq!(map_file.name, "Test");
+        assert_eq!(map_file.spawn, Position::new(1, 1));
+        assert_eq!(map_file.climate, Climate::Cold);
+        assert_eq!(map_file.map.width, 5);
+        assert_eq!(map_file.map.height, 3);
+        assert_eq!(
+            map_file.map.terrain(Position::new(0, 0)),
+            Some(Terrain::Wall)
+        );
+        assert_eq!(
+            map_file.map.terrain(Position::new(3, 1)),
+            Some(Terrain::Water)
+        );
+        assert_eq!(
+            map_file
+                .map
+                .get_tile(Position::new(1, 1))
+                .unwrap()
+                .describe(),
+            "A faded mosaic."
+        );
+        assert!(!map_file.map.is_walkable(Position::new(2, 1)));
+        assert_eq!(map_file.npcs.len(), 2);
+        assert_eq!(map_file.npcs[0].dialogue, "You shall not pass!");
+        assert_eq!(map_file.npcs[0].position, Position::new(3, 1));
+        assert_eq!(map_file.npcs[0].behaviour, Behaviour::Idle);
+        assert_eq!(map_file.npcs[0].notoriety, Notoriety::Invulnerable);
+        assert_eq!(map_file.npcs[1].notoriety, Notoriety::Innocent);
+        assert_eq!(
+            map_file.npcs[1].behaviour,
+            Behaviour::Wander {
+                home: Position::new(1, 1),
+                leash: 1
+            }
+        );
+        assert_eq!(map_file.npcs[1].flee_below, 3);
+        assert_eq!(map_file.items[0].name, "Health Potion");
+        assert_eq!(
+            map_file.portals,
+            vec![Portal::new(
+                Position::new(1, 1),
+                "Despise",
+                Position::new(4, 2)
+            )]
+        );
+        assert_eq!(map_file.map.regions().len(), 2);
+        let hall = &map_file.map.regions()[0];
+        assert_eq!(
+            hall.area,
+            Area::Rect {
+                top_left: Position::new(1, 0),
+                bottom_right: Position::new(3, 2)
+            }
+        );
+        assert!(hall.flags.guarded);
+        assert_eq!(
+            map_file.map.region_at(Position::new(1, 1)).unwrap().name,
+            "Corner"
+        );
+        assert_eq!(
+            map_file.map.region_at(Position::new(3, 1)).unwrap().name,
+            "Hall"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let map_file = parse_map(SAMPLE).unwrap();
+        let saved = format_map(&map_file);
+        let reloaded = parse_map(&saved).unwrap();
+        assert!(reloaded.map.tiles().eq(map_file.map.tiles()));
+        assert_eq!(reloaded.spawn, map_file.spawn);
+        assert_eq!(reloaded.climate, Climate::Cold);
+        assert_eq!(reloaded.npcs[0].name, "Guard");
+        assert_eq!(reloaded.npcs[0].health, 50);
+        assert_eq!(reloaded.npcs[1].behaviour, map_file.npcs[1].behaviour);
+        assert_eq!(reloaded.npcs[1].flee_below, 3);
+        assert_eq!(reloaded.npcs[0].notoriety, Notoriety::Invulnerable);
+        assert_eq!(reloaded.items[0].position, Position::new(2, 1));
+        assert_eq!(reloaded.portals, map_file.portals);
+        assert_eq!(reloaded.map.regions(), map_file.map.regions());
+        assert_eq!(format_map(&reloaded), saved);
+    }
+
+    #[test]
+    fn test_npcs_are_saved_at_home() {
+        let mut map_file = parse_map(SAMPLE).unwrap();
+        map_file.npcs.get_mut(1).unwrap().position = Position::new(3, 1);
+        let reloaded = parse_map(&format_map(&map_file)).unwrap();
+        assert_eq!(reloaded.npcs[1].position, Position::new(1, 1));
+        assert_eq!(reloaded.npcs[1].home, Position::new(1, 1));
+    }
+
+    #[test]
+    fn test_place_templates() {
+        let mut templates = TemplateRegistry::new();
+        let mut map_file = parse_map(SAMPLE).unwrap();
+        assert_eq!(
+            map_file.placements,
+            vec![Placement {
+                template: "orc".to_string(),
+                position: Position::new(3, 1)
+            }]
+        );
+        let errors = map_file.place_templates(&templates);
+        let errors: Vec<(Position, String)> = errors
+            .into_iter()
+            .map(|(position, err)| (position, err.to_string()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    Position::new(3, 1),
+                    "unknown NPC template 'orc'".to_string()
+                ),
+                (
+                    Position::new(1, 1),
+                    "unknown NPC template 'orc'".to_string()
+                ),
+                (
+                    Position::new(1, 1),
+                    "unknown NPC template 'rat'".to_string()
+                ),
+            ]
+        );
+        assert_eq!(map_file.npcs.len(), 2);
+
+        let mut map_file = parse_map(SAMPLE).unwrap();
+        templates.insert(NpcTemplate::new("orc", "Orc", 40, 8));
+        let errors = map_file.place_templates(&templates);
+        assert_eq!(
+            errors.len(),
+            1,
+            "the known placement is spawned, the unknown spawner template reported"
+        );
+        assert_eq!(map_file.npcs[2].name, "Orc");
+        templates.insert(NpcTemplate::new("rat", "Rat", 5, 1));
+        assert_eq!(
+            map_file.spawners[0].templates,
+            vec!["orc".to_string(), "rat".to_string()]
+        );
+        assert_eq!(
+            (
+                map_file.spawners[0].radius,
+                map_file.spawners[0].population,
+                map_file.spawners[0].delay
+            ),
+            (1, 2, 30)
+        );
+
+        let mut spawned = templates.spawn("rat", Position::new(1, 1)).unwrap();
+        spawned.spawner = Some(0);
+        map_file.npcs.push(spawned);
+        map_file.npcs.get_mut(2).unwrap().position = Position::new(1, 1);
+
+        let saved = format_map(&map_file);
+        assert!(
+            saved.contains("place = orc | 3,1\n"),
+            "the placement is written, not where the orc went"
+        );
+        assert!(saved.contains("spawner = orc, rat | 1,1 | 1 | 2 | 30\n"));
+        assert!(!saved.contains("npc = Orc"));
+        assert_eq!(
+            parse_map(&saved).unwrap().placements,
+            parse_map(SAMPLE).unwrap().placements
+        );
+
+        assert!(
+            !saved.contains("place = rat"),
+            "spawned NPCs are left to their spawner"
+        );
+
+        let err = parse_map("place = orc | 9,9\n[map]\n..\n").err().unwrap();
+        assert!(matches!(err, MapFileError::OutOfBounds { line: 1, .. }));
+        let err = parse_map("spawner = orc | 0,0 | 2 | many | 30\n[map]\n..\n")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("population"));
+        let err = parse_map("spawner = | 0,0 | 2 | 1 | 30\n[map]\n..\n")
+            .err()
+            .unwrap();
+        assert!(matches!(err, MapFileError::MalformedLine { line: 1, .. }));
+    }
+
+    #[test]
+    fn test_line_endings_and_separators() {
+        let map_file = parse_map(&SAMPLE.replace('\n', "\r\n")).unwrap();
+        assert_eq!((map_file.map.width, map_file.map.height), (5, 3));
+        assert_eq!(map_file.npcs[0].dialogue, "You shall not pass!");
+        assert_eq!(parse_map("[map]\n..\r\r\n..\r").unwrap().map.height, 2);
+
+        assert!(check_field("Name", "Lord British").is_ok());
+        assert_eq!(
+            check_field("Name", "Lord | British").unwrap_err(),
+            "Name 'Lord | British' cannot contain '|'."
+        );
+    }
+
+    #[test]
+    fn test_unknown_glyph() {
+        let err = parse_map("[map]\n###\n#?#\n").err().unwrap();
+        assert!(matches!(
+            err,
+            MapFileError::UnknownGlyph {
+                line: 3,
+                column: 2,
+                glyph: '?'
+            }
+        ));
+    }
+
+    #[test]
+    fn test_ragged_row() {
+        let err = parse_map("[map]\n###\n##\n").err().unwrap();
+        assert!(matches!(
+            err,
+            MapFileError::RaggedRow {
+                line: 3,
+                expected: 3,
+                found: 2
+            }
+        ));
+    }
+
+    #[test]
+    fn test_malformed_lines() {
+        let err = parse_map("spawn 1,1\n[map]\n.\n").err().unwrap();
+        assert!(matches!(err, MapFileError::MalformedLine { line: 1, .. }));
+
+        let err = parse_map("npc = Guard | hi | 1,1 | lots | 10\n[map]\n..\n")
+            .err()
+            .unwrap();
+        assert!(matches!(err, MapFileError::MalformedLine { line: 1, .. }));
+        assert!(err.to_string().contains("health"));
+        let err = parse_map("npc = Rat | hi | 1,1 | 5 | 1 | dance\n[map]\n..\n")
+            .err()
+            .unwrap();
+        assert!(matches!(err, MapFileError::MalformedLine { line: 1, .. }));
+        let err = parse_map("npc = Rat | hi | 1,1 | 5 | 1 | idle | blue\n[map]\n..\n")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("blue"));
+
+        let err = parse_map("region = Moonglow | 0,0 | guarded\n[map]\n..\n")
+            .err()
+            .unwrap();
+        assert!(matches!(err, MapFileError::MalformedLine { line: 1, .. }));
+        let err = parse_map("region = Moonglow | 0,0 1,0 | peaceful\n[map]\n..\n")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("peaceful"));
+        let err = parse_map("region = Moonglow | 0,0 5,0\n[map]\n..\n")
+            .err()
+            .unwrap();
+        assert!(matches!(err, MapFileError::OutOfBounds { line: 1, .. }));
+
+        let err = parse_map("color = red\n[map]\n.\n").err().unwrap();
+        assert!(matches!(err, MapFileError::UnknownKey { line: 1, .. }));
+    }
+
+    #[test]
+    fn test_out_of_bounds_and_missing_map() {
+        let err = parse_map("item = Potion | 4,0\n[map]\n...\n")
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            MapFileError::OutOfBounds {
+                line: 1,
+                position: Position { x: 4, y: 0 }
+            }
+        ));
+        let err = parse_map("npc = Guard | Halt! | 0,0 | 50 | 10 | patrol 2,0 9,0\n[map]\n...\n")
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            MapFileError::OutOfBounds {
+                line: 1,
+                position: Position { x: 9, y: 0 }
+            }
+        ));
+
+        assert!(matches!(
+            parse_map("name = Nowhere\n").err().unwrap(),
+            MapFileError::MissingMapSection
+        ));
+        assert!(matches!(
+            parse_map("[map]\n").err().unwrap(),
+            MapFileError::EmptyMap
+        ));
+    }
+
+    #[test]
+    fn test_load_start_map() {
+        let map_file = load_map("data/maps/start.map").unwrap();
+        assert_eq!(map_file.name, "Britain");
+        assert!(map_file.map.is_empty(map_file.spawn));
+    }
+
+    #[test]
+    fn test_bundled_placements_are_walkable() {
+        let templates = load_templates(TEMPLATES_DIR).unwrap();
+        for path in ["data/maps/start.map", "data/maps/despise.map"] {
+            let mut map_file = load_map(path).unwrap();
+            assert!(map_file.place_templates(&templates).is_empty());
+            let population: usize = map_file
+                .spawners
+                .iter()
+                .map(|spawner| spawner.population)
+                .sum();
+            let mut rng = StdRng::seed_from_u64(3);
+            let spawned = update_spawners(
+                &mut map_file.spawners,
+                &map_file.map,
+                &mut map_file.npcs,
+                &templates,
+                0,
+                &mut rng,
+            );
+            assert_eq!(spawned, population, "spawners of {} have room", path);
+            for npc in map_file.npcs.iter() {
+                assert!(
+                    map_file.map.is_walkable(npc.position),
+                    "{} in {}",
+                    npc.name,
+                    path
+                );
+            }
+        }
+    }
+}
@@ -1,120 +1,522 @@
-use crate::maps::tile::{TileContent};
-use crate::entities::player::Player;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::io;
+
 use crate::entities::npc::NPC;
 use crate::inventory::item::Item;
+use crate::maps::camera::Camera;
+use crate::maps::chunk::{chunk_of, Chunk, ChunkSource, CHUNK_SIZE};
+use crate::maps::fov::FieldOfView;
+use crate::maps::position::{Direction, Position};
+use crate::maps::region::Region;
+use crate::maps::spatial::EntityList;
+use crate::maps::terrain::{DoorState, Terrain};
+use crate::maps::tile::{CellVisibility, DrawnCell, Tile, TileContent};
 
+/// Persistent world map. The terrain layer is stored in chunks and has a
+/// fixed size that does not depend on the terminal; occupants are drawn on
+/// top. Small maps keep every chunk in memory, streamed maps only hold the
+/// chunks around the player (see `stream_around`). Tiles of chunks that are
+/// not loaded behave like solid rock: not walkable, opaque and drawn blank.
 pub struct Maps {
     pub width: usize,
     pub height: usize,
-    pub grid: Vec<Vec<TileContent>>,
+    chunks: HashMap<(usize, usize), Chunk>,
+    source: Option<Box<dyn ChunkSource>>,
+    player_position: Option<Position>,
+    /// Named areas, see `region.rs`.
+    regions: Vec<Region>,
 }
 
 impl Maps {
-
     pub fn new(width: usize, height: usize) -> Self {
+        let mut map = Self {
+            width,
+            height,
+            chunks: HashMap::new(),
+            source: None,
+            player_position: None,
+            regions: Vec::new(),
+        };
+        map.fill_missing_chunks();
+        map
+    }
+
+    /// Creates a map whose chunks come from `source` on demand. Nothing is
+    /// loaded until `stream_around` is called.
+    pub fn streamed(width: usize, height: usize, source: Box<dyn ChunkSource>) -> Self {
         Self {
             width,
             height,
-            grid: vec![vec![TileContent::Empty; width]; height]
+            chunks: HashMap::new(),
+            source: Some(source),
+            player_position: None,
+            regions: Vec::new(),
+        }
+    }
+
+    /// Creates a map whose outer ring is made of walls.
+    #[cfg(test)]
+    pub fn with_walls(width: usize, height: usize) -> Self {
+        let mut map = Self::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                    map.set_tile(Position::new(x, y), Terrain::Wall);
+                }
+            }
         }
+        map
     }
+
+    fn chunk_count(&self) -> (usize, usize) {
+        (
+            self.width.div_ceil(CHUNK_SIZE),
+            self.height.div_ceil(CHUNK_SIZE),
+        )
+    }
+
+    fn fill_missing_chunks(&mut self) {
+        let (columns, rows) = self.chunk_count();
+        for cy in 0..rows {
+            for cx in 0..columns {
+                self.chunks
+                    .entry((cx, cy))
+                    .or_insert_with(|| Chunk::filled(Terrain::Floor));
+            }
+        }
+    }
+
+    /// Changes the world size, keeping every tile that is still inside the new bounds.
+    // Nothing resizes a loaded map yet; maps are sized by their file.
+    #[allow(dead_code)]
     pub fn resize(&mut self, width: usize, height: usize) {
+        let (old_width, old_height) = (self.width, self.height);
         self.width = width;
-        self.height= height;
-        self.grid = vec![vec![TileContent::Empty; width]; height];
+        self.height = height;
+        let (columns, rows) = self.chunk_count();
+        self.chunks.retain(|&(cx, cy), _| cx < columns && cy < rows);
+
+        // Tiles cut off by a shrink must come back as floor if the map grows again.
+        for ((cx, cy), chunk) in self.chunks.iter_mut() {
+            for ly in 0..CHUNK_SIZE {
+                for lx in 0..CHUNK_SIZE {
+                    let (x, y) = (cx * CHUNK_SIZE + lx, cy * CHUNK_SIZE + ly);
+                    let outside = x >= width || y >= height;
+                    if outside && (x < old_width && y < old_height) {
+                        *chunk.get_mut(lx, ly) = Tile::new(Terrain::Floor);
+                    }
+                }
+            }
+        }
+        if self.source.is_none() {
+            self.fill_missing_chunks();
+        }
+
+        if let Some(position) = self.player_position {
+            if !self.contains(position) {
+                self.player_position = None;
+            }
+        }
+    }
+
+    /// Loads the chunks within `radius` chunks of `center` and hands back to
+    /// the source the ones further away than `radius + 1`, so walking back
+    /// and forth over a chunk border does not reload it every step.
+    /// Maps without a chunk source keep everything and ignore this.
+    pub fn stream_around(&mut self, center: Position, radius: usize) -> io::Result<()> {
+        let Some(source) = self.source.as_mut() else {
+            return Ok(());
+        };
+        let ((center_x, center_y), _) = chunk_of(center.x, center.y);
+
+        let far: Vec<(usize, usize)> = self
+            .chunks
+            .keys()
+            .filter(|&&(cx, cy)| {
+                cx.abs_diff(center_x) > radius + 1 || cy.abs_diff(center_y) > radius + 1
+            })
+            .copied()
+            .collect();
+        for position in far {
+            // Dropped only once the source has it, so a failed write loses nothing.
+            source.unload(position, &self.chunks[&position])?;
+            self.chunks.remove(&position);
+        }
+
+        let (columns, rows) = (
+            self.width.div_ceil(CHUNK_SIZE),
+            self.height.div_ceil(CHUNK_SIZE),
+        );
+        for cy in center_y.saturating_sub(radius)..(center_y + radius + 1).min(rows) {
+            for cx in center_x.saturating_sub(radius)..(center_x + radius + 1).min(columns) {
+                if let Entry::Vacant(entry) = self.chunks.entry((cx, cy)) {
+                    entry.insert(source.load((cx, cy))?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands every loaded chunk back to the source, e.g. before quitting.
+    pub fn unload_all(&mut self) -> io::Result<()> {
+        let Some(source) = self.source.as_mut() else {
+            return Ok(());
+        };
+        let loaded: Vec<(usize, usize)> = self.chunks.keys().copied().collect();
+        for position in loaded {
+            source.unload(position, &self.chunks[&position])?;
+            self.chunks.remove(&position);
+        }
+        Ok(())
+    }
+
+    /// Whether the chunks come from a `ChunkSource` instead of all living
+    /// in memory.
+    pub fn is_streamed(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// Whether the chunk holding the position is in memory.
+    pub fn is_loaded(&self, position: Position) -> bool {
+        self.contains(position)
+            && self
+                .chunks
+                .contains_key(&chunk_of(position.x, position.y).0)
+    }
+
+    #[cfg(test)]
+    pub fn loaded_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Adds a region on top of the existing ones.
+    pub fn add_region(&mut self, region: Region) {
+        self.regions.push(region);
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
     }
 
     pub fn width(&self) -> usize {
-        self.grid[0].len()
+        self.width
     }
 
     pub fn height(&self) -> usize {
-        self.grid.len()
+        self.height
     }
 
     pub fn in_bounds(&self, x: usize, y: usize) -> bool {
-        x <= self.width && y <= self.height
+        x < self.width && y < self.height
     }
 
-    pub fn is_empty(&self, x: usize, y: usize) -> bool {
-        self.grid[y][x] == TileContent::Empty
+    pub fn contains(&self, position: Position) -> bool {
+        self.in_bounds(position.x, position.y)
     }
 
-    pub fn find_nearby_npc(&self, player_position: (usize, usize), npcs: &Vec<NPC>) -> Option<usize> {
-        npcs.iter().position(|npc| {
-            let (npc_x, npc_y) = npc.position;
-            let (player_x, player_y) = player_position;
-            (npc_x as isize - player_x as isize).abs() <= 1 && (npc_y as isize - player_y as isize).abs() <= 1
-        })
+    /// The neighbour of `position` in `direction`, if it lies on the map.
+    pub fn neighbour(&self, position: Position, direction: Direction) -> Option<Position> {
+        position.step(direction).filter(|&next| self.contains(next))
+    }
+
+    /// Whether the terrain at the position can be walked on.
+    pub fn is_walkable(&self, position: Position) -> bool {
+        self.terrain(position)
+            .is_some_and(|terrain| terrain.is_walkable())
+    }
+
+    /// Whether the position is walkable and not taken by the player.
+    pub fn is_empty(&self, position: Position) -> bool {
+        self.is_walkable(position) && self.player_position != Some(position)
+    }
+
+    pub fn get_tile(&self, position: Position) -> Option<&Tile> {
+        if !self.contains(position) {
+            return None;
+        }
+        let (chunk, (lx, ly)) = chunk_of(position.x, position.y);
+        self.chunks.get(&chunk).map(|chunk| chunk.get(lx, ly))
+    }
+
+    fn get_tile_mut(&mut self, position: Position) -> Option<&mut Tile> {
+        if !self.contains(position) {
+            return None;
+        }
+        let (chunk, (lx, ly)) = chunk_of(position.x, position.y);
+        self.chunks
+            .get_mut(&chunk)
+            .map(|chunk| chunk.get_mut(lx, ly))
     }
-    pub fn find_nearby(&self, player_position: (usize, usize), items: &mut Vec<Item>) -> Option<usize> {
-        items.iter().position(|item| {
-            let (item_x, item_y) = item.position;
-            let (player_x, player_y) = player_position;
-            (item_x as isize - player_x as isize).abs() <= 1 && (item_y as isize - player_y as isize).abs() <= 1
+
+    /// Loaded tiles in row-major order.
+    #[cfg(test)]
+    pub fn tiles(&self) -> impl Iterator<Item = &Tile> + '_ {
+        (0..self.height).flat_map(move |y| {
+            (0..self.width).filter_map(move |x| self.get_tile(Position::new(x, y)))
         })
     }
-    pub fn update_player_position(&mut self, x: usize, y: usize) {
-        if self.in_bounds(x, y){
-            self.grid[y][x] = TileContent::Player;
+
+    pub fn terrain(&self, position: Position) -> Option<Terrain> {
+        self.get_tile(position).map(|tile| tile.terrain)
+    }
+
+    /// Replaces the tile at the position, dropping any custom description.
+    pub fn set_tile(&mut self, position: Position, terrain: Terrain) {
+        if let Some(tile) = self.get_tile_mut(position) {
+            *tile = Tile::new(terrain);
         }
     }
 
-    pub fn draw(&self, player_position: (usize, usize), npcs: &Vec<NPC>, items: &Vec<Item>) -> String {
-        let mut map_string = String::new();
+    pub fn set_description(&mut self, position: Position, description: &str) {
+        if let Some(tile) = self.get_tile_mut(position) {
+            tile.description = description.to_string();
+        }
+    }
+
+    /// What the player sees when looking at a position: the tile description
+    /// followed by the names of the NPCs and items standing there.
+    pub fn describe_at(
+        &self,
+        position: Position,
+        npcs: &EntityList<NPC>,
+        items: &EntityList<Item>,
+    ) -> String {
+        let Some(tile) = self.get_tile(position) else {
+            return "You see nothing there.".to_string();
+        };
+        let mut description = tile.describe().to_string();
+        let names: Vec<&str> = npcs
+            .at(position)
+            .map(|index| npcs[index].name.as_str())
+            .chain(items.at(position).map(|index| items[index].name.as_str()))
+            .collect();
+        if !names.is_empty() {
+            description.push_str(&format!(" You see: {}.", names.join(", ")));
+        }
+        description
+    }
 
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let tile: TileContent = if (x, y) == player_position {
-                    TileContent::Player
-                } else if npcs.iter().any(|npc| npc.position == (x, y)) {
-                    TileContent::NPC
-                } else if items.iter().any(|item| item.position == (x, y)) {
-                    TileContent::Item
-                }else {
-                    TileContent::Empty
-                };
-
-                map_string.push(tile.to_char());
+    /// Opens or closes the first door next to `position`.
+    /// Returns the message to show, or `None` when there is no door around.
+    pub fn use_door_near(&mut self, position: Position) -> Option<String> {
+        let (door, state) = Direction::CARDINAL.iter().find_map(|&direction| {
+            let next = self.neighbour(position, direction)?;
+            match self.terrain(next) {
+                Some(Terrain::Door(state)) => Some((next, state)),
+                _ => None,
             }
-            map_string.push('\n');
+        })?;
+
+        let message = match state {
+            DoorState::Open => {
+                self.set_tile(door, Terrain::Door(DoorState::Closed));
+                "You close the door."
+            }
+            DoorState::Closed => {
+                self.set_tile(door, Terrain::Door(DoorState::Open));
+                "You open the door."
+            }
+            DoorState::Locked => "The door is locked.",
+        };
+        Some(message.to_string())
+    }
+
+    pub fn find_nearby_npc(
+        &self,
+        player_position: Position,
+        npcs: &EntityList<NPC>,
+    ) -> Option<usize> {
+        npcs.nearby(player_position)
+    }
+    pub fn find_nearby(
+        &self,
+        player_position: Position,
+        items: &EntityList<Item>,
+    ) -> Option<usize> {
+        items.nearby(player_position)
+    }
+
+    /// Records where the player stands so the tile is no longer empty.
+    pub fn update_player_position(&mut self, position: Position) {
+        if self.contains(position) {
+            self.player_position = Some(position);
         }
+    }
 
-        map_string
+    /// Forgets the player, who has left this map.
+    pub fn clear_player_position(&mut self) {
+        self.player_position = None;
     }
 
-    pub fn resize_to_frame(&mut self, frame_width: usize, frame_height: usize) {
-        self.width = frame_width;
-        self.height = frame_height;
-        self.grid = vec![vec![TileContent::Empty; self.width]; self.height];
+    /// Camera covering the whole map.
+    pub fn full_view(&self) -> Camera {
+        Camera::new(0, 0, self.width, self.height)
     }
-}
 
+    /// Renders the part of the world inside `camera`, one row of cells per
+    /// line. Tiles in `fov` show terrain and occupants, dark tiles in `fov`
+    /// and `explored` tiles out of view show only their terrain and the rest
+    /// stays blank.
+    pub fn draw_cells(
+        &self,
+        camera: &Camera,
+        fov: &FieldOfView,
+        explored: &HashSet<Position>,
+        player_position: Position,
+        npcs: &EntityList<NPC>,
+        items: &EntityList<Item>,
+    ) -> Vec<Vec<DrawnCell>> {
+        let x_end = (camera.x + camera.width).min(self.width);
+        let y_end = (camera.y + camera.height).min(self.height);
+
+        (camera.y..y_end)
+            .map(|y| {
+                (camera.x..x_end)
+                    .map(|x| {
+                        let Some(terrain) = self.terrain(Position::new(x, y)) else {
+                            return DrawnCell {
+                                glyph: ' ',
+                                visibility: CellVisibility::Unknown,
+                                notoriety: None,
+                                colour: None,
+                            };
+                        };
+                        if fov.is_dark(x, y) {
+                            return DrawnCell {
+                                glyph: terrain.glyph(),
+                                visibility: CellVisibility::Dark,
+                                notoriety: None,
+                                colour: None,
+                            };
+                        }
+                        if !fov.is_visible(x, y) {
+                            return if explored.contains(&Position::new(x, y)) {
+                                DrawnCell {
+                                    glyph: terrain.glyph(),
+                                    visibility: CellVisibility::Remembered,
+                                    notoriety: None,
+                                    colour: None,
+                                }
+                            } else {
+                                DrawnCell {
+                                    glyph: ' ',
+                                    visibility: CellVisibility::Unknown,
+                                    notoriety: None,
+                                    colour: None,
+                                }
+                            };
+                        }
+
+                        let position = Position::new(x, y);
+                        let npc = npcs.at(position).next();
+                        let tile: TileContent = if position == player_position {
+                            TileContent::Player
+                        } else if npc.is_some() {
+                            TileContent::Npc
+                        } else if items.any_at(position) {
+                            TileContent::Item
+                        } else {
+                            TileContent::Empty
+                        };
+                        match (tile, npc) {
+                            (TileContent::Npc, Some(index)) => DrawnCell {
+                                glyph: npcs[index].glyph,
+                                visibility: CellVisibility::Visible,
+                                notoriety: Some(npcs[index].notoriety),
+                                colour: npcs[index].colour,
+                            },
+                            (TileContent::Empty, _) => DrawnCell {
+                                glyph: terrain.glyph(),
+                                visibility: CellVisibility::Visible,
+                                notoriety: None,
+                                colour: None,
+                            },
+                            (occupant, _) => DrawnCell {
+                                glyph: occupant.to_char(),
+                                visibility: CellVisibility::Visible,
+                                notoriety: None,
+                                colour: None,
+                            },
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Plain text version of `draw_cells`.
+    #[cfg(test)]
+    pub fn draw(
+        &self,
+        camera: &Camera,
+        fov: &FieldOfView,
+        explored: &HashSet<Position>,
+        player_position: Position,
+        npcs: &EntityList<NPC>,
+        items: &EntityList<Item>,
+    ) -> String {
+        let mut map_string = String::new();
+        for row in self.draw_cells(camera, fov, explored, player_position, npcs, items) {
+            map_string.extend(row.iter().map(|cell| cell.glyph));
+            map_string.push('\n');
+        }
+        map_string
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::entities::notoriety::Notoriety;
+    use crate::entities::player::Player;
+    use crate::maps::chunk::{DiskChunks, GeneratedChunks};
+    use crate::maps::light::SightConditions;
+    use crate::maps::pathfinding::Movement;
+    use crate::maps::tile::Colour;
 
     #[test]
     fn test_new_maps() {
         let maps = Maps::new(10, 10);
         assert_eq!(maps.width, 10);
         assert_eq!(maps.height, 10);
-        assert_eq!(maps.grid.len(), 10);
-        assert_eq!(maps.grid[0].len(), 10);
-        assert_eq!(maps.grid[0][0], TileContent::Empty);
+        assert_eq!(maps.tiles().count(), 100);
+        assert_eq!(maps.terrain(Position::new(0, 0)), Some(Terrain::Floor));
     }
 
-
     #[test]
     fn test_resize_maps() {
         let mut maps = Maps::new(10, 10);
         maps.resize(20, 15);
         assert_eq!(maps.width, 20);
         assert_eq!(maps.height, 15);
-        assert_eq!(maps.grid.len(), 15);
-        assert_eq!(maps.grid[0].len(), 20);
-        assert_eq!(maps.grid[0][0], TileContent::Empty);
+        assert_eq!(maps.tiles().count(), 300);
+        assert_eq!(maps.terrain(Position::new(19, 14)), Some(Terrain::Floor));
+    }
+
+    #[test]
+    fn test_resize_keeps_terrain() {
+        let mut maps = Maps::new(10, 10);
+        maps.set_tile(Position::new(3, 4), Terrain::Wall);
+        maps.resize(20, 15);
+        assert_eq!(maps.terrain(Position::new(3, 4)), Some(Terrain::Wall));
+        maps.resize(5, 5);
+        assert_eq!(maps.terrain(Position::new(3, 4)), Some(Terrain::Wall));
+        assert_eq!(maps.terrain(Position::new(5, 4)), None);
+
+        maps.set_tile(Position::new(4, 4), Terrain::Water);
+        maps.resize(3, 3);
+        maps.resize(5, 5);
+        assert_eq!(maps.terrain(Position::new(4, 4)), Some(Terrain::Floor));
+    }
+
+    #[test]
+    fn test_with_walls() {
+        let maps = Maps::with_walls(6, 4);
+        assert_eq!(maps.terrain(Position::new(0, 0)), Some(Terrain::Wall));
+        assert_eq!(maps.terrain(Position::new(5, 3)), Some(Terrain::Wall));
+        assert!(maps.is_empty(Position::new(1, 1)));
+        assert!(maps.is_empty(Position::new(4, 2)));
     }
 
     #[test]
@@ -136,59 +538,456 @@ mod tests {
     #[test]
     fn test_is_empty() {
         let mut maps = Maps::new(5, 5);
-        assert!(maps.is_empty(2, 2));
-        maps.grid[2][2] = TileContent::Player;
-        assert!(!maps.is_empty(2, 2));
+        assert!(maps.is_empty(Position::new(2, 2)));
+        maps.update_player_position(Position::new(2, 2));
+        assert!(!maps.is_empty(Position::new(2, 2)));
+        maps.set_tile(Position::new(3, 3), Terrain::Water);
+        assert!(maps.is_empty(Position::new(3, 3)));
+        maps.set_tile(Position::new(3, 3), Terrain::DeepWater);
+        assert!(!maps.is_empty(Position::new(3, 3)));
+    }
+
+    #[test]
+    fn test_use_door_near() {
+        let mut maps = Maps::new(5, 5);
+        assert_eq!(maps.use_door_near(Position::new(2, 2)), None);
+
+        maps.set_tile(Position::new(2, 1), Terrain::Door(DoorState::Closed));
+        assert!(!maps.is_walkable(Position::new(2, 1)));
+        assert_eq!(
+            maps.use_door_near(Position::new(2, 2)),
+            Some("You open the door.".to_string())
+        );
+        assert!(maps.is_walkable(Position::new(2, 1)));
+        assert_eq!(
+            maps.use_door_near(Position::new(2, 2)),
+            Some("You close the door.".to_string())
+        );
+        assert!(!maps.is_walkable(Position::new(2, 1)));
+
+        maps.set_tile(Position::new(2, 1), Terrain::Door(DoorState::Locked));
+        assert_eq!(
+            maps.use_door_near(Position::new(2, 2)),
+            Some("The door is locked.".to_string())
+        );
+        assert_eq!(
+            maps.terrain(Position::new(2, 1)),
+            Some(Terrain::Door(DoorState::Locked))
+        );
+    }
+
+    #[test]
+    fn test_describe_at() {
+        let mut maps = Maps::new(5, 5);
+        maps.set_tile(Position::new(1, 1), Terrain::Wall);
+        maps.set_description(Position::new(2, 2), "A faded mosaic of the Virtues.");
+        let npcs = EntityList::from(vec![NPC::new(
+            "Guard",
+            "Halt!",
+            Position::new(2, 2),
+            50,
+            10,
+        )]);
+        let items = EntityList::from(vec![Item::new("Health Potion", Position::new(2, 2))]);
+
+        assert_eq!(
+            maps.describe_at(Position::new(1, 1), &npcs, &items),
+            "A sturdy stone wall."
+        );
+        assert_eq!(
+            maps.describe_at(Position::new(2, 2), &npcs, &items),
+            "A faded mosaic of the Virtues. You see: Guard, Health Potion."
+        );
+        assert_eq!(
+            maps.describe_at(Position::new(9, 9), &npcs, &items),
+            "You see nothing there."
+        );
     }
 
     #[test]
     fn test_find_nearby_npc() {
-        let mut maps = Maps::new(10, 10);
-        let npc1 = NPC::new("Test NPC", "You shall not pass!", (5, 5), 50, 10);
-        let npc2 = NPC::new("Test NPC", "You shall not pass!", (7, 7), 50, 10);
+        let maps = Maps::new(10, 10);
+        let npc1 = NPC::new(
+            "Test NPC",
+            "You shall not pass!",
+            Position::new(5, 5),
+            50,
+            10,
+        );
+        let npc2 = NPC::new(
+            "Test NPC",
+            "You shall not pass!",
+            Position::new(7, 7),
+            50,
+            10,
+        );
 
-        let npcs = vec![npc1, npc2];
+        let npcs = EntityList::from(vec![npc1, npc2]);
 
-        assert_eq!(maps.find_nearby_npc((5, 5), &npcs), Some(0));
-        assert_ne!(maps.find_nearby_npc((3, 3), &npcs), Some(1));
-        assert_eq!(maps.find_nearby_npc((1, 1), &npcs), None);
+        assert_eq!(maps.find_nearby_npc(Position::new(5, 5), &npcs), Some(0));
+        assert_ne!(maps.find_nearby_npc(Position::new(3, 3), &npcs), Some(1));
+        assert_eq!(maps.find_nearby_npc(Position::new(1, 1), &npcs), None);
     }
 
     #[test]
     fn test_update_player_position() {
         let mut maps = Maps::new(10, 10);
-        maps.update_player_position(5, 5);
-        assert_eq!(maps.grid[5][5], TileContent::Player);
+        maps.update_player_position(Position::new(5, 5));
+        assert!(!maps.is_empty(Position::new(5, 5)));
+
+        maps.update_player_position(Position::new(7, 7));
+        assert!(!maps.is_empty(Position::new(7, 7)));
+        assert!(maps.is_empty(Position::new(5, 5)));
+        assert_eq!(maps.terrain(Position::new(7, 7)), Some(Terrain::Floor));
+    }
 
-        maps.update_player_position(7, 7);
-        assert_eq!(maps.grid[7][7], TileContent::Player);
+    #[test]
+    fn test_update_player_position_out_of_bounds() {
+        let mut maps = Maps::new(10, 10);
+        maps.update_player_position(Position::new(3, 3));
+        maps.update_player_position(Position::new(10, 3));
+        assert!(!maps.is_empty(Position::new(3, 3)));
     }
 
     #[test]
     fn test_draw() {
         let mut maps = Maps::new(10, 10);
-        let mut player = Player::new("Test Player");
-        let npc = NPC::new("Test NPC", "You shall not pass!", (5, 5), 50, 10);
-        let item = Item::new("Test Item", (6, 6));
+        maps.set_tile(Position::new(0, 9), Terrain::Wall);
+        maps.set_tile(Position::new(1, 9), Terrain::Water);
+        let player = Player::new("Test Player");
+        let npc = NPC::new(
+            "Test NPC",
+            "You shall not pass!",
+            Position::new(5, 5),
+            50,
+            10,
+        );
+        let item = Item::new("Test Item", Position::new(6, 6));
 
-        let npcs = vec![npc];
-        let items = vec![item];
+        let npcs = EntityList::from(vec![npc]);
+        let items = EntityList::from(vec![item]);
 
-        let map_string = maps.draw(player.position, &npcs, &items);
+        let map_string = maps.draw(
+            &maps.full_view(),
+            &FieldOfView::all(&maps.full_view()),
+            &HashSet::new(),
+            player.position,
+            &npcs,
+            &items,
+        );
         assert!(map_string.contains("."));
         assert!(map_string.contains("@"));
         assert!(map_string.contains("N"));
         assert!(map_string.contains("I"));
+        assert!(map_string.contains("#"));
+        assert!(map_string.contains("~"));
+
+        let mut orc = NPC::new("Orc", "Grrr!", Position::new(3, 3), 40, 8);
+        orc.glyph = 'o';
+        orc.notoriety = Notoriety::Enemy;
+        orc.colour = Some(Colour::Green);
+        let npcs = EntityList::from(vec![orc]);
+        let cells = maps.draw_cells(
+            &maps.full_view(),
+            &FieldOfView::all(&maps.full_view()),
+            &HashSet::new(),
+            player.position,
+            &npcs,
+            &items,
+        );
+        assert_eq!(
+            (cells[3][3].glyph, cells[3][3].notoriety),
+            ('o', Some(Notoriety::Enemy))
+        );
+        assert_eq!(cells[3][3].colour, Some(Colour::Green));
     }
 
     #[test]
-    fn test_resize_to_frame() {
-        let mut maps = Maps::new(20, 20);
-        maps.resize_to_frame(10, 15);
-        assert_eq!(maps.width, 10);
-        assert_eq!(maps.height, 15);
-        assert_eq!(maps.grid.len(), 15);
-        assert_eq!(maps.grid[0].len(), 10);
+    fn test_draw_viewport() {
+        let maps = Maps::new(100, 50);
+        let npcs = EntityList::from(vec![NPC::new(
+            "Far NPC",
+            "Hello",
+            Position::new(90, 40),
+            50,
+            10,
+        )]);
+        let items = EntityList::from(vec![Item::new("Near Item", Position::new(52, 26))]);
+
+        let camera = Camera::centered_on(Position::new(50, 25), 10, 4, maps.width, maps.height);
+        let map_string = maps.draw(
+            &camera,
+            &FieldOfView::all(&camera),
+            &HashSet::new(),
+            Position::new(50, 25),
+            &npcs,
+            &items,
+        );
+        let lines: Vec<&str> = map_string.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines.iter().all(|line| line.chars().count() == 10));
+        assert_eq!(lines[2].chars().nth(5), Some('@'));
+        assert_eq!(lines[3].chars().nth(7), Some('I'));
+        assert!(!map_string.contains("N"));
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_draw_hides_what_is_not_visible() {
+        let mut maps = Maps::new(10, 3);
+        maps.set_tile(Position::new(4, 0), Terrain::Wall);
+        maps.set_tile(Position::new(4, 1), Terrain::Wall);
+        maps.set_tile(Position::new(4, 2), Terrain::Wall);
+        let npcs = EntityList::from(vec![NPC::new(
+            "Hidden NPC",
+            "Boo",
+            Position::new(7, 1),
+            50,
+            10,
+        )]);
+        let items = EntityList::from(vec![Item::new("Seen Item", Position::new(2, 1))]);
+
+        let fov = maps.field_of_view(Position::new(1, 1), 20);
+        let map_string = maps.draw(
+            &maps.full_view(),
+            &fov,
+            &HashSet::new(),
+            Position::new(1, 1),
+            &npcs,
+            &items,
+        );
+        let lines: Vec<&str> = map_string.lines().collect();
+        assert_eq!(lines[1], ".@I.#     ");
+        assert!(!map_string.contains("N"));
+    }
+
+    #[test]
+    fn test_draw_remembers_explored_tiles() {
+        let mut maps = Maps::new(10, 1);
+        maps.set_tile(Position::new(4, 0), Terrain::Door(DoorState::Open));
+        maps.set_tile(Position::new(7, 0), Terrain::Water);
+        let npcs = EntityList::from(vec![NPC::new(
+            "Remembered NPC",
+            "Boo",
+            Position::new(6, 0),
+            50,
+            10,
+        )]);
+        let explored: HashSet<Position> = (0..10).map(|x| Position::new(x, 0)).collect();
+
+        let fov = maps.field_of_view(Position::new(1, 0), 2);
+        let cells = maps.draw_cells(
+            &maps.full_view(),
+            &fov,
+            &explored,
+            Position::new(1, 0),
+            &npcs,
+            &EntityList::new(),
+        );
+        assert_eq!(
+            cells[0][1],
+            DrawnCell {
+                glyph: '@',
+                visibility: CellVisibility::Visible,
+                notoriety: None,
+                colour: None
+            }
+        );
+        assert_eq!(cells[0][4].visibility, CellVisibility::Remembered);
+        assert_eq!(
+            cells[0][6],
+            DrawnCell {
+                glyph: '.',
+                visibility: CellVisibility::Remembered,
+                notoriety: None,
+                colour: None
+            }
+        );
+
+        let map_string = maps.draw(
+            &maps.full_view(),
+            &fov,
+            &HashSet::new(),
+            Position::new(1, 0),
+            &npcs,
+            &EntityList::new(),
+        );
+        assert_eq!(map_string, ".@..      \n");
+        let map_string = maps.draw(
+            &maps.full_view(),
+            &fov,
+            &explored,
+            Position::new(1, 0),
+            &npcs,
+            &EntityList::new(),
+        );
+        assert_eq!(map_string, ".@..'..~..\n");
+    }
+
+    #[test]
+    fn test_draw_darkness() {
+        let mut maps = Maps::new(20, 1);
+        maps.set_tile(Position::new(15, 0), Terrain::LampPost);
+        let npcs = EntityList::from(vec![
+            NPC::new("Lurker", "Boo", Position::new(8, 0), 50, 10),
+            NPC::new("Watchman", "All is well", Position::new(13, 0), 50, 10),
+        ]);
+
+        let fov = maps.lit_field_of_view(Position::new(1, 0), &SightConditions::at_light(0, false));
+        let cells = maps.draw_cells(
+            &maps.full_view(),
+            &fov,
+            &HashSet::new(),
+            Position::new(1, 0),
+            &npcs,
+            &EntityList::new(),
+        );
+        assert_eq!(
+            cells[0][8],
+            DrawnCell {
+                glyph: '.',
+                visibility: CellVisibility::Dark,
+                notoriety: None,
+                colour: None
+            }
+        );
+        assert_eq!(cells[0][13].notoriety, Some(Notoriety::Innocent));
+        assert_eq!((cells[0][13].glyph, cells[0][13].colour), ('N', None));
+        let map_string = maps.draw(
+            &maps.full_view(),
+            &fov,
+            &HashSet::new(),
+            Position::new(1, 0),
+            &npcs,
+            &EntityList::new(),
+        );
+        assert_eq!(
+            map_string, ".@...........N      \n",
+            "only the lamp-lit NPC shows at night"
+        );
+
+        let fov = maps.lit_field_of_view(Position::new(1, 0), &SightConditions::daylight());
+        let map_string = maps.draw(
+            &maps.full_view(),
+            &fov,
+            &HashSet::new(),
+            Position::new(1, 0),
+            &npcs,
+            &EntityList::new(),
+        );
+        assert_eq!(map_string, ".@......N....N      \n");
+    }
+
+    #[test]
+    fn test_queries_cross_chunk_borders() {
+        let size = CHUNK_SIZE * 2;
+        let mut maps = Maps::new(size, size);
+        assert_eq!(maps.loaded_chunks(), 4);
+        maps.set_tile(Position::new(CHUNK_SIZE, CHUNK_SIZE - 1), Terrain::Wall);
+        assert!(!maps.is_empty(Position::new(CHUNK_SIZE, CHUNK_SIZE - 1)));
+        assert!(maps.is_empty(Position::new(CHUNK_SIZE - 1, CHUNK_SIZE - 1)));
+
+        let path = maps.find_path(
+            Position::new(CHUNK_SIZE - 2, CHUNK_SIZE - 1),
+            Position::new(CHUNK_SIZE + 2, CHUNK_SIZE - 1),
+            Movement::FourWay,
+        );
+        assert_eq!(path.map(|path| path.len()), Some(6));
+
+        let camera = Camera::new(CHUNK_SIZE - 2, CHUNK_SIZE - 1, 4, 1);
+        let drawn = maps.draw(
+            &camera,
+            &FieldOfView::all(&camera),
+            &HashSet::new(),
+            Position::new(0, 0),
+            &EntityList::new(),
+            &EntityList::new(),
+        );
+        assert_eq!(drawn, "..#.\n");
+    }
+
+    #[test]
+    fn test_stream_around() {
+        let size = CHUNK_SIZE * 10;
+        let source = GeneratedChunks::new(|x, y| {
+            if (x + y) % 2 == 0 {
+                Terrain::Grass
+            } else {
+                Terrain::Road
+            }
+        });
+        let mut maps = Maps::streamed(size, size, Box::new(source));
+        assert_eq!(maps.loaded_chunks(), 0);
+        assert!(!maps.is_walkable(Position::new(0, 0)));
+
+        maps.stream_around(Position::new(0, 0), 1).unwrap();
+        assert_eq!(maps.loaded_chunks(), 4);
+        assert_eq!(maps.terrain(Position::new(1, 0)), Some(Terrain::Road));
+        assert!(!maps.is_loaded(Position::new(CHUNK_SIZE * 2, 0)));
+
+        maps.stream_around(Position::new(CHUNK_SIZE * 5, CHUNK_SIZE * 5), 1)
+            .unwrap();
+        assert_eq!(maps.loaded_chunks(), 9);
+        assert!(!maps.is_loaded(Position::new(0, 0)));
+        assert_eq!(
+            maps.terrain(Position::new(CHUNK_SIZE * 5, CHUNK_SIZE * 5)),
+            Some(Terrain::Grass)
+        );
+
+        let fov = maps.field_of_view(Position::new(CHUNK_SIZE * 5, CHUNK_SIZE * 5), 3);
+        let cells = maps.draw_cells(
+            &maps.full_view(),
+            &fov,
+            &HashSet::new(),
+            Position::new(0, 0),
+            &EntityList::new(),
+            &EntityList::new(),
+        );
+        assert_eq!(cells[0][0].visibility, CellVisibility::Unknown);
+    }
+
+    #[test]
+    fn test_streamed_changes_survive_eviction() {
+        let folder = std::env::temp_dir().join(format!("uo_stream_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&folder);
+        let source = DiskChunks::new(&folder, GeneratedChunks::new(|_, _| Terrain::Grass));
+        let mut maps = Maps::streamed(CHUNK_SIZE * 8, CHUNK_SIZE * 8, Box::new(source));
+
+        maps.stream_around(Position::new(0, 0), 0).unwrap();
+        maps.set_tile(Position::new(3, 3), Terrain::Wall);
+        maps.set_description(Position::new(4, 4), "A mossy stone.");
+        maps.stream_around(Position::new(CHUNK_SIZE * 6, 0), 0)
+            .unwrap();
+        assert_eq!(maps.terrain(Position::new(3, 3)), None);
+
+        maps.stream_around(Position::new(0, 0), 0).unwrap();
+        assert_eq!(maps.terrain(Position::new(3, 3)), Some(Terrain::Wall));
+        assert_eq!(
+            maps.get_tile(Position::new(4, 4))
+                .map(|tile| tile.describe()),
+            Some("A mossy stone.")
+        );
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_failed_unload_keeps_the_chunk() {
+        struct ReadOnly;
+        impl ChunkSource for ReadOnly {
+            fn load(&mut self, _chunk: (usize, usize)) -> io::Result<Chunk> {
+                Ok(Chunk::filled(Terrain::Grass))
+            }
+            fn unload(&mut self, _chunk: (usize, usize), _data: &Chunk) -> io::Result<()> {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, "read only"))
+            }
+        }
+        let mut maps = Maps::streamed(CHUNK_SIZE * 8, CHUNK_SIZE * 8, Box::new(ReadOnly));
+        maps.stream_around(Position::new(0, 0), 0).unwrap();
+        maps.set_tile(Position::new(3, 3), Terrain::Wall);
+        assert!(maps
+            .stream_around(Position::new(CHUNK_SIZE * 6, 0), 0)
+            .is_err());
+        assert_eq!(maps.terrain(Position::new(3, 3)), Some(Terrain::Wall));
+        assert!(maps.unload_all().is_err());
+        assert_eq!(maps.terrain(Position::new(3, 3)), Some(Terrain::Wall));
+    }
+}
@@ -1,2 +1,16 @@
+pub mod camera;
+pub mod chunk;
+pub mod fov;
+pub mod light;
+pub mod map_file;
+#[allow(clippy::module_inception)] // `maps::maps` holds the `Maps` grid
+pub mod maps;
+pub mod occupancy;
+pub mod pathfinding;
+pub mod portal;
+pub mod position;
+pub mod region;
+pub mod spatial;
+pub mod terrain;
 pub mod tile;
-pub mod maps;
\ No newline at end of file
+pub mod tiled;
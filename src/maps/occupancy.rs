@@ -0,0 +1,142 @@
+//! Who may stand where. Terrain, the player and NPCs each take a whole tile;
+//! items never block, so any number of them can stack on a tile and walkers
+//! can step onto them. The player and NPCs both move through these rules.
+
+use crate::entities::npc::NPC;
+use crate::maps::maps::Maps;
+use crate::maps::position::{Direction, Position};
+use crate::maps::spatial::EntityList;
+
+/// What stopped a step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Blocker {
+    /// The step would leave the map.
+    Edge,
+    Terrain,
+    Player,
+    /// The NPC at this index in the map's list.
+    Npc(usize),
+}
+
+/// Result of trying to step onto a neighbouring tile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Moved(Position),
+    /// The walker traded places with the NPC at this index.
+    Swapped {
+        npc: usize,
+        position: Position,
+    },
+    Blocked(Blocker),
+}
+
+impl Maps {
+    /// Why `position` cannot be entered, or `None` when it is free.
+    pub fn blocker_at(&self, position: Position, npcs: &EntityList<NPC>) -> Option<Blocker> {
+        if !self.is_walkable(position) {
+            return Some(Blocker::Terrain);
+        }
+        if !self.is_empty(position) {
+            return Some(Blocker::Player);
+        }
+        npcs.at(position).next().map(Blocker::Npc)
+    }
+
+    /// The tile a walker standing on `from` would reach in `direction`,
+    /// checked against terrain and occupants.
+    pub fn try_step(
+        &self,
+        from: Position,
+        direction: Direction,
+        npcs: &EntityList<NPC>,
+    ) -> Result<Position, Blocker> {
+        let next = self.neighbour(from, direction).ok_or(Blocker::Edge)?;
+        match self.blocker_at(next, npcs) {
+            Some(blocker) => Err(blocker),
+            None => Ok(next),
+        }
+    }
+}
+
+/// Moves the NPC at `index` one tile in `direction`. NPCs never walk into
+/// the player or each other.
+pub fn step_npc(
+    map: &Maps,
+    npcs: &mut EntityList<NPC>,
+    index: usize,
+    direction: Direction,
+) -> Step {
+    let Some(from) = npcs.get(index).map(|npc| npc.position) else {
+        return Step::Blocked(Blocker::Edge);
+    };
+    match map.try_step(from, direction, npcs) {
+        Ok(next) => {
+            if let Some(mut npc) = npcs.get_mut(index) {
+                npc.position = next;
+            }
+            Step::Moved(next)
+        }
+        Err(blocker) => Step::Blocked(blocker),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maps::terrain::Terrain;
+
+    fn npcs() -> EntityList<NPC> {
+        EntityList::from(vec![
+            NPC::new("Guard", "Halt!", Position::new(2, 1), 50, 10),
+            NPC::new("Orc", "Grr", Position::new(3, 1), 40, 8),
+        ])
+    }
+
+    #[test]
+    fn test_blocker_at() {
+        let mut map = Maps::new(5, 3);
+        map.set_tile(Position::new(0, 0), Terrain::Wall);
+        map.update_player_position(Position::new(1, 1));
+        let npcs = npcs();
+
+        assert_eq!(
+            map.blocker_at(Position::new(0, 0), &npcs),
+            Some(Blocker::Terrain)
+        );
+        assert_eq!(
+            map.blocker_at(Position::new(1, 1), &npcs),
+            Some(Blocker::Player)
+        );
+        assert_eq!(
+            map.blocker_at(Position::new(3, 1), &npcs),
+            Some(Blocker::Npc(1))
+        );
+        assert_eq!(map.blocker_at(Position::new(4, 2), &npcs), None);
+        assert_eq!(
+            map.try_step(Position::new(4, 2), Direction::East, &npcs),
+            Err(Blocker::Edge)
+        );
+    }
+
+    #[test]
+    fn test_npcs_obey_occupancy() {
+        let mut map = Maps::new(5, 3);
+        map.update_player_position(Position::new(1, 1));
+        let mut npcs = npcs();
+
+        assert_eq!(
+            step_npc(&map, &mut npcs, 0, Direction::West),
+            Step::Blocked(Blocker::Player)
+        );
+        assert_eq!(
+            step_npc(&map, &mut npcs, 0, Direction::East),
+            Step::Blocked(Blocker::Npc(1))
+        );
+        assert_eq!(
+            step_npc(&map, &mut npcs, 0, Direction::South),
+            Step::Moved(Position::new(2, 2))
+        );
+        assert!(npcs.any_at(Position::new(2, 2)));
+        assert!(!npcs.any_at(Position::new(2, 1)));
+    }
+}
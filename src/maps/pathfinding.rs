@@ -0,0 +1,376 @@
+//! Path finding on `Maps`: A* between two positions and Dijkstra flow fields
+//! for many walkers heading to the same target. Both use terrain movement
+//! costs; diagonal steps cost about 1.4 times a straight one and may not cut
+//! around blocked corners.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::maps::chunk::{chunk_of, CHUNK_SIZE};
+use crate::maps::maps::Maps;
+use crate::maps::position::{Direction, Position};
+
+const STRAIGHT: u32 = 10;
+const DIAGONAL: u32 = 14;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Movement {
+    /// Up, down, left and right only.
+    FourWay,
+    /// Diagonal steps are allowed too.
+    // NPCs only walk the cardinal directions so far.
+    #[allow(dead_code)]
+    EightWay,
+}
+
+impl Movement {
+    fn directions(&self) -> &'static [Direction] {
+        match self {
+            Movement::FourWay => &Direction::CARDINAL,
+            Movement::EightWay => &Direction::ALL,
+        }
+    }
+}
+
+/// Distance to a target from every position that can reach it. Costs are
+/// kept per chunk and only for chunks the search reached, so a field on a
+/// large streamed map stays as small as the area around the target.
+pub struct FlowField {
+    movement: Movement,
+    costs: HashMap<(usize, usize), Vec<u32>>,
+}
+
+impl FlowField {
+    /// Total cost to reach the target, `None` when it cannot be reached.
+    pub fn cost_at(&self, x: usize, y: usize) -> Option<u32> {
+        match self.cost(Position::new(x, y)) {
+            u32::MAX => None,
+            cost => Some(cost),
+        }
+    }
+
+    fn cost(&self, position: Position) -> u32 {
+        let (chunk, (lx, ly)) = chunk_of(position.x, position.y);
+        self.costs
+            .get(&chunk)
+            .map_or(u32::MAX, |costs| costs[ly * CHUNK_SIZE + lx])
+    }
+
+    fn set_cost(&mut self, position: Position, cost: u32) {
+        let (chunk, (lx, ly)) = chunk_of(position.x, position.y);
+        self.costs
+            .entry(chunk)
+            .or_insert_with(|| vec![u32::MAX; CHUNK_SIZE * CHUNK_SIZE])[ly * CHUNK_SIZE + lx] =
+            cost;
+    }
+
+    /// The neighbour to move to from `from` to get closer to the target.
+    pub fn next_step(&self, from: Position) -> Option<Position> {
+        let current = self.cost_at(from.x, from.y)?;
+        if current == 0 {
+            return None;
+        }
+        self.movement
+            .directions()
+            .iter()
+            .filter_map(|&direction| from.step(direction))
+            .filter_map(|next| self.cost_at(next.x, next.y).map(|cost| (cost, next)))
+            .filter(|(cost, _)| *cost < current)
+            .min_by_key(|(cost, _)| *cost)
+            .map(|(_, position)| position)
+    }
+}
+
+impl Maps {
+    /// Cost of stepping from `from` onto the neighbouring `to`, or `None` if
+    /// the step is not allowed.
+    fn step_cost(&self, from: Position, to: Position) -> Option<u32> {
+        let terrain = self.terrain(to)?;
+        if !terrain.is_walkable() {
+            return None;
+        }
+        let diagonal = from.x != to.x && from.y != to.y;
+        let corners = [Position::new(to.x, from.y), Position::new(from.x, to.y)];
+        if diagonal && !corners.iter().all(|&corner| self.is_walkable(corner)) {
+            return None;
+        }
+        let base = if diagonal { DIAGONAL } else { STRAIGHT };
+        Some(base * terrain.movement_cost())
+    }
+
+    /// Cheapest path from `from` to `to`, excluding `from` and including `to`.
+    /// Returns `None` when the goal cannot be reached.
+    pub fn find_path(
+        &self,
+        from: Position,
+        to: Position,
+        movement: Movement,
+    ) -> Option<Vec<Position>> {
+        if !self.contains(from) || !self.is_walkable(to) {
+            return None;
+        }
+        if from == to {
+            return Some(Vec::new());
+        }
+
+        let heuristic = |position: Position| {
+            let dx = position.x.abs_diff(to.x) as u32;
+            let dy = position.y.abs_diff(to.y) as u32;
+            match movement {
+                Movement::FourWay => STRAIGHT * (dx + dy),
+                Movement::EightWay => STRAIGHT * dx.max(dy) + (DIAGONAL - STRAIGHT) * dx.min(dy),
+            }
+        };
+
+        // Only the visited nodes are stored: on streamed maps the full grid can be huge.
+        let mut costs: HashMap<Position, u32> = HashMap::new();
+        let mut previous: HashMap<Position, Position> = HashMap::new();
+        let mut open = BinaryHeap::new();
+        costs.insert(from, 0);
+        open.push(Reverse((heuristic(from), 0u32, from)));
+
+        while let Some(Reverse((_, cost, current))) = open.pop() {
+            if current == to {
+                let mut path = vec![to];
+                let mut step = previous.get(&to).copied();
+                while let Some(position) = step {
+                    if position == from {
+                        break;
+                    }
+                    path.push(position);
+                    step = previous.get(&position).copied();
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if cost > costs[&current] {
+                continue;
+            }
+            for &direction in movement.directions() {
+                let Some(next) = current.step(direction) else {
+                    continue;
+                };
+                let Some(step) = self.step_cost(current, next) else {
+                    continue;
+                };
+                let next_cost = cost + step;
+                if next_cost < costs.get(&next).copied().unwrap_or(u32::MAX) {
+                    costs.insert(next, next_cost);
+                    previous.insert(next, current);
+                    open.push(Reverse((next_cost + heuristic(next), next_cost, next)));
+                }
+            }
+        }
+        None
+    }
+
+    /// Dijkstra map towards `target`, shared by every NPC chasing it.
+    pub fn flow_field(&self, target: Position, movement: Movement) -> FlowField {
+        let mut field = FlowField {
+            movement,
+            costs: HashMap::new(),
+        };
+        let mut open = BinaryHeap::new();
+        if self.contains(target) {
+            field.set_cost(target, 0);
+            open.push(Reverse((0u32, target)));
+        }
+
+        while let Some(Reverse((cost, current))) = open.pop() {
+            if cost > field.cost(current) {
+                continue;
+            }
+            for &direction in movement.directions() {
+                let Some(next) = current.step(direction) else {
+                    continue;
+                };
+                // Walking from `next` into `current` costs what entering `current` costs.
+                if !self.is_walkable(next) {
+                    continue;
+                }
+                let Some(step) = self.step_cost(next, current) else {
+                    continue;
+                };
+                let next_cost = cost + step;
+                if next_cost < field.cost(next) {
+                    field.set_cost(next, next_cost);
+                    open.push(Reverse((next_cost, next)));
+                }
+            }
+        }
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maps::terrain::Terrain;
+
+    fn wall_with_gap() -> Maps {
+        let mut map = Maps::new(10, 10);
+        for y in 0..10 {
+            if y != 8 {
+                map.set_tile(Position::new(5, y), Terrain::Wall);
+            }
+        }
+        map
+    }
+
+    fn assert_connected(path: &[Position], from: Position, movement: Movement) {
+        let mut previous = from;
+        for &step in path {
+            let dx = previous.x.abs_diff(step.x);
+            let dy = previous.y.abs_diff(step.y);
+            match movement {
+                Movement::FourWay => assert_eq!(dx + dy, 1),
+                Movement::EightWay => assert!(dx <= 1 && dy <= 1 && dx + dy > 0),
+            }
+            previous = step;
+        }
+    }
+
+    #[test]
+    fn test_straight_path() {
+        let map = Maps::new(10, 10);
+        let path = map
+            .find_path(Position::new(1, 1), Position::new(4, 1), Movement::FourWay)
+            .unwrap();
+        assert_eq!(
+            path,
+            vec![
+                Position::new(2, 1),
+                Position::new(3, 1),
+                Position::new(4, 1)
+            ]
+        );
+        assert_eq!(
+            map.find_path(Position::new(1, 1), Position::new(1, 1), Movement::FourWay),
+            Some(Vec::new())
+        );
+    }
+
+    #[test]
+    fn test_diagonal_path() {
+        let map = Maps::new(10, 10);
+        let path = map
+            .find_path(Position::new(0, 0), Position::new(4, 4), Movement::EightWay)
+            .unwrap();
+        assert_eq!(path.len(), 4);
+        assert_connected(&path, Position::new(0, 0), Movement::EightWay);
+        assert_eq!(
+            map.find_path(Position::new(0, 0), Position::new(4, 4), Movement::FourWay)
+                .unwrap()
+                .len(),
+            8
+        );
+    }
+
+    #[test]
+    fn test_path_around_walls() {
+        let map = wall_with_gap();
+        for movement in [Movement::FourWay, Movement::EightWay] {
+            let path = map
+                .find_path(Position::new(2, 2), Position::new(8, 2), movement)
+                .unwrap();
+            assert!(path.contains(&Position::new(5, 8)));
+            assert_eq!(path.last(), Some(&Position::new(8, 2)));
+            assert!(path.iter().all(|&step| map.is_walkable(step)));
+            assert_connected(&path, Position::new(2, 2), movement);
+        }
+    }
+
+    #[test]
+    fn test_no_corner_cutting() {
+        let mut map = Maps::new(3, 3);
+        map.set_tile(Position::new(1, 0), Terrain::Wall);
+        let path = map
+            .find_path(Position::new(0, 0), Position::new(2, 1), Movement::EightWay)
+            .unwrap();
+        assert_eq!(path[0], Position::new(0, 1));
+    }
+
+    #[test]
+    fn test_terrain_costs() {
+        let mut map = Maps::new(7, 3);
+        for x in 1..6 {
+            map.set_tile(Position::new(x, 1), Terrain::Water);
+        }
+        let path = map
+            .find_path(Position::new(0, 1), Position::new(6, 1), Movement::FourWay)
+            .unwrap();
+        assert!(path
+            .iter()
+            .all(|&step| map.terrain(step) != Some(Terrain::Water)));
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let mut map = wall_with_gap();
+        map.set_tile(Position::new(5, 8), Terrain::Wall);
+        assert_eq!(
+            map.find_path(Position::new(2, 2), Position::new(8, 2), Movement::EightWay),
+            None
+        );
+        assert_eq!(
+            map.find_path(Position::new(2, 2), Position::new(5, 5), Movement::EightWay),
+            None
+        );
+        assert_eq!(
+            map.find_path(
+                Position::new(2, 2),
+                Position::new(20, 2),
+                Movement::EightWay
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_flow_field() {
+        let map = wall_with_gap();
+        let target = Position::new(8, 2);
+        let field = map.flow_field(target, Movement::EightWay);
+        assert_eq!(field.cost_at(8, 2), Some(0));
+        assert_eq!(field.cost_at(5, 5), None);
+        assert_eq!(field.next_step(Position::new(8, 2)), None);
+
+        for start in [(0, 0), (2, 2), (4, 9), (9, 9)].map(Position::from) {
+            let mut position = start;
+            let mut steps = 0;
+            while let Some(next) = field.next_step(position) {
+                assert!(field.cost_at(next.x, next.y) < field.cost_at(position.x, position.y));
+                position = next;
+                steps += 1;
+                assert!(steps < 100);
+            }
+            assert_eq!(position, target);
+        }
+    }
+
+    #[test]
+    fn test_flow_field_matches_a_star() {
+        let map = wall_with_gap();
+        let field = map.flow_field(Position::new(8, 2), Movement::FourWay);
+        let path = map
+            .find_path(Position::new(2, 2), Position::new(8, 2), Movement::FourWay)
+            .unwrap();
+        let cost: u32 = path.len() as u32 * STRAIGHT;
+        assert_eq!(field.cost_at(2, 2), Some(cost));
+    }
+
+    #[test]
+    fn test_flow_field_stores_reached_chunks_only() {
+        let mut map = Maps::new(CHUNK_SIZE * 4, CHUNK_SIZE);
+        for y in 0..CHUNK_SIZE {
+            map.set_tile(Position::new(CHUNK_SIZE + 1, y), Terrain::Wall);
+        }
+        let field = map.flow_field(Position::new(1, 1), Movement::FourWay);
+        assert_eq!(field.costs.len(), 2);
+        assert_eq!(
+            field.cost_at(CHUNK_SIZE, 0),
+            Some(STRAIGHT * (CHUNK_SIZE as u32 - 1 + 1))
+        );
+        assert_eq!(field.cost_at(CHUNK_SIZE * 3, 0), None);
+    }
+}
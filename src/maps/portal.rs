@@ -0,0 +1,19 @@
+use crate::maps::position::Position;
+
+/// A tile that sends whoever steps on it to another map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Portal {
+    pub position: Position,
+    pub target_map: String,
+    pub target_position: Position,
+}
+
+impl Portal {
+    pub fn new(position: Position, target_map: &str, target_position: Position) -> Self {
+        Self {
+            position,
+            target_map: target_map.to_string(),
+            target_position,
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Map positions. Coordinates are unsigned and every move goes through
+//! checked arithmetic, so stepping off the top or left edge yields `None`
+//! instead of wrapping or panicking; `Maps::neighbour` also checks the far
+//! edges.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Position {
+    pub x: usize,
+    pub y: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    North,
+    South,
+    West,
+    East,
+    NorthWest,
+    NorthEast,
+    SouthWest,
+    SouthEast,
+}
+
+impl Direction {
+    pub const CARDINAL: [Direction; 4] = [
+        Direction::North,
+        Direction::South,
+        Direction::West,
+        Direction::East,
+    ];
+    pub const ALL: [Direction; 8] = [
+        Direction::North,
+        Direction::South,
+        Direction::West,
+        Direction::East,
+        Direction::NorthWest,
+        Direction::NorthEast,
+        Direction::SouthWest,
+        Direction::SouthEast,
+    ];
+
+    /// Change in x and y when stepping in this direction.
+    pub fn delta(self) -> (isize, isize) {
+        match self {
+            Direction::North => (0, -1),
+            Direction::South => (0, 1),
+            Direction::West => (-1, 0),
+            Direction::East => (1, 0),
+            Direction::NorthWest => (-1, -1),
+            Direction::NorthEast => (1, -1),
+            Direction::SouthWest => (-1, 1),
+            Direction::SouthEast => (1, 1),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::North => "north",
+            Direction::South => "south",
+            Direction::West => "west",
+            Direction::East => "east",
+            Direction::NorthWest => "north-west",
+            Direction::NorthEast => "north-east",
+            Direction::SouthWest => "south-west",
+            Direction::SouthEast => "south-east",
+        }
+    }
+
+    pub fn is_diagonal(self) -> bool {
+        let (dx, dy) = self.delta();
+        dx != 0 && dy != 0
+    }
+}
+
+impl Position {
+    pub const fn new(x: usize, y: usize) -> Self {
+        Self { x, y }
+    }
+
+    /// The position `dx`, `dy` tiles away, or `None` below zero or on overflow.
+    pub fn offset(self, dx: isize, dy: isize) -> Option<Position> {
+        Some(Position::new(
+            self.x.checked_add_signed(dx)?,
+            self.y.checked_add_signed(dy)?,
+        ))
+    }
+
+    pub fn step(self, direction: Direction) -> Option<Position> {
+        let (dx, dy) = direction.delta();
+        self.offset(dx, dy)
+    }
+
+    /// The direction leading to a neighbouring `other`, `None` if it is not
+    /// a neighbour.
+    pub fn direction_to(self, other: Position) -> Option<Direction> {
+        Direction::ALL
+            .into_iter()
+            .find(|&direction| self.step(direction) == Some(other))
+    }
+
+    /// Number of king moves between the two positions.
+    pub fn distance(self, other: Position) -> usize {
+        self.x.abs_diff(other.x).max(self.y.abs_diff(other.y))
+    }
+}
+
+impl From<(usize, usize)> for Position {
+    fn from((x, y): (usize, usize)) -> Self {
+        Self::new(x, y)
+    }
+}
+
+impl From<Position> for (usize, usize) {
+    fn from(position: Position) -> Self {
+        (position.x, position.y)
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.x, self.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset_is_checked() {
+        let origin = Position::new(0, 0);
+        assert_eq!(origin.offset(-1, 0), None);
+        assert_eq!(origin.step(Direction::North), None);
+        assert_eq!(origin.step(Direction::SouthEast), Some(Position::new(1, 1)));
+        assert_eq!(Position::new(usize::MAX, 0).step(Direction::East), None);
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(Position::new(2, 2).distance(Position::new(5, 3)), 3);
+        assert_eq!(Position::new(5, 3).distance(Position::new(5, 3)), 0);
+    }
+
+    #[test]
+    fn test_direction_to() {
+        let center = Position::new(3, 3);
+        assert_eq!(
+            center.direction_to(Position::new(3, 2)),
+            Some(Direction::North)
+        );
+        assert_eq!(
+            center.direction_to(Position::new(4, 4)),
+            Some(Direction::SouthEast)
+        );
+        assert_eq!(center.direction_to(Position::new(5, 3)), None);
+        assert_eq!(center.direction_to(center), None);
+    }
+
+    #[test]
+    fn test_conversions() {
+        let position = Position::from((3, 4));
+        assert_eq!(position, Position::new(3, 4));
+        assert_eq!(<(usize, usize)>::from(position), (3, 4));
+        assert_eq!(position.to_string(), "3,4");
+    }
+}
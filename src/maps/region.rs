@@ -0,0 +1,309 @@
+//! Named regions of a map (towns, dungeons, guard zones) and the rules that
+//! apply inside them. Regions may overlap; the one added last wins, so a
+//! town declared after the surrounding wilderness takes precedence.
+
+use std::fmt;
+
+use crate::entities::npc::NPC;
+use crate::entities::template::TemplateRegistry;
+use crate::maps::maps::Maps;
+use crate::maps::position::{Direction, Position};
+use crate::maps::spatial::EntityList;
+
+/// Template summoned guards are spawned from.
+const GUARD_TEMPLATE: &str = "guard";
+
+/// The tiles a region covers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Area {
+    /// Corners are inclusive.
+    Rect {
+        top_left: Position,
+        bottom_right: Position,
+    },
+    /// At least three corners, in drawing order. Tiles on the outline are
+    /// inside.
+    Polygon(Vec<Position>),
+}
+
+impl Area {
+    pub fn contains(&self, position: Position) -> bool {
+        match self {
+            Area::Rect {
+                top_left,
+                bottom_right,
+            } => {
+                (top_left.x..=bottom_right.x).contains(&position.x)
+                    && (top_left.y..=bottom_right.y).contains(&position.y)
+            }
+            Area::Polygon(corners) => polygon_contains(corners, position),
+        }
+    }
+
+    pub fn corners(&self) -> Vec<Position> {
+        match self {
+            Area::Rect {
+                top_left,
+                bottom_right,
+            } => vec![*top_left, *bottom_right],
+            Area::Polygon(corners) => corners.clone(),
+        }
+    }
+}
+
+/// Even-odd rule, plus the outline itself.
+fn polygon_contains(corners: &[Position], position: Position) -> bool {
+    let (px, py) = (position.x as f64, position.y as f64);
+    let mut inside = false;
+    for (index, a) in corners.iter().enumerate() {
+        let b = corners[(index + 1) % corners.len()];
+        let (ax, ay, bx, by) = (a.x as f64, a.y as f64, b.x as f64, b.y as f64);
+
+        let cross = (bx - ax) * (py - ay) - (by - ay) * (px - ax);
+        let within = px >= ax.min(bx) && px <= ax.max(bx) && py >= ay.min(by) && py <= ay.max(by);
+        if cross == 0.0 && within {
+            return true;
+        }
+        if (ay > py) != (by > py) && px < ax + (py - ay) * (bx - ax) / (by - ay) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RegionFlags {
+    /// Attacking innocents calls the guards.
+    pub guarded: bool,
+    /// Fighting is not possible at all.
+    pub no_combat: bool,
+    /// Magic gates do not work from or into the region.
+    pub no_teleport: bool,
+    /// Quitting here is safe.
+    pub safe_logout: bool,
+    /// Music or ambience to play while inside.
+    pub music: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub name: String,
+    pub area: Area,
+    pub flags: RegionFlags,
+}
+
+impl Region {
+    pub fn new(name: &str, area: Area, flags: RegionFlags) -> Self {
+        Self {
+            name: name.to_string(),
+            area,
+            flags,
+        }
+    }
+
+    pub fn contains(&self, position: Position) -> bool {
+        self.area.contains(position)
+    }
+}
+
+impl fmt::Display for RegionFlags {
+    /// `guarded, safe-logout, music:britain`, as written in map files.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut flags: Vec<String> = [
+            (self.guarded, "guarded"),
+            (self.no_combat, "no-combat"),
+            (self.no_teleport, "no-teleport"),
+            (self.safe_logout, "safe-logout"),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| name.to_string())
+        .collect();
+        if let Some(music) = &self.music {
+            flags.push(format!("music:{}", music));
+        }
+        write!(f, "{}", flags.join(", "))
+    }
+}
+
+impl RegionFlags {
+    /// Parses a comma-separated flag list; returns the unknown flag on error.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut flags = RegionFlags::default();
+        for flag in value
+            .split(',')
+            .map(str::trim)
+            .filter(|flag| !flag.is_empty())
+        {
+            match flag {
+                "guarded" => flags.guarded = true,
+                "no-combat" => flags.no_combat = true,
+                "no-teleport" => flags.no_teleport = true,
+                "safe-logout" => flags.safe_logout = true,
+                _ => match flag.strip_prefix("music:") {
+                    Some(tag) if !tag.trim().is_empty() => {
+                        flags.music = Some(tag.trim().to_string())
+                    }
+                    _ => return Err(flag.to_string()),
+                },
+            }
+        }
+        Ok(flags)
+    }
+}
+
+impl Maps {
+    /// The region covering `position`, if any.
+    pub fn region_at(&self, position: Position) -> Option<&Region> {
+        self.regions()
+            .iter()
+            .rev()
+            .find(|region| region.contains(position))
+    }
+
+    /// Message for moving from `from` to `to` across a region border.
+    pub fn region_change(&self, from: Position, to: Position) -> Option<String> {
+        let before = self.region_at(from).map(|region| region.name.as_str());
+        match self.region_at(to).map(|region| region.name.as_str()) {
+            after if after == before => None,
+            Some(name) => Some(format!("You have entered {}.", name)),
+            None => before.map(|name| format!("You have left {}.", name)),
+        }
+    }
+
+    /// Places a town guard from the `guard` template next to `near`, unless
+    /// one already stands there. Returns the guard's index, or `None` when
+    /// every neighbouring tile is taken or there is no such template.
+    pub fn summon_guard(
+        &self,
+        npcs: &mut EntityList<NPC>,
+        templates: &TemplateRegistry,
+        near: Position,
+    ) -> Option<usize> {
+        let is_guard = |npc: &NPC| npc.template.as_deref() == Some(GUARD_TEMPLATE);
+        if let Some(guard) = npcs
+            .in_range(near, 1)
+            .into_iter()
+            .find(|&index| is_guard(&npcs[index]))
+        {
+            return Some(guard);
+        }
+        let spot = Direction::ALL
+            .iter()
+            .filter_map(|&direction| self.neighbour(near, direction))
+            .find(|&spot| self.is_empty(spot) && !npcs.any_at(spot))?;
+        npcs.push(templates.spawn(GUARD_TEMPLATE, spot).ok()?);
+        Some(npcs.len() - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn britain() -> Region {
+        let area = Area::Rect {
+            top_left: Position::new(2, 2),
+            bottom_right: Position::new(5, 4),
+        };
+        Region::new(
+            "Britain",
+            area,
+            RegionFlags {
+                guarded: true,
+                ..RegionFlags::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_rect_and_polygon() {
+        let region = britain();
+        assert!(region.contains(Position::new(2, 2)));
+        assert!(region.contains(Position::new(5, 4)));
+        assert!(!region.contains(Position::new(6, 4)));
+
+        let triangle = Area::Polygon(vec![
+            Position::new(0, 0),
+            Position::new(8, 0),
+            Position::new(0, 8),
+        ]);
+        assert!(triangle.contains(Position::new(2, 2)));
+        assert!(
+            triangle.contains(Position::new(4, 4)),
+            "the outline is inside"
+        );
+        assert!(!triangle.contains(Position::new(6, 6)));
+    }
+
+    #[test]
+    fn test_flags_round_trip() {
+        let flags = RegionFlags::parse("guarded, safe-logout, music:britain").unwrap();
+        assert!(flags.guarded && flags.safe_logout && !flags.no_combat);
+        assert_eq!(flags.music.as_deref(), Some("britain"));
+        assert_eq!(flags.to_string(), "guarded, safe-logout, music:britain");
+        assert_eq!(RegionFlags::parse("peaceful"), Err("peaceful".to_string()));
+    }
+
+    #[test]
+    fn test_entering_and_leaving() {
+        let mut map = Maps::new(20, 20);
+        map.add_region(britain());
+        let inn = Area::Rect {
+            top_left: Position::new(4, 3),
+            bottom_right: Position::new(4, 3),
+        };
+        map.add_region(Region::new("Blue Boar Inn", inn, RegionFlags::default()));
+
+        assert_eq!(map.region_at(Position::new(3, 3)).unwrap().name, "Britain");
+        assert_eq!(
+            map.region_at(Position::new(4, 3)).unwrap().name,
+            "Blue Boar Inn"
+        );
+        assert_eq!(
+            map.region_change(Position::new(1, 3), Position::new(2, 3)),
+            Some("You have entered Britain.".to_string())
+        );
+        assert_eq!(
+            map.region_change(Position::new(2, 3), Position::new(3, 3)),
+            None
+        );
+        assert_eq!(
+            map.region_change(Position::new(2, 2), Position::new(1, 2)),
+            Some("You have left Britain.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_summon_guard() {
+        use crate::entities::notoriety::Notoriety;
+        use crate::entities::template::{load_templates, TEMPLATES_DIR};
+        let templates = load_templates(TEMPLATES_DIR).unwrap();
+        let map = Maps::with_walls(3, 3);
+        let mut npcs = EntityList::new();
+        assert!(
+            map.summon_guard(&mut npcs, &templates, Position::new(1, 1))
+                .is_none(),
+            "no room around the player"
+        );
+
+        let map = Maps::new(5, 5);
+        assert!(
+            map.summon_guard(&mut npcs, &TemplateRegistry::new(), Position::new(2, 2))
+                .is_none(),
+            "no guard template"
+        );
+        let guard = map
+            .summon_guard(&mut npcs, &templates, Position::new(2, 2))
+            .unwrap();
+        assert_eq!(npcs[guard].name, "Guard");
+        assert_eq!(npcs[guard].notoriety, Notoriety::Invulnerable);
+        assert_eq!(npcs[guard].position.distance(Position::new(2, 2)), 1);
+        assert_eq!(
+            map.summon_guard(&mut npcs, &templates, Position::new(2, 2)),
+            Some(guard),
+            "one guard is enough"
+        );
+        assert_eq!(npcs.len(), 1);
+    }
+}
@@ -0,0 +1,321 @@
+//! Spatial index for entities on a map. Entities are kept in a list together
+//! with grid buckets of their positions, so position queries only look at
+//! the buckets they touch instead of scanning every entity.
+
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+
+use crate::maps::position::Position;
+
+/// Side of a bucket, in tiles.
+const BUCKET_SIZE: usize = 8;
+
+pub trait Positioned {
+    fn position(&self) -> Position;
+}
+
+fn bucket_of(position: Position) -> (usize, usize) {
+    (position.x / BUCKET_SIZE, position.y / BUCKET_SIZE)
+}
+
+/// Entities plus the index of their positions. Reads go through the slice
+/// (`iter`, indexing, `len`); every change goes through methods that keep
+/// the index in sync.
+#[derive(Debug, Clone)]
+pub struct EntityList<T: Positioned> {
+    entities: Vec<T>,
+    buckets: HashMap<(usize, usize), Vec<usize>>,
+}
+
+impl<T: Positioned> Default for EntityList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Positioned> From<Vec<T>> for EntityList<T> {
+    fn from(entities: Vec<T>) -> Self {
+        let mut list = Self::new();
+        for entity in entities {
+            list.push(entity);
+        }
+        list
+    }
+}
+
+impl<T: Positioned> Deref for EntityList<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.entities
+    }
+}
+
+impl<'a, T: Positioned> IntoIterator for &'a EntityList<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entities.iter()
+    }
+}
+
+impl<T: Positioned> EntityList<T> {
+    pub fn new() -> Self {
+        Self {
+            entities: Vec::new(),
+            buckets: HashMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, entity: T) {
+        let index = self.entities.len();
+        self.buckets
+            .entry(bucket_of(entity.position()))
+            .or_default()
+            .push(index);
+        self.entities.push(entity);
+    }
+
+    /// Removes an entity, keeping the order of the others.
+    pub fn remove(&mut self, index: usize) -> T {
+        let entity = self.entities.remove(index);
+        self.unindex(index, entity.position());
+        for indices in self.buckets.values_mut() {
+            for other in indices.iter_mut() {
+                if *other > index {
+                    *other -= 1;
+                }
+            }
+        }
+        entity
+    }
+
+    /// Removes and returns every entity matching `predicate`.
+    pub fn take_where<F: FnMut(&T) -> bool>(&mut self, mut predicate: F) -> Vec<T> {
+        let (taken, kept): (Vec<T>, Vec<T>) = self
+            .entities
+            .drain(..)
+            .partition(|entity| predicate(entity));
+        *self = Self::from(kept);
+        taken
+    }
+
+    /// Mutable access; the index is updated if the position changes.
+    pub fn get_mut(&mut self, index: usize) -> Option<EntityMut<'_, T>> {
+        let old_position = self.entities.get(index)?.position();
+        Some(EntityMut {
+            list: self,
+            index,
+            old_position,
+        })
+    }
+
+    /// Indices of the entities standing on `position`.
+    pub fn at(&self, position: Position) -> impl Iterator<Item = usize> + '_ {
+        self.buckets
+            .get(&bucket_of(position))
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(move |&index| self.entities[index].position() == position)
+    }
+
+    pub fn any_at(&self, position: Position) -> bool {
+        self.at(position).next().is_some()
+    }
+
+    /// Indices of the entities within `radius` tiles of `center` in both
+    /// directions (a square), sorted by index.
+    pub fn in_range(&self, center: Position, radius: usize) -> Vec<usize> {
+        let min = Position::new(
+            center.x.saturating_sub(radius),
+            center.y.saturating_sub(radius),
+        );
+        let max = Position::new(
+            center.x.saturating_add(radius),
+            center.y.saturating_add(radius),
+        );
+        let (min_bucket, max_bucket) = (bucket_of(min), bucket_of(max));
+
+        let mut found: Vec<usize> = Vec::new();
+        for bucket_y in min_bucket.1..=max_bucket.1 {
+            for bucket_x in min_bucket.0..=max_bucket.0 {
+                let Some(indices) = self.buckets.get(&(bucket_x, bucket_y)) else {
+                    continue;
+                };
+                found.extend(indices.iter().copied().filter(|&index| {
+                    let Position { x, y } = self.entities[index].position();
+                    x >= min.x && x <= max.x && y >= min.y && y <= max.y
+                }));
+            }
+        }
+        found.sort_unstable();
+        found
+    }
+
+    /// First entity on `center` or one of its eight neighbours.
+    pub fn nearby(&self, center: Position) -> Option<usize> {
+        self.in_range(center, 1).into_iter().next()
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.entities
+    }
+
+    fn unindex(&mut self, index: usize, position: Position) {
+        let bucket = bucket_of(position);
+        if let Some(indices) = self.buckets.get_mut(&bucket) {
+            indices.retain(|&other| other != index);
+            if indices.is_empty() {
+                self.buckets.remove(&bucket);
+            }
+        }
+    }
+}
+
+/// Guard returned by `EntityList::get_mut`.
+pub struct EntityMut<'a, T: Positioned> {
+    list: &'a mut EntityList<T>,
+    index: usize,
+    old_position: Position,
+}
+
+impl<T: Positioned> Deref for EntityMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.list.entities[self.index]
+    }
+}
+
+impl<T: Positioned> DerefMut for EntityMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.list.entities[self.index]
+    }
+}
+
+impl<T: Positioned> Drop for EntityMut<'_, T> {
+    fn drop(&mut self) {
+        let new_position = self.list.entities[self.index].position();
+        if bucket_of(new_position) != bucket_of(self.old_position) {
+            self.list.unindex(self.index, self.old_position);
+            self.list
+                .buckets
+                .entry(bucket_of(new_position))
+                .or_default()
+                .push(self.index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Thing {
+        name: &'static str,
+        position: Position,
+    }
+
+    impl Positioned for Thing {
+        fn position(&self) -> Position {
+            self.position
+        }
+    }
+
+    fn thing(name: &'static str, x: usize, y: usize) -> Thing {
+        Thing {
+            name,
+            position: Position::new(x, y),
+        }
+    }
+
+    fn list() -> EntityList<Thing> {
+        EntityList::from(vec![
+            thing("a", 1, 1),
+            thing("b", 2, 2),
+            thing("c", 40, 40),
+            thing("d", 2, 2),
+        ])
+    }
+
+    #[test]
+    fn test_at() {
+        let things = list();
+        assert_eq!(
+            things.at(Position::new(2, 2)).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert!(things.any_at(Position::new(40, 40)));
+        assert!(!things.any_at(Position::new(3, 3)));
+    }
+
+    #[test]
+    fn test_in_range_across_buckets() {
+        let mut things = list();
+        things.push(thing("e", 9, 9));
+        things.push(thing("f", 6, 6));
+        assert_eq!(things.in_range(Position::new(7, 7), 2), vec![4, 5]);
+        assert_eq!(things.in_range(Position::new(0, 0), 2), vec![0, 1, 3]);
+        assert_eq!(things.in_range(Position::new(40, 40), 0), vec![2]);
+        assert_eq!(things.nearby(Position::new(3, 3)), Some(1));
+        assert_eq!(things.nearby(Position::new(20, 20)), None);
+    }
+
+    #[test]
+    fn test_remove_keeps_index_in_sync() {
+        let mut things = list();
+        let removed = things.remove(1);
+        assert_eq!(removed.name, "b");
+        assert_eq!(things.len(), 3);
+        assert_eq!(things.at(Position::new(2, 2)).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(things[2].name, "d");
+        assert_eq!(
+            things.at(Position::new(40, 40)).collect::<Vec<_>>(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_moving_updates_index() {
+        let mut things = list();
+        things.get_mut(2).unwrap().position = Position::new(3, 3);
+        assert!(!things.any_at(Position::new(40, 40)));
+        assert_eq!(things.at(Position::new(3, 3)).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(things.in_range(Position::new(0, 0), 3), vec![0, 1, 2, 3]);
+        assert!(things.get_mut(10).is_none());
+    }
+
+    #[test]
+    fn test_take_where() {
+        let mut things = list();
+        let taken = things.take_where(|thing| thing.position == Position::new(2, 2));
+        assert_eq!(taken.len(), 2);
+        assert_eq!(things.len(), 2);
+        assert_eq!(
+            things.at(Position::new(40, 40)).collect::<Vec<_>>(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_matches_linear_scan() {
+        let mut things = EntityList::new();
+        for i in 0..200 {
+            things.push(thing("x", (i * 37) % 61, (i * 17) % 53));
+        }
+        for center in [(0, 0), (30, 30), (60, 52), (15, 40)].map(Position::from) {
+            let expected: Vec<usize> = things
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| {
+                    t.position.x.abs_diff(center.x) <= 5 && t.position.y.abs_diff(center.y) <= 5
+                })
+                .map(|(index, _)| index)
+                .collect();
+            assert_eq!(things.in_range(center, 5), expected);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DoorState {
+    Open,
+    Closed,
+    Locked,
+}
+
+/// What the ground of a tile is made of. Occupants (player, NPCs, items) are
+/// tracked separately and drawn on top of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terrain {
+    Floor,
+    Wall,
+    Door(DoorState),
+    Water,
+    DeepWater,
+    Tree,
+    Grass,
+    Road,
+    Lava,
+    StairsUp,
+    StairsDown,
+    LampPost,
+}
+
+pub const ALL_TERRAIN: [Terrain; 14] = [
+    Terrain::Floor,
+    Terrain::Wall,
+    Terrain::Door(DoorState::Open),
+    Terrain::Door(DoorState::Closed),
+    Terrain::Door(DoorState::Locked),
+    Terrain::Water,
+    Terrain::DeepWater,
+    Terrain::Tree,
+    Terrain::Grass,
+    Terrain::Road,
+    Terrain::Lava,
+    Terrain::StairsUp,
+    Terrain::StairsDown,
+    Terrain::LampPost,
+];
+
+impl Terrain {
+    pub fn is_walkable(&self) -> bool {
+        !matches!(
+            self,
+            Terrain::Wall
+                | Terrain::Door(DoorState::Closed)
+                | Terrain::Door(DoorState::Locked)
+                | Terrain::DeepWater
+                | Terrain::Lava
+                | Terrain::LampPost
+        )
+    }
+
+    /// Cost of stepping onto the tile. Only meaningful for walkable terrain.
+    pub fn movement_cost(&self) -> u32 {
+        match self {
+            Terrain::Road => 1,
+            Terrain::Floor | Terrain::Door(_) => 1,
+            Terrain::Grass | Terrain::StairsUp | Terrain::StairsDown => 2,
+            Terrain::Tree => 3,
+            Terrain::Water => 4,
+            Terrain::Wall | Terrain::DeepWater | Terrain::Lava | Terrain::LampPost => u32::MAX,
+        }
+    }
+
+    /// Whether the terrain blocks line of sight.
+    pub fn is_opaque(&self) -> bool {
+        matches!(
+            self,
+            Terrain::Wall
+                | Terrain::Tree
+                | Terrain::Door(DoorState::Closed)
+                | Terrain::Door(DoorState::Locked)
+        )
+    }
+
+    /// Open-air ground, where snow piles up. Floors may be indoors.
+    pub fn is_outdoor(&self) -> bool {
+        matches!(
+            self,
+            Terrain::Grass | Terrain::Road | Terrain::Tree | Terrain::Water
+        )
+    }
+
+    /// Whether the light comes from a flame that bad weather can dampen.
+    pub fn is_flame(&self) -> bool {
+        matches!(self, Terrain::LampPost)
+    }
+
+    /// How far the terrain lights up its surroundings at night; 0 when it
+    /// gives no light.
+    pub fn light_radius(&self) -> usize {
+        match self {
+            Terrain::LampPost => 4,
+            Terrain::Lava => 2,
+            _ => 0,
+        }
+    }
+
+    pub fn glyph(&self) -> char {
+        match self {
+            Terrain::Floor => '.',
+            Terrain::Wall => '#',
+            Terrain::Door(DoorState::Open) => '\'',
+            Terrain::Door(DoorState::Closed) => '+',
+            Terrain::Door(DoorState::Locked) => '%',
+            Terrain::Water => '~',
+            Terrain::DeepWater => 'W',
+            Terrain::Tree => 'T',
+            Terrain::Grass => ',',
+            Terrain::Road => '=',
+            Terrain::Lava => '^',
+            Terrain::StairsUp => '<',
+            Terrain::StairsDown => '>',
+            Terrain::LampPost => '*',
+        }
+    }
+
+    pub fn from_glyph(glyph: char) -> Option<Terrain> {
+        ALL_TERRAIN
+            .iter()
+            .copied()
+            .find(|terrain| terrain.glyph() == glyph)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Terrain::Floor => "floor",
+            Terrain::Wall => "wall",
+            Terrain::Door(DoorState::Open) => "open door",
+            Terrain::Door(DoorState::Closed) => "closed door",
+            Terrain::Door(DoorState::Locked) => "locked door",
+            Terrain::Water => "shallow water",
+            Terrain::DeepWater => "deep water",
+            Terrain::Tree => "tree",
+            Terrain::Grass => "grass",
+            Terrain::Road => "road",
+            Terrain::Lava => "lava",
+            Terrain::StairsUp => "stairs up",
+            Terrain::StairsDown => "stairs down",
+            Terrain::LampPost => "lamp post",
+        }
+    }
+
+    /// Default text shown when the player looks at the terrain.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Terrain::Floor => "A plain stretch of ground.",
+            Terrain::Wall => "A sturdy stone wall.",
+            Terrain::Door(DoorState::Open) => "An open wooden door.",
+            Terrain::Door(DoorState::Closed) => "A closed wooden door.",
+            Terrain::Door(DoorState::Locked) => "A wooden door, firmly locked.",
+            Terrain::Water => "Shallow water, it would slow you down.",
+            Terrain::DeepWater => "Deep water, too deep to wade through.",
+            Terrain::Tree => "A tall tree.",
+            Terrain::Grass => "Soft green grass.",
+            Terrain::Road => "A well-trodden road.",
+            Terrain::Lava => "Molten lava. Walking here would be fatal.",
+            Terrain::StairsUp => "Stairs leading up.",
+            Terrain::StairsDown => "Stairs leading down.",
+            Terrain::LampPost => "An iron lamp post, lighting the street at night.",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walkability() {
+        assert!(Terrain::Floor.is_walkable());
+        assert!(Terrain::Door(DoorState::Open).is_walkable());
+        assert!(Terrain::Water.is_walkable());
+        assert!(!Terrain::Wall.is_walkable());
+        assert!(!Terrain::Door(DoorState::Closed).is_walkable());
+        assert!(!Terrain::Door(DoorState::Locked).is_walkable());
+        assert!(!Terrain::DeepWater.is_walkable());
+        assert!(!Terrain::Lava.is_walkable());
+        assert!(!Terrain::LampPost.is_walkable());
+    }
+
+    #[test]
+    fn test_movement_cost() {
+        assert_eq!(Terrain::Road.movement_cost(), 1);
+        assert!(Terrain::Grass.movement_cost() > Terrain::Road.movement_cost());
+        assert!(Terrain::Water.movement_cost() > Terrain::Grass.movement_cost());
+    }
+
+    #[test]
+    fn test_opacity() {
+        assert!(Terrain::Wall.is_opaque());
+        assert!(Terrain::Tree.is_opaque());
+        assert!(Terrain::Door(DoorState::Closed).is_opaque());
+        assert!(!Terrain::Door(DoorState::Open).is_opaque());
+        assert!(!Terrain::Water.is_opaque());
+        assert!(!Terrain::LampPost.is_opaque());
+    }
+
+    #[test]
+    fn test_glyph_round_trip() {
+        for terrain in ALL_TERRAIN {
+            assert_eq!(Terrain::from_glyph(terrain.glyph()), Some(terrain));
+        }
+        assert_eq!(Terrain::from_glyph('?'), None);
+    }
+}
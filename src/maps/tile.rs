@@ -1,31 +1,145 @@
+use crate::entities::notoriety::Notoriety;
+use crate::maps::terrain::Terrain;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum TileContent {
     Empty,
     Player,
-    NPC,
+    Npc,
     Item,
-    Obstacle
 }
 
+/// A single map cell: its terrain plus an optional custom description.
+/// An empty description falls back to the terrain's default one.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Tile {
-    pub content: TileContent,
-    pub description: String
+    pub terrain: Terrain,
+    pub description: String,
+}
+
+impl Tile {
+    pub fn new(terrain: Terrain) -> Self {
+        Self {
+            terrain,
+            description: String::new(),
+        }
+    }
+
+    pub fn describe(&self) -> &str {
+        if self.description.is_empty() {
+            self.terrain.description()
+        } else {
+            &self.description
+        }
+    }
+}
+
+/// How much the player knows about a rendered cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellVisibility {
+    /// In view right now: terrain and occupants are shown.
+    Visible,
+    /// Seen before but not in view: only the terrain is remembered.
+    Remembered,
+    /// In line of sight but unlit: only the terrain can be made out.
+    Dark,
+    /// Never seen.
+    Unknown,
+}
+
+/// Named terminal colours that data files can refer to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colour {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    Gray,
+    DarkGray,
+    LightRed,
+    LightGreen,
+    LightYellow,
+    LightBlue,
+    LightMagenta,
+    LightCyan,
+    White,
 }
 
+pub const ALL_COLOURS: [Colour; 16] = [
+    Colour::Black,
+    Colour::Red,
+    Colour::Green,
+    Colour::Yellow,
+    Colour::Blue,
+    Colour::Magenta,
+    Colour::Cyan,
+    Colour::Gray,
+    Colour::DarkGray,
+    Colour::LightRed,
+    Colour::LightGreen,
+    Colour::LightYellow,
+    Colour::LightBlue,
+    Colour::LightMagenta,
+    Colour::LightCyan,
+    Colour::White,
+];
+
+impl Colour {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Colour::Black => "black",
+            Colour::Red => "red",
+            Colour::Green => "green",
+            Colour::Yellow => "yellow",
+            Colour::Blue => "blue",
+            Colour::Magenta => "magenta",
+            Colour::Cyan => "cyan",
+            Colour::Gray => "gray",
+            Colour::DarkGray => "dark-gray",
+            Colour::LightRed => "light-red",
+            Colour::LightGreen => "light-green",
+            Colour::LightYellow => "light-yellow",
+            Colour::LightBlue => "light-blue",
+            Colour::LightMagenta => "light-magenta",
+            Colour::LightCyan => "light-cyan",
+            Colour::White => "white",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Colour> {
+        ALL_COLOURS
+            .iter()
+            .copied()
+            .find(|colour| colour.name() == name)
+    }
+}
+
+/// One rendered map cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrawnCell {
+    pub glyph: char,
+    pub visibility: CellVisibility,
+    /// Set when the cell shows an NPC or the player, so it can be coloured
+    /// by notoriety.
+    pub notoriety: Option<Notoriety>,
+    /// The NPC's own colour, which takes precedence over its notoriety.
+    pub colour: Option<Colour>,
+}
 
 impl TileContent {
-    pub(crate) fn to_char(self) -> char {
+    pub(crate) fn to_char(&self) -> char {
         match self {
             TileContent::Player => '@',
-            TileContent::NPC => 'N',
+            TileContent::Npc => 'N',
             TileContent::Empty => '.',
             TileContent::Item => 'I',
-            TileContent::Obstacle => '#'
         }
     }
 }
 
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -36,12 +150,18 @@ mod tests {
         assert_eq!(empty_tile.to_char(), '.');
         let player_tile = TileContent::Player;
         assert_eq!(player_tile.to_char(), '@');
-        let npc_tile = TileContent::NPC;
+        let npc_tile = TileContent::Npc;
         assert_eq!(npc_tile.to_char(), 'N');
         let item_tile = TileContent::Item;
         assert_eq!(item_tile.to_char(), 'I');
-        let obstacle_tile = TileContent::Obstacle;
-        assert_eq!(obstacle_tile.to_char(), '#');
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_describe() {
+        let plain = Tile::new(Terrain::Wall);
+        assert_eq!(plain.describe(), "A sturdy stone wall.");
+        let mut custom = Tile::new(Terrain::Wall);
+        custom.description = "The wall of Lord British's castle.".to_string();
+        assert_eq!(custom.describe(), "The wall of Lord British's castle.");
+    }
+}
@@ -0,0 +1,601 @@
+//! Imports maps made with the Tiled editor (JSON export, `.tmj` / `.json`).
+//!
+//! Tile layers become terrain and object layers become NPCs, items and the
+//! spawn point. What each Tiled tile id and object type turns into is read
+//! from a mapping file in the usual `key = value` format:
+//!
+//! ```text
+//! ; tile = <tile id> | <terrain glyph>
+//! tile = 1 | .
+//! tile = 2 | #
+//! ; tiles left empty in every layer
+//! empty = .
+//! ; object = <object type> | npc, item or spawn
+//! object = guard | npc
+//! object = potion | item
+//! object = start | spawn
+//! ```
+//!
+//! Tile ids are Tiled global ids (`gid`), i.e. the tileset's `firstgid`
+//! plus the tile index. Layers are applied bottom to top, and empty cells
+//! (id 0) keep whatever the layers below put there. NPCs take their name
+//! from the object name and read the optional `dialogue`, `health`,
+//! `strength`, `behaviour` and `notoriety` custom properties; items take the
+//! object name.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::entities::behaviour::Behaviour;
+use crate::entities::notoriety::Notoriety;
+use crate::entities::npc::NPC;
+use crate::inventory::item::Item;
+use crate::maps::map_file::{check_field, MapFile};
+use crate::maps::maps::Maps;
+use crate::maps::position::Position;
+use crate::maps::spatial::EntityList;
+use crate::maps::terrain::Terrain;
+use crate::world::weather::Climate;
+
+/// Tiled stores flip and rotation flags in the top bits of a tile id.
+const TILE_ID_MASK: u64 = 0x0FFF_FFFF;
+
+const DEFAULT_HEALTH: i32 = 50;
+const DEFAULT_STRENGTH: i32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObjectKind {
+    Npc,
+    Item,
+    Spawn,
+}
+
+/// How Tiled tile ids and object types translate into this game's types.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiledMapping {
+    pub tiles: HashMap<u32, Terrain>,
+    pub objects: HashMap<String, ObjectKind>,
+    /// Terrain of cells no layer paints.
+    pub empty: Terrain,
+}
+
+#[derive(Debug)]
+pub enum TiledError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The mapping file has a bad line.
+    Mapping {
+        line: usize,
+        reason: String,
+    },
+    /// The Tiled file is valid JSON but not a map we can read.
+    Invalid(String),
+    UnmappedTile {
+        layer: String,
+        id: u32,
+    },
+    UnmappedObject {
+        layer: String,
+        object_type: String,
+    },
+    OutOfBounds {
+        layer: String,
+        position: Position,
+    },
+}
+
+impl fmt::Display for TiledError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TiledError::Io(err) => write!(f, "cannot read file: {}", err),
+            TiledError::Json(err) => write!(f, "invalid JSON: {}", err),
+            TiledError::Mapping { line, reason } => write!(f, "mapping line {}: {}", line, reason),
+            TiledError::Invalid(reason) => write!(f, "not a Tiled map: {}", reason),
+            TiledError::UnmappedTile { layer, id } => write!(
+                f,
+                "layer '{}': tile id {} has no terrain mapping",
+                layer, id
+            ),
+            TiledError::UnmappedObject { layer, object_type } => {
+                write!(
+                    f,
+                    "layer '{}': object type '{}' has no mapping",
+                    layer, object_type
+                )
+            }
+            TiledError::OutOfBounds { layer, position } => {
+                write!(
+                    f,
+                    "layer '{}': object at {} is outside the map",
+                    layer, position
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TiledError {}
+
+impl From<io::Error> for TiledError {
+    fn from(err: io::Error) -> Self {
+        TiledError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for TiledError {
+    fn from(err: serde_json::Error) -> Self {
+        TiledError::Json(err)
+    }
+}
+
+pub fn load_mapping<P: AsRef<Path>>(path: P) -> Result<TiledMapping, TiledError> {
+    parse_mapping(&fs::read_to_string(path)?)
+}
+
+pub fn parse_mapping(contents: &str) -> Result<TiledMapping, TiledError> {
+    let mut mapping = TiledMapping {
+        tiles: HashMap::new(),
+        objects: HashMap::new(),
+        empty: Terrain::Floor,
+    };
+
+    for (index, raw_line) in contents.lines().enumerate() {
+        let line = index + 1;
+        let trimmed = raw_line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(';') {
+            continue;
+        }
+        let malformed = |reason: String| TiledError::Mapping { line, reason };
+        let (key, value) = trimmed
+            .split_once('=')
+            .ok_or_else(|| malformed("expected 'key = value'".to_string()))?;
+        let (key, value) = (key.trim(), value.trim());
+        match key {
+            "tile" => {
+                let (id, glyph) = value
+                    .split_once('|')
+                    .ok_or_else(|| malformed("expected 'tile id | glyph'".to_string()))?;
+                let id = id
+                    .trim()
+                    .parse()
+                    .map_err(|_| malformed(format!("invalid tile id '{}'", id.trim())))?;
+                mapping.tiles.insert(id, parse_glyph(line, glyph.trim())?);
+            }
+            "empty" => mapping.empty = parse_glyph(line, value)?,
+            "object" => {
+                let (object_type, kind) = value.split_once('|').ok_or_else(|| {
+                    malformed("expected 'object type | npc, item or spawn'".to_string())
+                })?;
+                let kind = match kind.trim() {
+                    "npc" => ObjectKind::Npc,
+                    "item" => ObjectKind::Item,
+                    "spawn" => ObjectKind::Spawn,
+                    other => return Err(malformed(format!("unknown object kind '{}'", other))),
+                };
+                mapping.objects.insert(object_type.trim().to_string(), kind);
+            }
+            other => return Err(malformed(format!("unknown key '{}'", other))),
+        }
+    }
+    Ok(mapping)
+}
+
+fn parse_glyph(line: usize, value: &str) -> Result<Terrain, TiledError> {
+    let mut chars = value.chars();
+    match (chars.next().and_then(Terrain::from_glyph), chars.next()) {
+        (Some(terrain), None) => Ok(terrain),
+        _ => Err(TiledError::Mapping {
+            line,
+            reason: format!("'{}' is not a terrain glyph", value),
+        }),
+    }
+}
+
+/// Reads a Tiled JSON map. The map is named after its `name` custom
+/// property, or after the file when there is none; an optional `climate`
+/// property works as in map files.
+pub fn import_tiled<P: AsRef<Path>>(
+    path: P,
+    mapping: &TiledMapping,
+) -> Result<MapFile, TiledError> {
+    let path = path.as_ref();
+    let mut map_file = parse_tiled(&fs::read_to_string(path)?, mapping)?;
+    if map_file.name.is_empty() {
+        map_file.name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+    }
+    Ok(map_file)
+}
+
+pub fn parse_tiled(contents: &str, mapping: &TiledMapping) -> Result<MapFile, TiledError> {
+    let root: Value = serde_json::from_str(contents)?;
+    if root.get("infinite").and_then(Value::as_bool) == Some(true) {
+        return Err(TiledError::Invalid(
+            "infinite maps are not supported".to_string(),
+        ));
+    }
+    let width = number(&root, "width")? as usize;
+    let height = number(&root, "height")? as usize;
+    let tile_width = number(&root, "tilewidth")?.max(1) as f64;
+    let tile_height = number(&root, "tileheight")?.max(1) as f64;
+
+    let mut map_file = MapFile {
+        name: property(&root, "name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        spawn: Position::default(),
+        climate: match property(&root, "climate").and_then(Value::as_str) {
+            Some(name) => Climate::from_name(name)
+                .ok_or_else(|| TiledError::Invalid(format!("unknown climate '{}'", name)))?,
+            None => Climate::default(),
+        },
+        map: Maps::new(width, height),
+        npcs: EntityList::new(),
+        items: EntityList::new(),
+        portals: Vec::new(),
+        placements: Vec::new(),
+        spawners: Vec::new(),
+        path: None,
+    };
+    for y in 0..height {
+        for x in 0..width {
+            map_file.map.set_tile(Position::new(x, y), mapping.empty);
+        }
+    }
+
+    let layers = root
+        .get("layers")
+        .and_then(Value::as_array)
+        .ok_or_else(|| TiledError::Invalid("missing 'layers'".to_string()))?;
+    for layer in flatten_groups(layers) {
+        let name = layer
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .to_string();
+        match layer.get("type").and_then(Value::as_str) {
+            Some("tilelayer") => apply_tile_layer(&mut map_file.map, layer, &name, mapping)?,
+            Some("objectgroup") => apply_object_layer(
+                &mut map_file,
+                layer,
+                &name,
+                mapping,
+                (tile_width, tile_height),
+            )?,
+            _ => (),
+        }
+    }
+    Ok(map_file)
+}
+
+/// Layers inside group layers, in drawing order.
+fn flatten_groups(layers: &[Value]) -> Vec<&Value> {
+    let mut flat = Vec::new();
+    for layer in layers {
+        match layer.get("layers").and_then(Value::as_array) {
+            Some(children) if layer.get("type").and_then(Value::as_str) == Some("group") => {
+                flat.extend(flatten_groups(children))
+            }
+            _ => flat.push(layer),
+        }
+    }
+    flat
+}
+
+fn apply_tile_layer(
+    map: &mut Maps,
+    layer: &Value,
+    name: &str,
+    mapping: &TiledMapping,
+) -> Result<(), TiledError> {
+    let data = layer.get("data").and_then(Value::as_array).ok_or_else(|| {
+        TiledError::Invalid(format!(
+            "layer '{}' has no CSV 'data' array (base64 encoding is not supported)",
+            name
+        ))
+    })?;
+    let layer_width = layer
+        .get("width")
+        .and_then(Value::as_u64)
+        .unwrap_or(map.width as u64) as usize;
+    for (index, cell) in data.iter().enumerate() {
+        let id = (cell.as_u64().unwrap_or(0) & TILE_ID_MASK) as u32;
+        if id == 0 {
+            continue;
+        }
+        let terrain = *mapping
+            .tiles
+            .get(&id)
+            .ok_or_else(|| TiledError::UnmappedTile {
+                layer: name.to_string(),
+                id,
+            })?;
+        map.set_tile(
+            Position::new(index % layer_width.max(1), index / layer_width.max(1)),
+            terrain,
+        );
+    }
+    Ok(())
+}
+
+fn apply_object_layer(
+    map_file: &mut MapFile,
+    layer: &Value,
+    name: &str,
+    mapping: &TiledMapping,
+    (tile_width, tile_height): (f64, f64),
+) -> Result<(), TiledError> {
+    let objects = layer
+        .get("objects")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    for object in objects {
+        // Older Tiled versions call the class "type".
+        let object_type = ["class", "type"]
+            .iter()
+            .filter_map(|key| object.get(*key).and_then(Value::as_str))
+            .find(|value| !value.is_empty())
+            .unwrap_or("");
+        let kind = *mapping
+            .objects
+            .get(object_type)
+            .ok_or_else(|| TiledError::UnmappedObject {
+                layer: name.to_string(),
+                object_type: object_type.to_string(),
+            })?;
+
+        let x = object.get("x").and_then(Value::as_f64).unwrap_or(0.0);
+        let mut y = object.get("y").and_then(Value::as_f64).unwrap_or(0.0);
+        // Tile objects are anchored at their bottom-left corner.
+        if object.get("gid").is_some() {
+            y -= tile_height;
+        }
+        let position = Position::new(
+            (x / tile_width).max(0.0) as usize,
+            (y / tile_height).max(0.0) as usize,
+        );
+        if !map_file.map.contains(position) {
+            return Err(TiledError::OutOfBounds {
+                layer: name.to_string(),
+                position,
+            });
+        }
+
+        let object_name = object.get("name").and_then(Value::as_str).unwrap_or("");
+        let object_name = if object_name.is_empty() {
+            object_type
+        } else {
+            object_name
+        };
+        let invalid = |reason: String| {
+            TiledError::Invalid(format!(
+                "object '{}' in layer '{}': {}",
+                object_name, name, reason
+            ))
+        };
+        check_field("The name", object_name).map_err(invalid)?;
+        match kind {
+            ObjectKind::Spawn => map_file.spawn = position,
+            ObjectKind::Item => map_file.items.push(Item::new(object_name, position)),
+            ObjectKind::Npc => {
+                let dialogue = property(object, "dialogue")
+                    .and_then(Value::as_str)
+                    .unwrap_or("");
+                check_field("The dialogue", dialogue).map_err(invalid)?;
+                let health = property(object, "health")
+                    .and_then(Value::as_i64)
+                    .map_or(DEFAULT_HEALTH, |value| value as i32);
+                let strength = property(object, "strength")
+                    .and_then(Value::as_i64)
+                    .map_or(DEFAULT_STRENGTH, |value| value as i32);
+                let mut npc = NPC::new(object_name, dialogue, position, health, strength);
+                if let Some(spec) = property(object, "behaviour").and_then(Value::as_str) {
+                    let (behaviour, flee_below) =
+                        Behaviour::parse(spec, position).map_err(invalid)?;
+                    if let Some(&waypoint) = behaviour
+                        .waypoints()
+                        .iter()
+                        .find(|&&waypoint| !map_file.map.contains(waypoint))
+                    {
+                        return Err(TiledError::OutOfBounds {
+                            layer: name.to_string(),
+                            position: waypoint,
+                        });
+                    }
+                    npc.behaviour = behaviour;
+                    npc.flee_below = flee_below;
+                }
+                if let Some(notoriety) = property(object, "notoriety").and_then(Value::as_str) {
+                    npc.notoriety = Notoriety::from_name(notoriety)
+                        .ok_or_else(|| invalid(format!("unknown notoriety '{}'", notoriety)))?;
+                }
+                map_file.npcs.push(npc);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn number(root: &Value, key: &str) -> Result<u64, TiledError> {
+    root.get(key)
+        .and_then(Value::as_u64)
+        .ok_or_else(|| TiledError::Invalid(format!("missing '{}'", key)))
+}
+
+/// Value of a Tiled custom property (`properties: [{name, type, value}]`).
+fn property<'a>(value: &'a Value, name: &str) -> Option<&'a Value> {
+    value
+        .get("properties")?
+        .as_array()?
+        .iter()
+        .find(|property| property.get("name").and_then(Value::as_str) == Some(name))?
+        .get("value")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPPING: &str = "\
+; grass, wall and water tiles
+tile = 1 | ,
+tile = 2 | #
+tile = 3 | ~
+empty = .
+object = guard | npc
+object = potion | item
+object = start | spawn
+";
+
+    const TILED: &str = r#"{
+  "width": 4, "height": 3, "tilewidth": 16, "tileheight": 16, "infinite": false,
+  "properties": [{"name": "name", "type": "string", "value": "Yew"}],
+  "layers": [
+    {"type": "tilelayer", "name": "ground", "width": 4, "height": 3,
+     "data": [2, 2, 2, 2,
+              1, 1, 0, 1,
+              2, 2, 2, 2]},
+    {"type": "group", "name": "details", "layers": [
+      {"type": "tilelayer", "name": "water", "width": 4, "height": 3,
+       "data": [0, 0, 0, 0,
+                0, 0, 0, 2147483651,
+                0, 0, 0, 0]}
+    ]},
+    {"type": "objectgroup", "name": "entities", "objects": [
+      {"name": "", "type": "start", "x": 16, "y": 16, "width": 0, "height": 0},
+      {"name": "Ranger", "class": "guard", "x": 40, "y": 20,
+       "properties": [{"name": "dialogue", "type": "string", "value": "Welcome to Yew."},
+                      {"name": "strength", "type": "int", "value": 14},
+                      {"name": "behaviour", "type": "string", "value": "wander 3"},
+                      {"name": "notoriety", "type": "string", "value": "ally"}]},
+      {"name": "Health Potion", "type": "potion", "gid": 5, "x": 0, "y": 32}
+    ]}
+  ]
+}"#;
+
+    #[test]
+    fn test_parse_mapping() {
+        let mapping = parse_mapping(MAPPING).unwrap();
+        assert_eq!(mapping.tiles[&2], Terrain::Wall);
+        assert_eq!(mapping.objects["start"], ObjectKind::Spawn);
+        assert_eq!(mapping.empty, Terrain::Floor);
+
+        assert!(matches!(
+            parse_mapping("tile = 1 | ?"),
+            Err(TiledError::Mapping { line: 1, .. })
+        ));
+        assert!(matches!(
+            parse_mapping("object = orc | monster"),
+            Err(TiledError::Mapping { line: 1, .. })
+        ));
+        assert!(matches!(
+            parse_mapping("\nlayer = 1"),
+            Err(TiledError::Mapping { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_import_layers_and_objects() {
+        let map_file = parse_tiled(TILED, &parse_mapping(MAPPING).unwrap()).unwrap();
+        assert_eq!(map_file.name, "Yew");
+        assert_eq!((map_file.map.width, map_file.map.height), (4, 3));
+        assert_eq!(
+            map_file.map.terrain(Position::new(0, 0)),
+            Some(Terrain::Wall)
+        );
+        assert_eq!(
+            map_file.map.terrain(Position::new(0, 1)),
+            Some(Terrain::Grass)
+        );
+        let terrain = |x, y| map_file.map.terrain(Position::new(x, y));
+        assert_eq!(
+            terrain(2, 1),
+            Some(Terrain::Floor),
+            "empty cells use the 'empty' terrain"
+        );
+        assert_eq!(
+            terrain(3, 1),
+            Some(Terrain::Water),
+            "upper layers win, flip flags are ignored"
+        );
+
+        assert_eq!(map_file.spawn, Position::new(1, 1));
+        assert_eq!(map_file.npcs.len(), 1);
+        assert_eq!(map_file.npcs[0].name, "Ranger");
+        assert_eq!(map_file.npcs[0].position, Position::new(2, 1));
+        assert_eq!(map_file.npcs[0].dialogue, "Welcome to Yew.");
+        assert_eq!(map_file.npcs[0].strength, 14);
+        assert!(matches!(
+            map_file.npcs[0].behaviour,
+            Behaviour::Wander { leash: 3, .. }
+        ));
+        assert_eq!(map_file.npcs[0].notoriety, Notoriety::Ally);
+        assert_eq!(map_file.npcs[0].health, DEFAULT_HEALTH);
+        assert_eq!(map_file.items[0].name, "Health Potion");
+        assert_eq!(map_file.items[0].position, Position::new(0, 1));
+    }
+
+    #[test]
+    fn test_unmapped_and_invalid() {
+        let mut mapping = parse_mapping(MAPPING).unwrap();
+        mapping.tiles.remove(&3);
+        assert!(matches!(
+            parse_tiled(TILED, &mapping),
+            Err(TiledError::UnmappedTile { id: 3, .. })
+        ));
+
+        let mut mapping = parse_mapping(MAPPING).unwrap();
+        mapping.objects.remove("guard");
+        assert!(matches!(
+            parse_tiled(TILED, &mapping),
+            Err(TiledError::UnmappedObject { .. })
+        ));
+
+        let mapping = parse_mapping(MAPPING).unwrap();
+        assert!(matches!(
+            parse_tiled("{\"width\": 2}", &mapping),
+            Err(TiledError::Invalid(_))
+        ));
+        assert!(matches!(
+            parse_tiled("{", &mapping),
+            Err(TiledError::Json(_))
+        ));
+        let piped = TILED.replace("Welcome to Yew.", "Welcome | Yew.");
+        assert!(parse_tiled(&piped, &mapping)
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("cannot contain '|'"));
+        let infinite = TILED.replace("\"infinite\": false", "\"infinite\": true");
+        assert!(matches!(
+            parse_tiled(&infinite, &mapping),
+            Err(TiledError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_objects_outside_the_map() {
+        let far = TILED.replace("\"x\": 40, \"y\": 20", "\"x\": 400, \"y\": 20");
+        assert!(matches!(
+            parse_tiled(&far, &parse_mapping(MAPPING).unwrap()),
+            Err(TiledError::OutOfBounds { .. })
+        ));
+        let lost = TILED.replace("\"value\": \"wander 3\"", "\"value\": \"patrol 1,1 90,1\"");
+        assert!(matches!(
+            parse_tiled(&lost, &parse_mapping(MAPPING).unwrap()),
+            Err(TiledError::OutOfBounds {
+                position: Position { x: 90, y: 1 },
+                ..
+            })
+        ));
+    }
+}
@@ -1 +1,2 @@
-mod quest;
\ No newline at end of file
+#[allow(clippy::module_inception)] // `quest::quest` holds the quest states
+pub mod quest;
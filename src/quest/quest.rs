@@ -1,8 +1,12 @@
+// Dialogue tracks quests by id through `QuestState`; titled quests are not
+// loaded from data yet.
+#[allow(dead_code)]
 pub struct Quest {
     pub title: String,
     pub description: String,
 }
 
+#[allow(dead_code)]
 impl Quest {
     pub fn new(title: &str, description: &str) -> Self {
         Self {
@@ -15,3 +19,26 @@ impl Quest {
         println!("Quest started: {}", self.title);
     }
 }
+
+/// How far the player has got with a quest; quests they never started have
+/// no state at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuestState {
+    Active,
+    Done,
+}
+
+impl QuestState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            QuestState::Active => "active",
+            QuestState::Done => "done",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<QuestState> {
+        [QuestState::Active, QuestState::Done]
+            .into_iter()
+            .find(|state| state.name() == name)
+    }
+}
@@ -0,0 +1,858 @@
+//! Saved games, stored as `key = value` lines like map files:
+//!
+//! ```text
+//! name = Hero
+//! map = Britain
+//! position = 2,2
+//! time = 1920
+//! weather = Britain | 0,0 | rain | 2100
+//! health = 100
+//! notoriety = criminal
+//! murders = 1
+//! criminal = 90
+//! reputation = 5
+//! quest = ore | active
+//! item = Health Potion
+//! explored = Britain | 3 | 1-8 12-15
+//! state = Britain
+//! ground = Britain | Torch | 12,10
+//! npc = Britain | 25,13 | 31,13 | 42
+//! spawner = Britain | 0 | 2130 2145
+//! spawned = Britain | 0 | rat | 105,31 | 7
+//! ```
+//!
+//! Each `weather` line holds the weather of one region of a map and the
+//! game minute it lasts until. `criminal` is the number of game minutes
+//! left before a criminal flag wears off. Each `quest` line is a quest the
+//! player has started and whether it is `active` or `done`. Each
+//! `explored` line lists, for one map and one row, the ranges of columns
+//! the player has already seen.
+//!
+//! A `state` line says the save holds what is left on a map: the items
+//! lying on the `ground` and, for each NPC placed by the map file that is
+//! still alive, the tile it was placed on, where it stands and its health.
+//! The items and NPCs the map file adds are replaced by those when loading.
+//! Each `spawner` line holds the game minutes of the pending respawns of
+//! one spawner, by its index in the map file, and each `spawned` line an
+//! NPC it spawned, so loading a game does not refill spawners for free.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::entities::notoriety::Notoriety;
+use crate::entities::player::Player;
+use crate::entities::template::TemplateRegistry;
+use crate::inventory::item::Item;
+use crate::maps::map_file::MapFile;
+use crate::maps::position::Position;
+use crate::maps::spatial::EntityList;
+use crate::quest::quest::QuestState;
+use crate::world::clock::WorldClock;
+use crate::world::weather::{Spell, Weather, WeatherSystem};
+use crate::world::world::World;
+
+pub const SAVE_FILE: &str = "saves/player.sav";
+
+/// A player together with the map they were on, the time of day, the
+/// weather and what is left on each map.
+pub struct SavedGame {
+    pub player: Player,
+    pub map_name: String,
+    pub clock: WorldClock,
+    pub weather: WeatherSystem,
+    pub maps: Vec<MapState>,
+}
+
+/// The items and NPCs of one map at the time of saving.
+#[derive(Debug, Clone)]
+pub struct MapState {
+    pub name: String,
+    pub items: Vec<Item>,
+    pub npcs: Vec<NpcState>,
+    /// Pending respawns of each spawner, by spawner index.
+    pub respawns: Vec<(usize, Vec<u64>)>,
+    pub spawned: Vec<SpawnedState>,
+}
+
+/// A living NPC from the map file, recognised by the tile it was placed on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NpcState {
+    pub home: Position,
+    pub position: Position,
+    pub health: i32,
+}
+
+/// A living NPC of a spawner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnedState {
+    pub spawner: usize,
+    pub template: String,
+    pub position: Position,
+    pub health: i32,
+}
+
+impl SavedGame {
+    /// Forgets the explored tiles of maps that are gone or that no longer
+    /// lie on their map, for maps changed since the game was saved.
+    pub fn forget_lost_tiles(&mut self, world: &World) {
+        self.player
+            .explored
+            .retain(|name, explored| match world.get(name) {
+                Some(map_file) => {
+                    explored.retain(|&position| map_file.map.contains(position));
+                    true
+                }
+                None => false,
+            });
+    }
+}
+
+impl MapState {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            items: Vec::new(),
+            npcs: Vec::new(),
+            respawns: Vec::new(),
+            spawned: Vec::new(),
+        }
+    }
+
+    pub fn capture(map_file: &MapFile) -> Self {
+        Self {
+            name: map_file.name.clone(),
+            items: map_file.items.iter().cloned().collect(),
+            npcs: map_file
+                .npcs
+                .iter()
+                .filter(|npc| npc.spawner.is_none())
+                .map(|npc| NpcState {
+                    home: npc.home,
+                    position: npc.position,
+                    health: npc.health,
+                })
+                .collect(),
+            respawns: map_file
+                .spawners
+                .iter()
+                .enumerate()
+                .map(|(index, spawner)| (index, spawner.respawns().to_vec()))
+                .collect(),
+            spawned: map_file
+                .npcs
+                .iter()
+                .filter_map(|npc| {
+                    Some(SpawnedState {
+                        spawner: npc.spawner?,
+                        template: npc.template.clone()?,
+                        position: npc.position,
+                        health: npc.health,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Puts the saved items back and keeps only the NPCs that were alive,
+    /// where they stood. Anything that no longer fits the map stays where
+    /// the map file put it, or is dropped. Spawners get their NPCs, spawned
+    /// again from `templates`, and their pending respawns back.
+    pub fn restore(&self, map_file: &mut MapFile, templates: &TemplateRegistry) {
+        let items = self
+            .items
+            .iter()
+            .filter(|item| map_file.map.contains(item.position))
+            .cloned();
+        map_file.items = EntityList::from(items.collect::<Vec<Item>>());
+
+        let placed = map_file.npcs.take_where(|npc| npc.spawner.is_none());
+        let mut saved = self.npcs.clone();
+        for mut npc in placed {
+            let Some(index) = saved.iter().position(|state| state.home == npc.home) else {
+                continue;
+            };
+            let state = saved.remove(index);
+            if map_file.map.is_walkable(state.position) {
+                npc.position = state.position;
+            }
+            npc.health = state.health;
+            map_file.npcs.push(npc);
+        }
+
+        for (index, respawns) in &self.respawns {
+            if let Some(spawner) = map_file.spawners.get_mut(*index) {
+                spawner.set_respawns(respawns.clone());
+            }
+        }
+        map_file.npcs.take_where(|npc| npc.spawner.is_some());
+        for state in &self.spawned {
+            if state.spawner >= map_file.spawners.len() || !map_file.map.is_walkable(state.position)
+            {
+                continue;
+            }
+            if let Ok(mut npc) = templates.spawn(&state.template, state.position) {
+                npc.spawner = Some(state.spawner);
+                npc.health = state.health;
+                map_file.npcs.push(npc);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    MalformedLine { line: usize, reason: String },
+    UnknownKey { line: usize, key: String },
+    MissingField(&'static str),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "cannot read save file: {}", err),
+            SaveError::MalformedLine { line, reason } => write!(f, "line {}: {}", line, reason),
+            SaveError::UnknownKey { line, key } => {
+                write!(f, "line {}: unknown key '{}'", line, key)
+            }
+            SaveError::MissingField(field) => write!(f, "missing '{}'", field),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<io::Error> for SaveError {
+    fn from(err: io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+pub fn save_game<P: AsRef<Path>>(path: P, player: &Player, world: &World) -> io::Result<()> {
+    if let Some(parent) = path.as_ref().parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let maps: Vec<MapState> = world.maps().iter().map(MapState::capture).collect();
+    fs::write(
+        path,
+        format_save(
+            player,
+            &world.current().name,
+            &world.clock,
+            &world.weather,
+            &maps,
+        ),
+    )
+}
+
+pub fn load_game<P: AsRef<Path>>(path: P) -> Result<SavedGame, SaveError> {
+    let contents = fs::read_to_string(path)?;
+    parse_save(&contents)
+}
+
+pub fn format_save(
+    player: &Player,
+    map_name: &str,
+    clock: &WorldClock,
+    weather: &WeatherSystem,
+    maps: &[MapState],
+) -> String {
+    let mut out = String::new();
+    out.push_str(&format!("name = {}\n", player.name));
+    out.push_str(&format!("map = {}\n", map_name));
+    out.push_str(&format!("position = {}\n", player.position));
+    out.push_str(&format!("time = {}\n", clock.minutes()));
+    for (name, (x, y), spell) in weather.spells() {
+        out.push_str(&format!(
+            "weather = {} | {},{} | {} | {}\n",
+            name,
+            x,
+            y,
+            spell.weather.name(),
+            spell.until
+        ));
+    }
+    out.push_str(&format!("health = {}\n", player.health));
+    out.push_str(&format!("mana = {}\n", player.mana));
+    out.push_str(&format!("level = {}\n", player.level));
+    out.push_str(&format!("experience = {}\n", player.experience));
+    out.push_str(&format!("strength = {}\n", player.strength));
+    out.push_str(&format!("agility = {}\n", player.agility));
+    out.push_str(&format!("charisma = {}\n", player.charisma));
+    out.push_str(&format!("notoriety = {}\n", player.notoriety.name()));
+    out.push_str(&format!("murders = {}\n", player.murders));
+    out.push_str(&format!("criminal = {}\n", player.criminal_minutes));
+    out.push_str(&format!("reputation = {}\n", player.reputation));
+    for (quest, state) in &player.quests {
+        out.push_str(&format!("quest = {} | {}\n", quest, state.name()));
+    }
+    for item in &player.inventory {
+        out.push_str(&format!("item = {}\n", item.name));
+    }
+
+    // Sorted so the same state always produces the same file.
+    let explored: BTreeMap<&String, BTreeSet<(usize, usize)>> = player
+        .explored
+        .iter()
+        .map(|(name, tiles)| {
+            (
+                name,
+                tiles
+                    .iter()
+                    .map(|position| (position.y, position.x))
+                    .collect(),
+            )
+        })
+        .collect();
+    for (name, tiles) in explored {
+        let mut rows: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (y, x) in tiles {
+            rows.entry(y).or_default().push(x);
+        }
+        for (y, columns) in rows {
+            out.push_str(&format!(
+                "explored = {} | {} | {}\n",
+                name,
+                y,
+                format_ranges(&columns)
+            ));
+        }
+    }
+
+    for state in maps {
+        out.push_str(&format!("state = {}\n", state.name));
+        for item in &state.items {
+            out.push_str(&format!(
+                "ground = {} | {} | {}\n",
+                state.name, item.name, item.position
+            ));
+        }
+        for npc in &state.npcs {
+            out.push_str(&format!(
+                "npc = {} | {} | {} | {}\n",
+                state.name, npc.home, npc.position, npc.health
+            ));
+        }
+        for (index, respawns) in &state.respawns {
+            let minutes: Vec<String> = respawns.iter().map(u64::to_string).collect();
+            out.push_str(&format!(
+                "spawner = {} | {} | {}\n",
+                state.name,
+                index,
+                minutes.join(" ")
+            ));
+        }
+        for npc in &state.spawned {
+            out.push_str(&format!(
+                "spawned = {} | {} | {} | {} | {}\n",
+                state.name, npc.spawner, npc.template, npc.position, npc.health
+            ));
+        }
+    }
+    out
+}
+
+pub fn parse_save(contents: &str) -> Result<SavedGame, SaveError> {
+    let mut player = Player::new("");
+    let mut name = None;
+    let mut map_name = None;
+    let mut clock = WorldClock::new();
+    let mut weather = WeatherSystem::new();
+    let mut maps: Vec<MapState> = Vec::new();
+
+    for (index, raw_line) in contents.lines().enumerate() {
+        let line = index + 1;
+        let trimmed = raw_line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(';') {
+            continue;
+        }
+        let (key, value) = trimmed
+            .split_once('=')
+            .ok_or_else(|| SaveError::MalformedLine {
+                line,
+                reason: "expected 'key = value'".to_string(),
+            })?;
+        let key = key.trim();
+        let value = value.trim();
+        match key {
+            "name" => name = Some(value.to_string()),
+            "map" => map_name = Some(value.to_string()),
+            "position" => player.position = parse_position(line, value)?,
+            "time" => clock = WorldClock::at(parse_number(line, key, value)?),
+            "weather" => {
+                let (map, region, spell) = parse_weather(line, value)?;
+                weather.set_spell(&map, region, spell);
+            }
+            "health" => player.health = parse_number(line, key, value)?,
+            "mana" => player.mana = parse_number(line, key, value)?,
+            "level" => player.level = parse_number(line, key, value)?,
+            "experience" => player.experience = parse_number(line, key, value)?,
+            "strength" => player.strength = parse_number(line, key, value)?,
+            "agility" => player.agility = parse_number(line, key, value)?,
+            "charisma" => player.charisma = parse_number(line, key, value)?,
+            "notoriety" => {
+                player.notoriety =
+                    Notoriety::from_name(value).ok_or_else(|| SaveError::MalformedLine {
+                        line,
+                        reason: format!("unknown notoriety '{}'", value),
+                    })?
+            }
+            "murders" => player.murders = parse_number(line, key, value)?,
+            "criminal" => player.criminal_minutes = parse_number(line, key, value)?,
+            "reputation" => player.reputation = parse_number(line, key, value)?,
+            "quest" => {
+                let (quest, state) = parse_quest(line, value)?;
+                player.quests.insert(quest, state);
+            }
+            "item" => player.inventory.push(Item::new(value, Position::default())),
+            "explored" => {
+                let (map, y, columns) = parse_explored(line, value)?;
+                player
+                    .explored
+                    .entry(map)
+                    .or_default()
+                    .extend(columns.into_iter().map(|x| Position::new(x, y)));
+            }
+            "state" => maps.push(MapState::new(value)),
+            "ground" => {
+                let fields = split_fields(line, value, 3, "map | item | x,y")?;
+                let item = Item::new(fields[1], parse_position(line, fields[2])?);
+                map_state(&mut maps, line, fields[0])?.items.push(item);
+            }
+            "npc" => {
+                let fields = split_fields(line, value, 4, "map | home | x,y | health")?;
+                let npc = NpcState {
+                    home: parse_position(line, fields[1])?,
+                    position: parse_position(line, fields[2])?,
+                    health: parse_number(line, "health", fields[3])?,
+                };
+                map_state(&mut maps, line, fields[0])?.npcs.push(npc);
+            }
+            "spawner" => {
+                let fields = split_fields(line, value, 3, "map | spawner | minutes")?;
+                let index = parse_number(line, "spawner", fields[1])?;
+                let respawns = fields[2]
+                    .split_whitespace()
+                    .map(|minute| parse_number(line, "minute", minute))
+                    .collect::<Result<Vec<u64>, SaveError>>()?;
+                map_state(&mut maps, line, fields[0])?
+                    .respawns
+                    .push((index, respawns));
+            }
+            "spawned" => {
+                let fields =
+                    split_fields(line, value, 5, "map | spawner | template | x,y | health")?;
+                let npc = SpawnedState {
+                    spawner: parse_number(line, "spawner", fields[1])?,
+                    template: fields[2].to_string(),
+                    position: parse_position(line, fields[3])?,
+                    health: parse_number(line, "health", fields[4])?,
+                };
+                map_state(&mut maps, line, fields[0])?.spawned.push(npc);
+            }
+            _ => {
+                return Err(SaveError::UnknownKey {
+                    line,
+                    key: key.to_string(),
+                })
+            }
+        }
+    }
+
+    player.name = name.ok_or(SaveError::MissingField("name"))?;
+    let map_name = map_name.ok_or(SaveError::MissingField("map"))?;
+    Ok(SavedGame {
+        player,
+        map_name,
+        clock,
+        weather,
+        maps,
+    })
+}
+
+fn split_fields<'a>(
+    line: usize,
+    value: &'a str,
+    count: usize,
+    expected: &str,
+) -> Result<Vec<&'a str>, SaveError> {
+    let fields: Vec<&str> = value.split('|').map(str::trim).collect();
+    if fields.len() != count {
+        return Err(SaveError::MalformedLine {
+            line,
+            reason: format!("expected '{}'", expected),
+        });
+    }
+    Ok(fields)
+}
+
+/// The state opened by the last `state` line, which must be for `name`.
+fn map_state<'a>(
+    maps: &'a mut [MapState],
+    line: usize,
+    name: &str,
+) -> Result<&'a mut MapState, SaveError> {
+    match maps.last_mut() {
+        Some(state) if state.name == name => Ok(state),
+        _ => Err(SaveError::MalformedLine {
+            line,
+            reason: format!("no 'state = {}' line before this one", name),
+        }),
+    }
+}
+
+/// `[1, 2, 3, 7, 9, 10]` becomes `1-3 7 9-10`.
+fn format_ranges(columns: &[usize]) -> String {
+    let mut ranges: Vec<String> = Vec::new();
+    let mut index = 0;
+    while index < columns.len() {
+        let start = columns[index];
+        let mut end = start;
+        while index + 1 < columns.len() && columns[index + 1] == end + 1 {
+            index += 1;
+            end = columns[index];
+        }
+        if start == end {
+            ranges.push(start.to_string());
+        } else {
+            ranges.push(format!("{}-{}", start, end));
+        }
+        index += 1;
+    }
+    ranges.join(" ")
+}
+
+fn parse_explored(line: usize, value: &str) -> Result<(String, usize, Vec<usize>), SaveError> {
+    let malformed = |reason: String| SaveError::MalformedLine { line, reason };
+    let fields: Vec<&str> = value.split('|').map(str::trim).collect();
+    if fields.len() != 3 {
+        return Err(malformed("expected 'map | row | ranges'".to_string()));
+    }
+    let y = parse_number(line, "row", fields[1])?;
+    let mut columns = Vec::new();
+    for range in fields[2].split_whitespace() {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let start: usize = start
+            .parse()
+            .map_err(|_| malformed(format!("invalid range '{}'", range)))?;
+        let end: usize = end
+            .parse()
+            .map_err(|_| malformed(format!("invalid range '{}'", range)))?;
+        if end < start {
+            return Err(malformed(format!("invalid range '{}'", range)));
+        }
+        columns.extend(start..=end);
+    }
+    Ok((fields[0].to_string(), y, columns))
+}
+
+fn parse_quest(line: usize, value: &str) -> Result<(String, QuestState), SaveError> {
+    let malformed = |reason: String| SaveError::MalformedLine { line, reason };
+    let (quest, state) = value
+        .split_once('|')
+        .ok_or_else(|| malformed("expected 'quest | state'".to_string()))?;
+    let state = state.trim();
+    let state = QuestState::from_name(state)
+        .ok_or_else(|| malformed(format!("unknown quest state '{}'", state)))?;
+    Ok((quest.trim().to_string(), state))
+}
+
+fn parse_weather(line: usize, value: &str) -> Result<(String, (usize, usize), Spell), SaveError> {
+    let fields: Vec<&str> = value.split('|').map(str::trim).collect();
+    if fields.len() != 4 {
+        return Err(SaveError::MalformedLine {
+            line,
+            reason: "expected 'map | x,y | weather | until'".to_string(),
+        });
+    }
+    let region = parse_position(line, fields[1])?;
+    let weather = Weather::from_name(fields[2]).ok_or_else(|| SaveError::MalformedLine {
+        line,
+        reason: format!("unknown weather '{}'", fields[2]),
+    })?;
+    let until = parse_number(line, "time", fields[3])?;
+    Ok((
+        fields[0].to_string(),
+        (region.x, region.y),
+        Spell { weather, until },
+    ))
+}
+
+fn parse_position(line: usize, value: &str) -> Result<Position, SaveError> {
+    let malformed = || SaveError::MalformedLine {
+        line,
+        reason: format!("invalid position '{}', expected 'x,y'", value),
+    };
+    let (x, y) = value.split_once(',').ok_or_else(malformed)?;
+    let x = x.trim().parse().map_err(|_| malformed())?;
+    let y = y.trim().parse().map_err(|_| malformed())?;
+    Ok(Position::new(x, y))
+}
+
+fn parse_number<T: std::str::FromStr>(
+    line: usize,
+    field: &str,
+    value: &str,
+) -> Result<T, SaveError> {
+    value.parse().map_err(|_| SaveError::MalformedLine {
+        line,
+        reason: format!("invalid {} '{}'", field, value),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hero() -> Player {
+        let mut player = Player::new("Hero");
+        player.position = Position::new(4, 7);
+        player.health = 80;
+        player.level = 3;
+        player.commit_crime();
+        player.murders = 2;
+        player.reputation = -3;
+        player.quests.insert("ore".to_string(), QuestState::Done);
+        player
+            .inventory
+            .push(Item::new("Health Potion", Position::new(0, 0)));
+        let britain = player.explored.entry("Britain".to_string()).or_default();
+        britain.extend([(1, 3), (2, 3), (3, 3), (7, 3), (5, 4)].map(Position::from));
+        player
+            .explored
+            .entry("Despise".to_string())
+            .or_default()
+            .insert(Position::new(0, 0));
+        player
+    }
+
+    fn stormy() -> WeatherSystem {
+        let mut weather = WeatherSystem::new();
+        weather.set_spell(
+            "Britain",
+            (1, 0),
+            Spell {
+                weather: Weather::Storm,
+                until: 2000,
+            },
+        );
+        weather
+    }
+
+    #[test]
+    fn test_format_save() {
+        let saved = format_save(&hero(), "Britain", &WorldClock::at(1920), &stormy(), &[]);
+        assert!(saved.contains("map = Britain\n"));
+        assert!(saved.contains("position = 4,7\n"));
+        assert!(saved.contains("time = 1920\n"));
+        assert!(saved.contains("weather = Britain | 1,0 | storm | 2000\n"));
+        assert!(saved.contains("item = Health Potion\n"));
+        assert!(saved.contains("notoriety = criminal\n"));
+        assert!(saved.contains("quest = ore | done\n"));
+        assert!(saved.contains("explored = Britain | 3 | 1-3 7\n"));
+        assert!(saved.contains("explored = Britain | 4 | 5\n"));
+        assert!(saved.contains("explored = Despise | 0 | 0\n"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let player = hero();
+        let loaded = parse_save(&format_save(
+            &player,
+            "Despise",
+            &WorldClock::at(1920),
+            &stormy(),
+            &[],
+        ))
+        .unwrap();
+        assert_eq!(loaded.map_name, "Despise");
+        assert_eq!(loaded.clock, WorldClock::at(1920));
+        assert_eq!(loaded.weather, stormy());
+        assert_eq!(loaded.player.name, "Hero");
+        assert_eq!(loaded.player.position, Position::new(4, 7));
+        assert_eq!(loaded.player.health, 80);
+        assert_eq!(loaded.player.level, 3);
+        assert_eq!(loaded.player.notoriety, Notoriety::Criminal);
+        assert_eq!(loaded.player.murders, 2);
+        assert_eq!(loaded.player.reputation, -3);
+        assert_eq!(loaded.player.quests, player.quests);
+        assert_eq!(loaded.player.criminal_minutes, player.criminal_minutes);
+        assert_eq!(loaded.player.inventory[0].name, "Health Potion");
+        assert_eq!(loaded.player.explored, player.explored);
+    }
+
+    #[test]
+    fn test_forget_lost_tiles() {
+        use crate::maps::map_file::parse_map;
+        let mut player = hero();
+        player.explored.insert(
+            "Britain".to_string(),
+            [Position::new(1, 1), Position::new(9, 1)].into(),
+        );
+        player
+            .explored
+            .insert("Wind".to_string(), [Position::new(1, 1)].into());
+        let mut saved = parse_save(&format_save(
+            &player,
+            "Britain",
+            &WorldClock::new(),
+            &WeatherSystem::new(),
+            &[],
+        ))
+        .unwrap();
+
+        let world = World::new(parse_map("name = Britain\n[map]\n....\n....\n").unwrap());
+        saved.forget_lost_tiles(&world);
+        assert_eq!(saved.player.explored.len(), 1);
+        assert_eq!(
+            saved.player.explored["Britain"],
+            [Position::new(1, 1)].into()
+        );
+    }
+
+    #[test]
+    fn test_map_state() {
+        use crate::maps::map_file::parse_map;
+        let britain = "name = Britain\n\
+            npc = Guard | Halt! | 1,1 | 50 | 10\n\
+            npc = Rat | Squeak! | 3,1 | 5 | 1\n\
+            item = Torch | 2,1\n\
+            item = Gold | 4,1\n\
+            [map]\n\
+            ......\n\
+            ......\n";
+        let mut played = parse_map(britain).unwrap();
+        played.npcs.take_where(|npc| npc.name == "Rat");
+        played.npcs.get_mut(0).unwrap().position = Position::new(5, 1);
+        played.npcs.get_mut(0).unwrap().health = 20;
+        played.items.take_where(|item| item.name == "Torch");
+        played.items.push(Item::new("Bone", Position::new(3, 1)));
+
+        let saved = format_save(
+            &hero(),
+            "Britain",
+            &WorldClock::at(0),
+            &stormy(),
+            &[MapState::capture(&played)],
+        );
+        assert!(saved.contains(
+            "state = Britain\nground = Britain | Gold | 4,1\nground = Britain | Bone | 3,1\n"
+        ));
+        assert!(saved.contains("npc = Britain | 1,1 | 5,1 | 20\n"));
+
+        let loaded = parse_save(&saved).unwrap();
+        let mut reloaded = parse_map(britain).unwrap();
+        loaded.maps[0].restore(&mut reloaded, &TemplateRegistry::new());
+        assert_eq!(reloaded.npcs.len(), 1, "the rat stays dead");
+        assert_eq!(
+            (reloaded.npcs[0].position, reloaded.npcs[0].health),
+            (Position::new(5, 1), 20)
+        );
+        assert_eq!(reloaded.npcs[0].home, Position::new(1, 1));
+        let items: Vec<&str> = reloaded
+            .items
+            .iter()
+            .map(|item| item.name.as_str())
+            .collect();
+        assert_eq!(items, vec!["Gold", "Bone"], "the torch stays picked up");
+
+        assert!(matches!(
+            parse_save("name = Hero\nground = Britain | Gold | 1,1\n")
+                .err()
+                .unwrap(),
+            SaveError::MalformedLine { line: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn test_spawner_state() {
+        use crate::entities::template::parse_templates;
+        use crate::maps::map_file::parse_map;
+        use std::path::Path;
+        let mut templates = TemplateRegistry::new();
+        parse_templates(
+            Path::new("rat.npc"),
+            "[rat]\nname = Rat\nhealth = 5\nstrength = 1\n",
+            &mut templates,
+        )
+        .unwrap();
+        let britain = "name = Britain\n\
+            spawner = rat | 2,1 | 1 | 2 | 30\n\
+            [map]\n\
+            ......\n\
+            ......\n";
+        let mut played = parse_map(britain).unwrap();
+        let mut rat = templates.spawn("rat", Position::new(3, 1)).unwrap();
+        rat.spawner = Some(0);
+        rat.health = 2;
+        played.npcs.push(rat);
+        played.spawners[0].set_respawns(vec![130]);
+
+        let saved = format_save(
+            &hero(),
+            "Britain",
+            &WorldClock::at(0),
+            &stormy(),
+            &[MapState::capture(&played)],
+        );
+        assert!(
+            saved.contains("spawner = Britain | 0 | 130\nspawned = Britain | 0 | rat | 3,1 | 2\n")
+        );
+
+        let loaded = parse_save(&saved).unwrap();
+        let mut reloaded = parse_map(britain).unwrap();
+        loaded.maps[0].restore(&mut reloaded, &templates);
+        assert_eq!(
+            reloaded.spawners[0].respawns(),
+            &[130],
+            "the dead rat is not respawned for free"
+        );
+        assert_eq!(reloaded.npcs.len(), 1);
+        let rat = &reloaded.npcs[0];
+        assert_eq!(
+            (rat.spawner, rat.position, rat.health),
+            (Some(0), Position::new(3, 1), 2)
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            parse_save("map = Britain\n").err().unwrap(),
+            SaveError::MissingField("name")
+        ));
+        assert!(matches!(
+            parse_save("name = Hero\nhealth = lots\n").err().unwrap(),
+            SaveError::MalformedLine { line: 2, .. }
+        ));
+        assert!(matches!(
+            parse_save("name = Hero\nexplored = Britain | 1 | 5-2\n")
+                .err()
+                .unwrap(),
+            SaveError::MalformedLine { line: 2, .. }
+        ));
+        assert!(matches!(
+            parse_save("name = Hero\nweather = Britain | 0,0 | hail | 10\n")
+                .err()
+                .unwrap(),
+            SaveError::MalformedLine { line: 2, .. }
+        ));
+        assert!(matches!(
+            parse_save("name = Hero\nnotoriety = blue\n").err().unwrap(),
+            SaveError::MalformedLine { line: 2, .. }
+        ));
+        assert!(matches!(
+            parse_save("name = Hero\nquest = ore | lost\n")
+                .err()
+                .unwrap(),
+            SaveError::MalformedLine { line: 2, .. }
+        ));
+        assert!(matches!(
+            parse_save("gold = 5\n").err().unwrap(),
+            SaveError::UnknownKey { line: 1, .. }
+        ));
+    }
+}
@@ -1,20 +1,46 @@
+use crate::dialogue::{Conversation, DialogueLibrary};
+use crate::editor::{Editor, PromptKind};
+use crate::entities::behaviour::tick_npcs;
+use crate::entities::notoriety::{npcs_attack, Notoriety};
 use crate::entities::npc::NPC;
-use crate::entities::player::Player;
+use crate::entities::player::{Player, PlayerStatus, INJURED_HEALTH};
+use crate::entities::template::roll_loot;
+use crate::game::MAPS_DIR;
+use crate::inventory::item::{Item, HEALTH_POTION, MANA_POTION};
+use crate::maps::camera::Camera;
+use crate::maps::chunk::STREAM_RADIUS;
+use crate::maps::fov::FieldOfView;
+use crate::maps::light::SightConditions;
+use crate::maps::map_file::MapFile;
 use crate::maps::maps::Maps;
+use crate::maps::occupancy::{Blocker, Step};
+use crate::maps::position::{Direction, Position};
+use crate::maps::spatial::EntityList;
+use crate::maps::tile::{CellVisibility, Colour, DrawnCell};
+use crate::save::{save_game, SAVE_FILE};
+use crate::world::clock::MINUTES_PER_TURN;
+use crate::world::spawner;
+use crate::world::weather::Weather;
+use crate::world::world::World;
 use crossterm::event::{self, Event, KeyCode};
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{self, Constraint, Layout, Rect},
     style::{Color, Style},
-    widgets::{Block, Borders, Paragraph},
-    Terminal
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Terminal,
 };
-use std::io::{self};
 use std::collections::VecDeque;
-use crate::inventory::item::Item;
+use std::io::{self};
+use std::path::Path;
 
-pub fn run_ui(player: &mut Player, map: &mut Maps, npcs: &mut Vec<NPC>, items: &mut Vec<Item>) -> Result<(), io::Error> {
-    let mut stdout = io::stdout();
+pub fn run_ui(
+    player: &mut Player,
+    world: &mut World,
+    dialogues: &DialogueLibrary,
+) -> Result<(), io::Error> {
+    let stdout = io::stdout();
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
     crossterm::terminal::enable_raw_mode()?;
@@ -22,46 +48,150 @@ pub fn run_ui(player: &mut Player, map: &mut Maps, npcs: &mut Vec<NPC>, items: &
     let initial_size = terminal.size()?;
     let max_log_lines = (initial_size.height * 20 / 100) as usize;
     let mut ui_state = UIState::new(max_log_lines);
+    world.update_spawners(&mut rand::thread_rng());
 
     loop {
+        // The editor and the admin view take over the stats panel.
+        let panel_status = match (&ui_state.mode, &ui_state.editor) {
+            (InputMode::Edit, Some(editor)) => Some(("Editor", editor.status(world.current()))),
+            (InputMode::Admin, _) => {
+                let current = world.current();
+                Some((
+                    "Spawners",
+                    spawner::report(&current.spawners, &current.npcs, world.clock.minutes()),
+                ))
+            }
+            _ => None,
+        };
+        let weather = world.weather_at(player.position);
+        match &ui_state.weather {
+            Some((map_name, previous))
+                if *map_name == world.current().name && *previous != weather =>
+            {
+                ui_state.add_log(weather.message().to_string())
+            }
+            _ => (),
+        }
+        ui_state.weather = Some((world.current().name.clone(), weather));
+        let clock = world.clock;
+        let (
+            MapFile {
+                name: map_name,
+                spawn,
+                map,
+                npcs,
+                items,
+                spawners,
+                ..
+            },
+            templates,
+        ) = world.current_with_templates();
         let size = terminal.size()?;
-        let map_width = (size.width * 70 / 100) as usize;
-        let map_height = (size.height * 80 / 100) as usize;
-        map.resize(map_width, map_height);
+        if let Err(err) = map.stream_around(player.position, STREAM_RADIUS) {
+            ui_state.add_log(format!("Could not load the world around you: {}", err));
+        }
+        let conditions = SightConditions {
+            ambient_light: clock.ambient_light(),
+            carries_light: player.carries_light(),
+            visibility: weather.visibility(),
+            fire_dampened: weather.dampens_fire(),
+        };
+        let region_status = match map.region_at(player.position) {
+            Some(region) => match &region.flags.music {
+                Some(music) => format!("\nRegion: {} (music: {})", region.name, music),
+                None => format!("\nRegion: {}", region.name),
+            },
+            None => "\nRegion: wilderness".to_string(),
+        };
+        let fov = map.lit_field_of_view(player.position, &conditions);
+        player.explore(map_name, &fov);
+        let explored = &player.explored[map_name.as_str()];
         ui_state.max_log_lines = (size.height * 20 / 100) as usize;
+        // Part of the map on screen, so the look cursor can be kept on it.
+        let mut view = map.full_view();
 
         terminal.draw(|f| {
             let chunks = Layout::default()
-                .direction(Direction::Vertical)
+                .direction(layout::Direction::Vertical)
                 .constraints([Constraint::Percentage(80), Constraint::Percentage(20)].as_ref())
-                .split(f.size());
+                .split(f.area());
 
             let left_chunks = Layout::default()
-                .direction(Direction::Horizontal)
+                .direction(layout::Direction::Horizontal)
                 .constraints([Constraint::Percentage(70), Constraint::Percentage(30)].as_ref())
                 .split(chunks[0]);
 
             let right_chunks = Layout::default()
-                .direction(Direction::Vertical)
+                .direction(layout::Direction::Vertical)
                 .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
                 .split(left_chunks[1]);
 
             let map_area = left_chunks[0];
-            map.resize_to_frame(map_area.width as usize, map_area.height as usize);
+            let editor_cursor = ui_state.editor.as_ref().filter(|_| ui_state.mode == InputMode::Edit).map(|editor| editor.cursor);
+            let camera = Camera::centered_on(
+                editor_cursor.unwrap_or(player.position),
+                map_area.width.saturating_sub(2) as usize,
+                map_area.height.saturating_sub(2) as usize,
+                map.width(),
+                map.height(),
+            );
+            view = camera;
 
-            let map_display = map.draw(player.position, npcs, items);
-            let map_widget = Paragraph::new(map_display)
+            // The editor shows the whole map, not only what the player sees.
+            let mut cells = match editor_cursor {
+                Some(_) => map.draw_cells(&camera, &FieldOfView::all(&camera), explored, player.position, npcs, items),
+                None => map.draw_cells(&camera, &fov, explored, player.position, npcs, items),
+            };
+            let cursor = match ui_state.mode {
+                InputMode::Look(cursor) => Some(cursor),
+                _ => editor_cursor,
+            };
+            if ui_state.mode == InputMode::Admin {
+                for spawner in spawners.iter() {
+                    if let Some((column, row)) = camera.screen_position(spawner.center.x, spawner.center.y) {
+                        cells[row][column].glyph = 'S';
+                    }
+                }
+            }
+            if let Some((column, row)) = camera.screen_position(player.position.x, player.position.y) {
+                cells[row][column].notoriety = Some(player.notoriety);
+            }
+            if let Some(cursor) = cursor {
+                if let Some((column, row)) = camera.screen_position(cursor.x, cursor.y) {
+                    cells[row][column].glyph = 'X';
+                }
+            }
+            let title = match editor_cursor {
+                Some(_) => format!("Map - {} [editor]", map_name),
+                None => format!("Map - {}", map_name),
+            };
+            let map_widget = Paragraph::new(map_lines(cells))
                 .style(Style::default().fg(Color::White))
-                .block(Block::default().borders(Borders::ALL).title("Map"));
+                .block(Block::default().borders(Borders::ALL).title(title));
             f.render_widget(map_widget, map_area);
 
-            let stats = format!(
-                "Name: {}\nHealth: {}\nStrength: {}\nAgility: {}\nExperience: {}",
-                player.name, player.health, player.strength, player.agility, player.experience
-            );
+            if let Some(conversation) = ui_state.conversation.as_ref().filter(|_| ui_state.mode == InputMode::Talk) {
+                let area = centered(map_area, 60, 50);
+                let dialogue_widget = Paragraph::new(conversation_text(conversation, dialogues, player))
+                    .style(Style::default().fg(Color::White))
+                    .wrap(Wrap { trim: true })
+                    .block(Block::default().borders(Borders::ALL).title(conversation.npc_name.as_str()));
+                f.render_widget(Clear, area);
+                f.render_widget(dialogue_widget, area);
+            }
+
+            let (stats_title, stats) = match &panel_status {
+                Some((title, status)) => (*title, status.clone()),
+                None => ("Player Stats", format!(
+                    "Name: {} ({})\nHealth: {}\nMana: {}\nStrength: {}\nAgility: {}\nExperience: {}\nReputation: {}\n{}\n\n{} ({})\nWeather: {}",
+                    player.name, player.notoriety.name(), player.health, player.mana, player.strength, player.agility,
+                    player.experience, player.reputation, player.check_status(),
+                    clock, clock.time_of_day().name(), weather.name()
+                ) + &region_status),
+            };
             let stats_block = Paragraph::new(stats)
                 .style(Style::default().fg(Color::Green))
-                .block(Block::default().borders(Borders::ALL).title("Player Stats"));
+                .block(Block::default().borders(Borders::ALL).title(stats_title));
             f.render_widget(stats_block, right_chunks[0]);
 
             let inventory = Player::get_inventory(player);
@@ -70,7 +200,11 @@ pub fn run_ui(player: &mut Player, map: &mut Maps, npcs: &mut Vec<NPC>, items: &
                 .block(Block::default().borders(Borders::ALL).title("Inventary"));
             f.render_widget(inventory_paragraph, right_chunks[1]);
 
-            let command_output = format!("> {}", ui_state.get_log());
+            let prompt = ui_state.editor.as_ref().and_then(|editor| editor.prompt.as_ref());
+            let command_output = match prompt {
+                Some(prompt) => format!("{} ({}): {}_", prompt_label(prompt.kind), prompt.usage(), prompt.input),
+                None => format!("> {}", ui_state.get_log()),
+            };
             let command_input_display = Paragraph::new(command_output)
                 .style(Style::default().fg(Color::Blue))
                 .block(Block::default().borders(Borders::ALL).title("Commands"));
@@ -78,45 +212,112 @@ pub fn run_ui(player: &mut Player, map: &mut Maps, npcs: &mut Vec<NPC>, items: &
         })?;
 
         if let Event::Key(key) = event::read()? {
+            if key.code != KeyCode::Char('q') {
+                ui_state.quit_requested = false;
+            }
+            let previous_position = player.position;
+            // Saving needs the whole world, so it waits until the key is handled.
+            let mut save_requested = false;
+            if let InputMode::Look(cursor) = ui_state.mode {
+                handle_look_key(
+                    key.code,
+                    cursor,
+                    &mut ui_state,
+                    world.current(),
+                    &view,
+                    &fov,
+                );
+                continue;
+            }
+            if ui_state.mode == InputMode::Edit {
+                handle_editor_key(key.code, &mut ui_state, world.current_mut());
+                continue;
+            }
+            if ui_state.mode == InputMode::Talk {
+                handle_talk_key(key.code, &mut ui_state, dialogues, player);
+                continue;
+            }
+            if ui_state.mode == InputMode::Admin {
+                if matches!(key.code, KeyCode::F(3) | KeyCode::Esc) {
+                    ui_state.mode = InputMode::Play;
+                }
+                continue;
+            }
+
             match key.code {
                 KeyCode::Char('w') => {
-                    player.move_up(map);
-                    ui_state.add_log("Player moved up".to_string());
+                    move_player(player, map, npcs, Direction::North, &mut ui_state)
                 }
                 KeyCode::Char('s') => {
-                    player.move_down(map);
-                    ui_state.add_log("Player moved down".to_string());
+                    move_player(player, map, npcs, Direction::South, &mut ui_state)
                 }
                 KeyCode::Char('a') => {
-                    player.move_left(map);
-                    ui_state.add_log("Player moved left".to_string());
+                    move_player(player, map, npcs, Direction::West, &mut ui_state)
                 }
                 KeyCode::Char('d') => {
-                    player.move_right(map);
-                    ui_state.add_log("Player moved right".to_string());
+                    move_player(player, map, npcs, Direction::East, &mut ui_state)
                 }
 
                 KeyCode::Char('t') => {
                     Player::train_player(player);
+                    player.set_status(PlayerStatus::Exhausted);
                     ui_state.add_log("You train to improve your strength and agility.".to_string());
                 }
 
+                KeyCode::Char('h') => {
+                    let potion_log = Item::use_item(player, HEALTH_POTION);
+                    player.set_status(PlayerStatus::Normal);
+                    ui_state.add_log(potion_log);
+                }
+
+                KeyCode::Char('m') => {
+                    let potion_log = Item::use_item(player, MANA_POTION);
+                    player.set_status(PlayerStatus::Normal);
+                    ui_state.add_log(potion_log);
+                }
+
                 KeyCode::Char('g') => {
-                    if let Some(item_index) = map.find_nearby(player.position, items) {
-                        let item = &mut items[item_index];
-                        let combat_log = Item::add_item(player, item.clone());
-                        items.remove(item_index);
+                    // The whole stack under the player, otherwise the first item next to them.
+                    let mut picked = items.take_where(|item| item.position == player.position);
+                    if picked.is_empty() {
+                        if let Some(item_index) = map.find_nearby(player.position, items) {
+                            picked.push(items.remove(item_index));
+                        }
+                    }
+                    for item in picked {
+                        let combat_log = Item::add_item(player, item);
                         ui_state.add_log(combat_log);
                     }
                 }
 
                 KeyCode::Char('f') => {
                     if let Some(npc_index) = map.find_nearby_npc(player.position, npcs) {
-                        let npc = &mut npcs[npc_index];
-                        let combat_log = player.engage_in_combat(npc);
+                        let region = map.region_at(player.position);
+                        let (combat_log, defeated) = match npcs.get_mut(npc_index) {
+                            Some(mut npc) => {
+                                (player.engage_in_combat(&mut npc, region), npc.health <= 0)
+                            }
+                            None => continue,
+                        };
+                        let lawbreaker =
+                            matches!(player.notoriety, Notoriety::Criminal | Notoriety::Murderer);
+                        if lawbreaker && region.is_some_and(|region| region.flags.guarded) {
+                            map.summon_guard(npcs, templates, player.position);
+                        }
 
-                        if npc.health <= 0 {
-                            npcs.remove(npc_index);
+                        player.set_status(if player.health < INJURED_HEALTH {
+                            PlayerStatus::Injured
+                        } else if defeated {
+                            PlayerStatus::Normal
+                        } else {
+                            PlayerStatus::InCombat
+                        });
+                        if defeated {
+                            let npc = npcs.remove(npc_index);
+                            for item in roll_loot(&npc.loot, npc.position, &mut rand::thread_rng())
+                            {
+                                items.push(item);
+                            }
                             Player::gain_experience(player, 10);
                         }
                         ui_state.add_log(combat_log)
@@ -125,13 +326,64 @@ pub fn run_ui(player: &mut Player, map: &mut Maps, npcs: &mut Vec<NPC>, items: &
 
                 KeyCode::Char('e') => {
                     if let Some(npc_index) = map.find_nearby_npc(player.position, npcs) {
-                        let npc = &mut npcs[npc_index];
-                        let combat_log = npc.interact();
-                        ui_state.add_log(combat_log)
+                        let npc = &npcs[npc_index];
+                        match npc
+                            .conversation
+                            .as_ref()
+                            .filter(|tree| dialogues.get(tree).is_some())
+                        {
+                            Some(tree) => {
+                                ui_state.conversation = Some(Conversation::new(&npc.name, tree));
+                                ui_state.mode = InputMode::Talk;
+                            }
+                            None => ui_state.add_log(npc.interact()),
+                        }
+                    } else if let Some(door_log) = map.use_door_near(player.position) {
+                        ui_state.add_log(door_log)
                     }
                 }
 
+                KeyCode::F(2) => {
+                    let editor = ui_state
+                        .editor
+                        .get_or_insert_with(|| Editor::new(player.position));
+                    editor.cursor = player.position;
+                    ui_state.mode = InputMode::Edit;
+                    ui_state.add_log(EDITOR_HELP.to_string());
+                }
+
+                KeyCode::F(3) => {
+                    ui_state.mode = InputMode::Admin;
+                    ui_state.add_log(
+                        "Admin view: spawners are marked S (F3 or Esc to leave)".to_string(),
+                    );
+                }
+
+                KeyCode::Char('l') => {
+                    ui_state.mode = InputMode::Look(player.position);
+                    ui_state.add_log("Look where? (move with w/a/s/d, Esc to stop)".to_string());
+                }
+
+                KeyCode::F(5) => save_requested = true,
+
                 KeyCode::Char('q') => {
+                    let safe = map
+                        .region_at(player.position)
+                        .is_some_and(|region| region.flags.safe_logout);
+                    if !safe && !ui_state.quit_requested {
+                        ui_state.quit_requested = true;
+                        ui_state.add_log(
+                            "This is not a safe place to log out. Press q again to quit anyway."
+                                .to_string(),
+                        );
+                        continue;
+                    }
+                    if let Err(err) = save_game(SAVE_FILE, player, world) {
+                        eprintln!("Could not save the game: {}", err);
+                    }
+                    if let Err(err) = world.unload_all() {
+                        eprintln!("Could not store the world: {}", err);
+                    }
                     break;
                 }
 
@@ -141,22 +393,328 @@ pub fn run_ui(player: &mut Player, map: &mut Maps, npcs: &mut Vec<NPC>, items: &
                 KeyCode::Backspace => {
                     ui_state.command_input.pop();
                 }
-                _ => ()
+                _ => (),
             }
 
-            map.update_player_position(player.position.0, player.position.1);
+            if let Some(region_log) = map.region_change(previous_position, player.position) {
+                ui_state.add_log(region_log);
+            }
+            // Wading through snow takes longer than walking. The tile entered
+            // sets the cost; bumping into a wall takes no time.
+            let moved = player.position != previous_position;
+            let turns = match key.code {
+                KeyCode::Char('w' | 'a' | 's' | 'd') if moved => map
+                    .terrain(player.position)
+                    .map_or(1, |terrain| weather.turns_to_enter(terrain)),
+                KeyCode::Char('w' | 'a' | 's' | 'd') => 0,
+                KeyCode::Char('t' | 'h' | 'm' | 'g' | 'f' | 'e') => 1,
+                _ => 0,
+            };
+            map.update_player_position(player.position);
+            for _ in 0..turns {
+                for npc_log in tick_npcs(map, npcs, player.position, &mut rand::thread_rng()) {
+                    ui_state.add_log(npc_log);
+                }
+                for attack_log in npcs_attack(npcs, player) {
+                    ui_state.add_log(attack_log);
+                }
+            }
+            // The dead go back to the spawn point instead of travelling on.
+            let died = player.is_dead();
+            if died {
+                let respawn_log = player.respawn(*spawn);
+                ui_state.add_log(respawn_log);
+            }
+            if let Some(notoriety_log) = player.pass_time(MINUTES_PER_TURN * turns) {
+                ui_state.add_log(notoriety_log);
+            }
+            world.clock.advance(MINUTES_PER_TURN * turns);
+            if turns > 0 {
+                world.update_spawners(&mut rand::thread_rng());
+            }
+            world
+                .current_mut()
+                .map
+                .update_player_position(player.position);
+            // Only stepping onto a portal uses it, so arriving on the portal
+            // back does not send the player straight home on the next key.
+            if let Some(travel_log) = (moved && !died).then(|| world.travel(player)).flatten() {
+                match world.current().map.region_at(player.position) {
+                    Some(region) => ui_state
+                        .add_log(format!("{} You have entered {}.", travel_log, region.name)),
+                    None => ui_state.add_log(travel_log),
+                }
+            }
+            if save_requested {
+                match save_game(SAVE_FILE, player, world) {
+                    Ok(()) => ui_state.add_log("Game saved.".to_string()),
+                    Err(err) => ui_state.add_log(format!("Could not save the game: {}", err)),
+                }
+            }
         }
     }
 
     Ok(())
 }
 
+fn move_player(
+    player: &mut Player,
+    map: &Maps,
+    npcs: &mut EntityList<NPC>,
+    direction: Direction,
+    ui_state: &mut UIState,
+) {
+    match player.move_towards(map, npcs, direction) {
+        Step::Moved(_) => ui_state.add_log(format!("Player moved {}", direction.name())),
+        Step::Swapped { npc, .. } => {
+            ui_state.add_log(format!("You swap places with {}.", npcs[npc].name))
+        }
+        Step::Blocked(Blocker::Npc(npc)) => {
+            ui_state.add_log(format!("{} is in your way.", npcs[npc].name))
+        }
+        Step::Blocked(_) => (),
+    }
+}
+
+const EDITOR_HELP: &str = "Editor: w/a/s/d move, [ ] brush, space paint, n NPC, i item, x clear, p spawn, F5 save, F2 leave";
+
+fn prompt_label(kind: PromptKind) -> &'static str {
+    match kind {
+        PromptKind::Npc => "NPC",
+        PromptKind::Item => "Item",
+    }
+}
+
+fn handle_editor_key(code: KeyCode, ui_state: &mut UIState, map_file: &mut MapFile) {
+    let Some(editor) = ui_state.editor.as_mut() else {
+        ui_state.mode = InputMode::Play;
+        return;
+    };
 
+    if let Some(prompt) = editor.prompt.as_mut() {
+        match code {
+            KeyCode::Char(c) => prompt.input.push(c),
+            KeyCode::Backspace => {
+                prompt.input.pop();
+            }
+            KeyCode::Esc => editor.prompt = None,
+            KeyCode::Enter => {
+                let message = editor.submit_prompt(map_file).unwrap_or_else(|err| err);
+                ui_state.add_log(message);
+            }
+            _ => (),
+        }
+        return;
+    }
+
+    let message = match code {
+        KeyCode::Char('w') => return editor.move_cursor(&map_file.map, Direction::North),
+        KeyCode::Char('s') => return editor.move_cursor(&map_file.map, Direction::South),
+        KeyCode::Char('a') => return editor.move_cursor(&map_file.map, Direction::West),
+        KeyCode::Char('d') => return editor.move_cursor(&map_file.map, Direction::East),
+        KeyCode::Char(']') => return editor.next_brush(),
+        KeyCode::Char('[') => return editor.previous_brush(),
+        KeyCode::Char(' ') => editor.paint(map_file).unwrap_or_else(|err| err),
+        KeyCode::Char('n') => return editor.start_npc_prompt(map_file),
+        KeyCode::Char('i') => return editor.start_item_prompt(),
+        KeyCode::Char('x') => editor.clear(map_file).unwrap_or_else(|err| err),
+        KeyCode::Char('p') => editor.set_spawn(map_file).unwrap_or_else(|err| err),
+        KeyCode::F(5) => editor
+            .save(map_file, Path::new(MAPS_DIR))
+            .unwrap_or_else(|err| err),
+        KeyCode::F(2) | KeyCode::Esc => {
+            ui_state.mode = InputMode::Play;
+            "You leave the editor.".to_string()
+        }
+        _ => return,
+    };
+    ui_state.add_log(message);
+}
+
+fn handle_talk_key(
+    code: KeyCode,
+    ui_state: &mut UIState,
+    dialogues: &DialogueLibrary,
+    player: &mut Player,
+) {
+    let Some(conversation) = ui_state.conversation.as_mut() else {
+        ui_state.mode = InputMode::Play;
+        return;
+    };
+    let number = match code {
+        KeyCode::Char(c) => match c.to_digit(10) {
+            Some(number) => number as usize,
+            None => return,
+        },
+        KeyCode::Esc => {
+            let farewell = format!("You leave {}.", conversation.npc_name);
+            ui_state.conversation = None;
+            ui_state.add_log(farewell);
+            ui_state.mode = InputMode::Play;
+            return;
+        }
+        _ => return,
+    };
+    let Some(choice) = conversation.choose(dialogues, player, number) else {
+        return;
+    };
+    if choice.ended {
+        ui_state.conversation = None;
+        ui_state.mode = InputMode::Play;
+    }
+    for message in choice.messages {
+        ui_state.add_log(message);
+    }
+}
+
+/// The NPC's line and the numbered replies the player may pick.
+fn conversation_text(
+    conversation: &Conversation,
+    dialogues: &DialogueLibrary,
+    player: &Player,
+) -> String {
+    let mut text = format!("{}\n", conversation.text(dialogues));
+    for (index, reply) in conversation.replies(dialogues, player).iter().enumerate() {
+        text.push_str(&format!("\n{}. {}", index + 1, reply.text));
+    }
+    text + "\n\n(number to answer, Esc to leave)"
+}
+
+/// A rectangle of the given percentages of `area`, centered in it.
+fn centered(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
+    let width = area.width * percent_x / 100;
+    let height = area.height * percent_y / 100;
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+fn handle_look_key(
+    code: KeyCode,
+    cursor: Position,
+    ui_state: &mut UIState,
+    map_file: &MapFile,
+    view: &Camera,
+    fov: &FieldOfView,
+) {
+    let direction = match code {
+        KeyCode::Char('w') => Direction::North,
+        KeyCode::Char('s') => Direction::South,
+        KeyCode::Char('a') => Direction::West,
+        KeyCode::Char('d') => Direction::East,
+        KeyCode::Esc | KeyCode::Enter | KeyCode::Char('l') => {
+            ui_state.mode = InputMode::Play;
+            return;
+        }
+        _ => return,
+    };
+
+    // The cursor stops at the edge of the screen, where it would vanish.
+    if let Some(moved) = map_file
+        .map
+        .neighbour(cursor, direction)
+        .filter(|moved| view.contains(moved.x, moved.y))
+    {
+        ui_state.mode = InputMode::Look(moved);
+        if fov.is_visible(moved.x, moved.y) {
+            ui_state.add_log(
+                map_file
+                    .map
+                    .describe_at(moved, &map_file.npcs, &map_file.items),
+            );
+        } else {
+            ui_state.add_log("You cannot see that.".to_string());
+        }
+    }
+}
+
+/// Styles rendered map cells: remembered tiles are dimmed.
+fn map_lines(cells: Vec<Vec<DrawnCell>>) -> Vec<Line<'static>> {
+    cells
+        .into_iter()
+        .map(|row| {
+            let spans: Vec<Span> = row
+                .into_iter()
+                .map(|cell| {
+                    let style = match (cell.visibility, cell.notoriety) {
+                        (CellVisibility::Visible, Some(notoriety)) => Style::default().fg(cell
+                            .colour
+                            .map_or(notoriety_color(notoriety), terminal_color)),
+                        (CellVisibility::Remembered, _) => Style::default().fg(Color::DarkGray),
+                        (CellVisibility::Dark, _) => Style::default().fg(Color::Blue),
+                        (CellVisibility::Visible | CellVisibility::Unknown, _) => Style::default(),
+                    };
+                    Span::styled(cell.glyph.to_string(), style)
+                })
+                .collect();
+            Line::from(spans)
+        })
+        .collect()
+}
+
+/// Name colours as in Ultima Online: blue innocents, grey criminals, red
+/// murderers.
+fn notoriety_color(notoriety: Notoriety) -> Color {
+    match notoriety {
+        Notoriety::Innocent => Color::LightBlue,
+        Notoriety::Ally => Color::Green,
+        Notoriety::Enemy => Color::LightRed,
+        Notoriety::Criminal => Color::Gray,
+        Notoriety::Murderer => Color::Red,
+        Notoriety::Invulnerable => Color::Yellow,
+    }
+}
+
+fn terminal_color(colour: Colour) -> Color {
+    match colour {
+        Colour::Black => Color::Black,
+        Colour::Red => Color::Red,
+        Colour::Green => Color::Green,
+        Colour::Yellow => Color::Yellow,
+        Colour::Blue => Color::Blue,
+        Colour::Magenta => Color::Magenta,
+        Colour::Cyan => Color::Cyan,
+        Colour::Gray => Color::Gray,
+        Colour::DarkGray => Color::DarkGray,
+        Colour::LightRed => Color::LightRed,
+        Colour::LightGreen => Color::LightGreen,
+        Colour::LightYellow => Color::LightYellow,
+        Colour::LightBlue => Color::LightBlue,
+        Colour::LightMagenta => Color::LightMagenta,
+        Colour::LightCyan => Color::LightCyan,
+        Colour::White => Color::White,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputMode {
+    Play,
+    /// Examining the map, with the cursor at the given position.
+    Look(Position),
+    /// Editing the current map; the cursor lives in `UIState::editor`.
+    Edit,
+    /// Inspecting the spawners of the current map.
+    Admin,
+    /// Talking to an NPC; the dialogue lives in `UIState::conversation`.
+    Talk,
+}
 
 pub struct UIState {
     pub command_input: String,
     pub log_buffer: VecDeque<String>,
-    pub max_log_lines: usize
+    pub max_log_lines: usize,
+    pub mode: InputMode,
+    /// Kept between editor sessions so the brush is remembered.
+    pub editor: Option<Editor>,
+    pub conversation: Option<Conversation>,
+    /// Weather around the player last frame and the map it was on, to
+    /// announce changes.
+    pub weather: Option<(String, Weather)>,
+    /// Set after `q` outside a safe-logout region; a second `q` quits.
+    pub quit_requested: bool,
 }
 
 impl UIState {
@@ -164,20 +722,27 @@ impl UIState {
         Self {
             command_input: String::new(),
             log_buffer: VecDeque::with_capacity(max_log_lines),
-            max_log_lines
+            max_log_lines,
+            mode: InputMode::Play,
+            editor: None,
+            conversation: None,
+            weather: None,
+            quit_requested: false,
         }
     }
 
     pub fn add_log(&mut self, message: String) {
-        if self.log_buffer.len() > 0  {
+        if !self.log_buffer.is_empty() {
             self.log_buffer.pop_front();
         }
         self.log_buffer.push_back(message)
     }
 
     pub fn get_log(&self) -> String {
-        self.log_buffer.iter().cloned().collect::<Vec<_>>().join(" | ")
+        self.log_buffer
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" | ")
     }
 }
-
-
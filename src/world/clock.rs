@@ -0,0 +1,162 @@
+//! Game time. The clock only moves when the player acts, so a day lasts as
+//! many turns as `MINUTES_PER_DAY / MINUTES_PER_TURN`.
+
+use std::fmt;
+
+pub const MINUTES_PER_DAY: u64 = 24 * 60;
+/// Game minutes that pass for each action of the player.
+pub const MINUTES_PER_TURN: u64 = 2;
+
+/// New games start on the first morning.
+const START_TIME: u64 = 8 * 60;
+
+const DAWN: u64 = 5;
+const MORNING: u64 = 7;
+const EVENING: u64 = 19;
+const NIGHTFALL: u64 = 21;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeOfDay {
+    Dawn,
+    Day,
+    Dusk,
+    Night,
+}
+
+impl TimeOfDay {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimeOfDay::Dawn => "dawn",
+            TimeOfDay::Day => "day",
+            TimeOfDay::Dusk => "dusk",
+            TimeOfDay::Night => "night",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorldClock {
+    /// Game minutes since midnight of the first day.
+    minutes: u64,
+}
+
+impl Default for WorldClock {
+    fn default() -> Self {
+        Self::at(START_TIME)
+    }
+}
+
+impl WorldClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn at(minutes: u64) -> Self {
+        Self { minutes }
+    }
+
+    pub fn minutes(&self) -> u64 {
+        self.minutes
+    }
+
+    pub fn advance(&mut self, minutes: u64) {
+        self.minutes += minutes;
+    }
+
+    /// Counted from 1.
+    pub fn day(&self) -> u64 {
+        self.minutes / MINUTES_PER_DAY + 1
+    }
+
+    pub fn hour(&self) -> u64 {
+        self.minutes % MINUTES_PER_DAY / 60
+    }
+
+    pub fn minute(&self) -> u64 {
+        self.minutes % 60
+    }
+
+    pub fn time_of_day(&self) -> TimeOfDay {
+        if self.is_between(DAWN, MORNING) {
+            TimeOfDay::Dawn
+        } else if self.is_between(MORNING, EVENING) {
+            TimeOfDay::Day
+        } else if self.is_between(EVENING, NIGHTFALL) {
+            TimeOfDay::Dusk
+        } else {
+            TimeOfDay::Night
+        }
+    }
+
+    /// Whether the hour is in `from..to`, wrapping past midnight when `to`
+    /// is earlier than `from` (e.g. a night watch from 22 to 6).
+    pub fn is_between(&self, from: u64, to: u64) -> bool {
+        let hour = self.hour();
+        if from <= to {
+            (from..to).contains(&hour)
+        } else {
+            hour >= from || hour < to
+        }
+    }
+
+    /// Daylight from 0 (night) to 100 (day), rising through dawn and
+    /// falling through dusk.
+    pub fn ambient_light(&self) -> u8 {
+        let minute_of_day = self.minutes % MINUTES_PER_DAY;
+        let ramp =
+            |start: u64| ((minute_of_day - start * 60) * 100 / ((MORNING - DAWN) * 60)) as u8;
+        match self.time_of_day() {
+            TimeOfDay::Day => 100,
+            TimeOfDay::Night => 0,
+            TimeOfDay::Dawn => ramp(DAWN),
+            TimeOfDay::Dusk => 100 - ramp(EVENING),
+        }
+    }
+}
+
+impl fmt::Display for WorldClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Day {}, {:02}:{:02}",
+            self.day(),
+            self.hour(),
+            self.minute()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calendar() {
+        let mut clock = WorldClock::new();
+        assert_eq!(clock.to_string(), "Day 1, 08:00");
+        clock.advance(MINUTES_PER_DAY + 125);
+        assert_eq!((clock.day(), clock.hour(), clock.minute()), (2, 10, 5));
+        assert_eq!(clock.to_string(), "Day 2, 10:05");
+    }
+
+    #[test]
+    fn test_time_of_day_and_light() {
+        assert_eq!(WorldClock::at(12 * 60).time_of_day(), TimeOfDay::Day);
+        assert_eq!(WorldClock::at(12 * 60).ambient_light(), 100);
+        assert_eq!(WorldClock::at(23 * 60).time_of_day(), TimeOfDay::Night);
+        assert_eq!(WorldClock::at(3 * 60).ambient_light(), 0);
+        assert_eq!(WorldClock::at(6 * 60).time_of_day(), TimeOfDay::Dawn);
+        assert_eq!(WorldClock::at(6 * 60).ambient_light(), 50);
+        assert_eq!(WorldClock::at(20 * 60 + 30).time_of_day(), TimeOfDay::Dusk);
+        assert_eq!(WorldClock::at(20 * 60 + 30).ambient_light(), 25);
+    }
+
+    #[test]
+    fn test_schedules() {
+        let night_watch = |hour: u64| WorldClock::at(hour * 60).is_between(22, 6);
+        assert!(night_watch(23) && night_watch(2));
+        assert!(!night_watch(6) && !night_watch(12));
+        assert!(WorldClock::at(9 * 60).is_between(8, 18));
+        assert!(!WorldClock::at(18 * 60).is_between(8, 18));
+    }
+}
@@ -0,0 +1,5 @@
+pub mod clock;
+pub mod spawner;
+pub mod weather;
+#[allow(clippy::module_inception)] // `world::world` holds the `World` registry
+pub mod world;
@@ -0,0 +1,257 @@
+//! Spawners keep a population of template NPCs alive around a point, like
+//! Ultima Online spawners. When one of their NPCs dies, a replacement is
+//! spawned somewhere within the radius once the delay of game time has
+//! passed. Map files declare them as
+//!
+//! ```text
+//! spawner = orc, skeleton | 48,6 | 4 | 3 | 30
+//! ```
+//!
+//! that is templates, center, radius, population and delay in game minutes.
+
+use rand::Rng;
+
+use crate::entities::npc::NPC;
+use crate::entities::template::TemplateRegistry;
+use crate::maps::maps::Maps;
+use crate::maps::position::Position;
+use crate::maps::spatial::EntityList;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spawner {
+    /// Template ids, picked by spawn weight.
+    pub templates: Vec<String>,
+    pub center: Position,
+    pub radius: usize,
+    pub population: usize,
+    /// Game minutes between a death and the respawn.
+    pub delay: u64,
+    /// Game minute at which each missing NPC comes back, soonest first.
+    respawns: Vec<u64>,
+}
+
+impl Spawner {
+    /// A new spawner fills its whole population on the first update.
+    pub fn new(
+        templates: Vec<String>,
+        center: Position,
+        radius: usize,
+        population: usize,
+        delay: u64,
+    ) -> Self {
+        Self {
+            templates,
+            center,
+            radius,
+            population,
+            delay,
+            respawns: vec![0; population],
+        }
+    }
+
+    /// NPCs of spawner `index` still alive.
+    pub fn live(index: usize, npcs: &EntityList<NPC>) -> usize {
+        npcs.iter().filter(|npc| npc.spawner == Some(index)).count()
+    }
+
+    pub fn next_respawn(&self) -> Option<u64> {
+        self.respawns.first().copied()
+    }
+
+    /// Game minutes of the pending respawns, soonest first.
+    pub fn respawns(&self) -> &[u64] {
+        &self.respawns
+    }
+
+    /// Replaces the pending respawns, e.g. with those of a saved game.
+    pub fn set_respawns(&mut self, mut respawns: Vec<u64>) {
+        respawns.sort_unstable();
+        self.respawns = respawns;
+    }
+
+    /// One line for the admin view, e.g.
+    /// `#1 orc, skeleton at 48,6 r4: 2/3 alive, next in 24 min`.
+    pub fn status(&self, index: usize, npcs: &EntityList<NPC>, now: u64) -> String {
+        let next = match self.next_respawn() {
+            Some(due) if due > now => format!(", next in {} min", due - now),
+            Some(_) => ", respawn due".to_string(),
+            None => String::new(),
+        };
+        format!(
+            "#{} {} at {} r{}: {}/{} alive{}",
+            index + 1,
+            self.templates.join(", "),
+            self.center,
+            self.radius,
+            Self::live(index, npcs),
+            self.population,
+            next
+        )
+    }
+
+    /// A random walkable, unoccupied tile within the radius.
+    fn free_spot<R: Rng>(
+        &self,
+        map: &Maps,
+        npcs: &EntityList<NPC>,
+        rng: &mut R,
+    ) -> Option<Position> {
+        let (left, top) = (
+            self.center.x.saturating_sub(self.radius),
+            self.center.y.saturating_sub(self.radius),
+        );
+        let spots: Vec<Position> = (top..=self.center.y + self.radius)
+            .flat_map(|y| (left..=self.center.x + self.radius).map(move |x| Position::new(x, y)))
+            .filter(|&spot| map.is_empty(spot) && !npcs.any_at(spot))
+            .collect();
+        (!spots.is_empty()).then(|| spots[rng.gen_range(0..spots.len())])
+    }
+}
+
+/// The admin view of a map's spawners, one line each.
+pub fn report(spawners: &[Spawner], npcs: &EntityList<NPC>, now: u64) -> String {
+    if spawners.is_empty() {
+        return "No spawners on this map.".to_string();
+    }
+    spawners
+        .iter()
+        .enumerate()
+        .map(|(index, spawner)| spawner.status(index, npcs, now))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Schedules a respawn for every NPC that died since the last update and
+/// spawns those that are due at `now`. Returns how many NPCs were spawned.
+pub fn update_spawners<R: Rng>(
+    spawners: &mut [Spawner],
+    map: &Maps,
+    npcs: &mut EntityList<NPC>,
+    templates: &TemplateRegistry,
+    now: u64,
+    rng: &mut R,
+) -> usize {
+    let mut spawned = 0;
+    for (index, spawner) in spawners.iter_mut().enumerate() {
+        let live = Spawner::live(index, npcs);
+        while live + spawner.respawns.len() < spawner.population {
+            spawner.respawns.push(now + spawner.delay);
+        }
+        while spawner.next_respawn().is_some_and(|due| due <= now) {
+            let Some(template) = templates.choose_from(&spawner.templates, rng) else {
+                break;
+            };
+            // No room this turn; try again on the next one.
+            let Some(position) = spawner.free_spot(map, npcs, rng) else {
+                break;
+            };
+            let mut npc = template.spawn(position);
+            npc.spawner = Some(index);
+            npcs.push(npc);
+            spawner.respawns.remove(0);
+            spawned += 1;
+        }
+    }
+    spawned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::template::NpcTemplate;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn templates() -> TemplateRegistry {
+        let mut templates = TemplateRegistry::new();
+        templates.insert(NpcTemplate::new("orc", "Orc", 40, 8));
+        templates
+    }
+
+    #[test]
+    fn test_fill_and_respawn_after_delay() {
+        let map = Maps::new(20, 20);
+        let mut npcs = EntityList::new();
+        let mut spawners = vec![Spawner::new(
+            vec!["orc".to_string()],
+            Position::new(10, 10),
+            2,
+            3,
+            30,
+        )];
+        let mut rng = StdRng::seed_from_u64(1);
+
+        assert_eq!(
+            update_spawners(&mut spawners, &map, &mut npcs, &templates(), 0, &mut rng),
+            3
+        );
+        assert!(
+            npcs.iter()
+                .all(|npc| npc.position.distance(Position::new(10, 10)) <= 2
+                    && npc.spawner == Some(0))
+        );
+        assert_eq!(
+            update_spawners(&mut spawners, &map, &mut npcs, &templates(), 5, &mut rng),
+            0
+        );
+
+        npcs.remove(1);
+        assert_eq!(
+            update_spawners(&mut spawners, &map, &mut npcs, &templates(), 10, &mut rng),
+            0
+        );
+        assert_eq!(spawners[0].next_respawn(), Some(40));
+        assert_eq!(
+            spawners[0].status(0, &npcs, 16),
+            "#1 orc at 10,10 r2: 2/3 alive, next in 24 min"
+        );
+        assert_eq!(
+            update_spawners(&mut spawners, &map, &mut npcs, &templates(), 39, &mut rng),
+            0
+        );
+        assert_eq!(
+            update_spawners(&mut spawners, &map, &mut npcs, &templates(), 40, &mut rng),
+            1
+        );
+        assert_eq!(Spawner::live(0, &npcs), 3);
+        assert_eq!(
+            spawners[0].status(0, &npcs, 40),
+            "#1 orc at 10,10 r2: 3/3 alive"
+        );
+        assert_eq!(
+            report(&spawners, &npcs, 40),
+            "#1 orc at 10,10 r2: 3/3 alive"
+        );
+        assert_eq!(report(&[], &npcs, 40), "No spawners on this map.");
+    }
+
+    #[test]
+    fn test_waits_for_room() {
+        let mut map = Maps::with_walls(3, 3);
+        map.update_player_position(Position::new(1, 1));
+        let mut npcs = EntityList::new();
+        let mut spawners = vec![Spawner::new(
+            vec!["orc".to_string()],
+            Position::new(1, 1),
+            1,
+            1,
+            10,
+        )];
+        let mut rng = StdRng::seed_from_u64(2);
+        assert_eq!(
+            update_spawners(&mut spawners, &map, &mut npcs, &templates(), 0, &mut rng),
+            0
+        );
+        assert_eq!(
+            spawners[0].status(0, &npcs, 0),
+            "#1 orc at 1,1 r1: 0/1 alive, respawn due"
+        );
+
+        map.update_player_position(Position::new(0, 0));
+        assert_eq!(
+            update_spawners(&mut spawners, &map, &mut npcs, &templates(), 1, &mut rng),
+            1
+        );
+        assert_eq!(npcs[0].position, Position::new(1, 1));
+    }
+}
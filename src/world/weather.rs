@@ -0,0 +1,296 @@
+//! Regional weather. Every map is cut into square regions of `REGION_SIZE`
+//! tiles, each with its own spell of weather that lasts a few game hours
+//! before the next one is rolled. What can be rolled depends on the map's
+//! climate; maps without one (dungeons) always stay clear.
+
+use std::collections::BTreeMap;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::maps::fov::DEFAULT_SIGHT_RADIUS;
+use crate::maps::position::Position;
+use crate::maps::terrain::Terrain;
+use crate::world::clock::WorldClock;
+
+/// Side of a weather region, in tiles.
+pub const REGION_SIZE: usize = 64;
+
+/// Shortest and longest spell of weather, in game minutes.
+const MIN_SPELL: u64 = 2 * 60;
+const MAX_SPELL: u64 = 8 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weather {
+    Clear,
+    Rain,
+    Snow,
+    Fog,
+    Storm,
+}
+
+pub const ALL_WEATHER: [Weather; 5] = [
+    Weather::Clear,
+    Weather::Rain,
+    Weather::Snow,
+    Weather::Fog,
+    Weather::Storm,
+];
+
+impl Weather {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Weather::Clear => "clear",
+            Weather::Rain => "rain",
+            Weather::Snow => "snow",
+            Weather::Fog => "fog",
+            Weather::Storm => "storm",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Weather> {
+        ALL_WEATHER
+            .iter()
+            .copied()
+            .find(|weather| weather.name() == name)
+    }
+
+    /// Logged when the weather around the player turns to this.
+    pub fn message(&self) -> &'static str {
+        match self {
+            Weather::Clear => "The sky clears.",
+            Weather::Rain => "It starts to rain.",
+            Weather::Snow => "Snow begins to fall.",
+            Weather::Fog => "A thick fog rolls in.",
+            Weather::Storm => "A storm breaks overhead.",
+        }
+    }
+
+    /// Farthest anyone can see in this weather, day or night.
+    pub fn visibility(&self) -> usize {
+        match self {
+            Weather::Clear => DEFAULT_SIGHT_RADIUS,
+            Weather::Rain => 9,
+            Weather::Snow => 8,
+            Weather::Storm => 6,
+            Weather::Fog => 4,
+        }
+    }
+
+    /// Whether flames (torches, lamp posts) struggle to stay lit.
+    pub fn dampens_fire(&self) -> bool {
+        matches!(self, Weather::Storm)
+    }
+
+    /// How many turns a step onto `terrain` takes: deep snow slows walking
+    /// outdoors.
+    pub fn turns_to_enter(&self, terrain: Terrain) -> u64 {
+        if *self == Weather::Snow && terrain.is_outdoor() {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+/// Which weather a map gets and how often.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Climate {
+    #[default]
+    Temperate,
+    Cold,
+    /// Underground or indoors: always clear.
+    None,
+}
+
+impl Climate {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Climate::Temperate => "temperate",
+            Climate::Cold => "cold",
+            Climate::None => "none",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Climate> {
+        [Climate::Temperate, Climate::Cold, Climate::None]
+            .into_iter()
+            .find(|climate| climate.name() == name)
+    }
+
+    /// Relative chance of each weather, in `ALL_WEATHER` order.
+    fn weights(&self) -> [u32; 5] {
+        match self {
+            Climate::Temperate => [50, 25, 3, 12, 10],
+            Climate::Cold => [35, 5, 35, 15, 10],
+            Climate::None => [1, 0, 0, 0, 0],
+        }
+    }
+}
+
+/// A stretch of one kind of weather over a region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spell {
+    pub weather: Weather,
+    /// Game minute at which the next spell is rolled.
+    pub until: u64,
+}
+
+/// Weather of every region visited so far, keyed by map name and region.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WeatherSystem {
+    spells: BTreeMap<(String, (usize, usize)), Spell>,
+}
+
+impl WeatherSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn region_of(position: Position) -> (usize, usize) {
+        (position.x / REGION_SIZE, position.y / REGION_SIZE)
+    }
+
+    /// Weather at `position`, rolling a new spell for its region when the
+    /// previous one is over. Rolls are seeded by map, region and time, so
+    /// the same world always sees the same weather.
+    pub fn weather_at(
+        &mut self,
+        map_name: &str,
+        climate: Climate,
+        position: Position,
+        clock: &WorldClock,
+    ) -> Weather {
+        if climate == Climate::None {
+            return Weather::Clear;
+        }
+        let region = Self::region_of(position);
+        let now = clock.minutes();
+        let key = (map_name.to_string(), region);
+        match self.spells.get(&key) {
+            Some(spell) if spell.until > now => spell.weather,
+            _ => {
+                let spell = roll_spell(map_name, region, climate, now);
+                self.spells.insert(key, spell);
+                spell.weather
+            }
+        }
+    }
+
+    pub fn set_spell(&mut self, map_name: &str, region: (usize, usize), spell: Spell) {
+        self.spells.insert((map_name.to_string(), region), spell);
+    }
+
+    /// Every known spell, sorted by map and region.
+    pub fn spells(&self) -> impl Iterator<Item = (&str, (usize, usize), Spell)> + '_ {
+        self.spells
+            .iter()
+            .map(|((map_name, region), spell)| (map_name.as_str(), *region, *spell))
+    }
+}
+
+fn roll_spell(map_name: &str, region: (usize, usize), climate: Climate, now: u64) -> Spell {
+    // FNV-1a, so the seed does not depend on the standard library's hasher.
+    let mut seed: u64 = 0xcbf2_9ce4_8422_2325;
+    let bytes = map_name
+        .bytes()
+        .chain(region.0.to_le_bytes())
+        .chain(region.1.to_le_bytes())
+        .chain(now.to_le_bytes());
+    for byte in bytes {
+        seed = (seed ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let weights = climate.weights();
+    let mut roll = rng.gen_range(0..weights.iter().sum::<u32>());
+    let mut weather = Weather::Clear;
+    for (candidate, weight) in ALL_WEATHER.into_iter().zip(weights) {
+        if roll < weight {
+            weather = candidate;
+            break;
+        }
+        roll -= weight;
+    }
+    Spell {
+        weather,
+        until: now + rng.gen_range(MIN_SPELL..=MAX_SPELL),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spell(system: &WeatherSystem, map_name: &str, region: (usize, usize)) -> Option<Spell> {
+        system.spells.get(&(map_name.to_string(), region)).copied()
+    }
+
+    #[test]
+    fn test_weather_changes_over_time() {
+        let mut system = WeatherSystem::new();
+        let mut clock = WorldClock::new();
+        let position = Position::new(10, 10);
+        let first = system.weather_at("Britain", Climate::Temperate, position, &clock);
+        let first_spell = spell(&system, "Britain", (0, 0)).unwrap();
+        assert_eq!(first_spell.weather, first);
+        assert!(first_spell.until > clock.minutes());
+
+        clock.advance(MIN_SPELL - 1);
+        assert_eq!(
+            system.weather_at("Britain", Climate::Temperate, position, &clock),
+            first
+        );
+        clock.advance(MAX_SPELL);
+        system.weather_at("Britain", Climate::Temperate, position, &clock);
+        assert!(spell(&system, "Britain", (0, 0)).unwrap().until > clock.minutes());
+    }
+
+    #[test]
+    fn test_regions_are_independent() {
+        let mut system = WeatherSystem::new();
+        let clock = WorldClock::new();
+        system.set_spell(
+            "Britain",
+            (1, 0),
+            Spell {
+                weather: Weather::Fog,
+                until: clock.minutes() + 60,
+            },
+        );
+        let east = Position::new(REGION_SIZE + 3, 5);
+        assert_eq!(
+            system.weather_at("Britain", Climate::Temperate, east, &clock),
+            Weather::Fog
+        );
+        system.weather_at("Britain", Climate::Temperate, Position::new(3, 5), &clock);
+        assert_eq!(system.spells().count(), 2);
+    }
+
+    #[test]
+    fn test_rolls_follow_the_climate() {
+        let mut system = WeatherSystem::new();
+        let mut clock = WorldClock::new();
+        let mut seen = Vec::new();
+        for _ in 0..200 {
+            seen.push(system.weather_at("Wind", Climate::Cold, Position::new(0, 0), &clock));
+            clock.advance(MAX_SPELL);
+        }
+        assert!(seen.contains(&Weather::Snow));
+        assert!(seen.contains(&Weather::Clear));
+
+        let dungeon = system.weather_at("Despise", Climate::None, Position::new(0, 0), &clock);
+        assert_eq!(dungeon, Weather::Clear);
+        assert!(spell(&system, "Despise", (0, 0)).is_none());
+    }
+
+    #[test]
+    fn test_effects() {
+        assert!(Weather::Fog.visibility() < Weather::Clear.visibility());
+        assert_eq!(Weather::Snow.turns_to_enter(Terrain::Grass), 2);
+        assert_eq!(Weather::Snow.turns_to_enter(Terrain::Floor), 1);
+        assert_eq!(Weather::Rain.turns_to_enter(Terrain::Grass), 1);
+        assert!(Weather::Storm.dampens_fire());
+        assert_eq!(Weather::from_name("fog"), Some(Weather::Fog));
+    }
+}
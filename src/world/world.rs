@@ -0,0 +1,523 @@
+use std::io;
+
+use rand::Rng;
+
+use crate::entities::npc::NPC;
+use crate::entities::player::Player;
+use crate::entities::template::TemplateRegistry;
+use crate::maps::chunk::STREAM_RADIUS;
+use crate::maps::map_file::MapFile;
+use crate::maps::position::Position;
+use crate::maps::terrain::Terrain;
+use crate::world::clock::WorldClock;
+use crate::world::spawner::update_spawners;
+use crate::world::weather::{Weather, WeatherSystem};
+
+/// How far from the arrival point followers may be placed.
+const FOLLOWER_RADIUS: usize = 2;
+
+/// Registry of every loaded map (facets, dungeon levels, ...). Each map owns
+/// its NPCs and items; the player is always on the current one.
+pub struct World {
+    maps: Vec<MapFile>,
+    current: usize,
+    /// Shared by every map, so NPC schedules and spawns agree on the time.
+    pub clock: WorldClock,
+    pub weather: WeatherSystem,
+    /// NPC templates, for anything spawned after the maps are loaded.
+    pub templates: TemplateRegistry,
+}
+
+impl World {
+    pub fn new(start: MapFile) -> Self {
+        Self {
+            maps: vec![start],
+            current: 0,
+            clock: WorldClock::new(),
+            weather: WeatherSystem::new(),
+            templates: TemplateRegistry::new(),
+        }
+    }
+
+    /// Adds a map, replacing any map with the same name.
+    pub fn add_map(&mut self, map_file: MapFile) {
+        match self
+            .maps
+            .iter()
+            .position(|existing| existing.name == map_file.name)
+        {
+            Some(index) => self.maps[index] = map_file,
+            None => self.maps.push(map_file),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MapFile> {
+        self.maps.iter().find(|map_file| map_file.name == name)
+    }
+
+    pub fn maps(&self) -> &[MapFile] {
+        &self.maps
+    }
+
+    pub fn current(&self) -> &MapFile {
+        &self.maps[self.current]
+    }
+
+    pub fn current_mut(&mut self) -> &mut MapFile {
+        &mut self.maps[self.current]
+    }
+
+    /// The current map together with the NPC templates, for spawning on it
+    /// while it is borrowed.
+    pub fn current_with_templates(&mut self) -> (&mut MapFile, &TemplateRegistry) {
+        (&mut self.maps[self.current], &self.templates)
+    }
+
+    /// The map called `name` together with the NPC templates.
+    pub fn get_mut_with_templates(
+        &mut self,
+        name: &str,
+    ) -> Option<(&mut MapFile, &TemplateRegistry)> {
+        let map_file = self
+            .maps
+            .iter_mut()
+            .find(|map_file| map_file.name == name)?;
+        Some((map_file, &self.templates))
+    }
+
+    /// Makes `name` the current map without moving anyone.
+    pub fn set_current(&mut self, name: &str) -> bool {
+        match self.maps.iter().position(|map_file| map_file.name == name) {
+            Some(index) => {
+                self.current = index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Runs the spawners of every map at the current game time. Returns
+    /// how many NPCs were spawned.
+    pub fn update_spawners<R: Rng>(&mut self, rng: &mut R) -> usize {
+        let now = self.clock.minutes();
+        self.maps
+            .iter_mut()
+            .map(|map_file| {
+                update_spawners(
+                    &mut map_file.spawners,
+                    &map_file.map,
+                    &mut map_file.npcs,
+                    &self.templates,
+                    now,
+                    rng,
+                )
+            })
+            .sum()
+    }
+
+    /// Weather at `position` on the current map.
+    pub fn weather_at(&mut self, position: Position) -> Weather {
+        let map_file = &self.maps[self.current];
+        self.weather
+            .weather_at(&map_file.name, map_file.climate, position, &self.clock)
+    }
+
+    /// Writes the streamed chunks of every map back to their source. Keeps
+    /// going after a failure and returns the first error.
+    pub fn unload_all(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for map_file in &mut self.maps {
+            if let Err(err) = map_file.map.unload_all() {
+                result = result.and(Err(err));
+            }
+        }
+        result
+    }
+
+    /// Portals whose target map or position does not exist. Targets in
+    /// chunks that are not streamed in yet are checked on arrival instead.
+    pub fn broken_portals(&self) -> Vec<String> {
+        let mut broken = Vec::new();
+        for map_file in &self.maps {
+            for portal in &map_file.portals {
+                let position = portal.target_position;
+                let valid = self.get(&portal.target_map).is_some_and(|target| {
+                    let unloaded = target.map.contains(position) && !target.map.is_loaded(position);
+                    target.map.is_walkable(position) || unloaded
+                });
+                if !valid {
+                    broken.push(format!(
+                        "{} {} -> {} {}",
+                        map_file.name, portal.position, portal.target_map, portal.target_position
+                    ));
+                }
+            }
+        }
+        broken
+    }
+
+    /// Moves the player (and their followers) through the portal they stand on.
+    /// Returns the message to log, or `None` when there is no usable portal.
+    /// Magic gates do not work from or into no-teleport regions.
+    pub fn travel(&mut self, player: &mut Player) -> Option<String> {
+        let origin = self.current;
+        let portal = self.maps[origin]
+            .portals
+            .iter()
+            .find(|portal| portal.position == player.position)?
+            .clone();
+        let target = self
+            .maps
+            .iter()
+            .position(|map_file| map_file.name == portal.target_map)?;
+        let arrival = portal.target_position;
+        if let Err(err) = self.maps[target].map.stream_around(arrival, STREAM_RADIUS) {
+            return Some(format!(
+                "The way to {} is lost: {}",
+                self.maps[target].name, err
+            ));
+        }
+        if !self.maps[target].map.is_walkable(arrival) {
+            return None;
+        }
+
+        // Stairs are walked; any other portal is a magic gate.
+        let terrain = self.maps[origin].map.terrain(player.position);
+        let magic = !matches!(terrain, Some(Terrain::StairsDown | Terrain::StairsUp));
+        let verb = match terrain {
+            Some(Terrain::StairsDown) => "You go down the stairs to",
+            Some(Terrain::StairsUp) => "You climb the stairs to",
+            _ => "You travel to",
+        };
+        let no_teleport = |map_file: &MapFile, position: Position| {
+            map_file
+                .map
+                .region_at(position)
+                .is_some_and(|region| region.flags.no_teleport)
+        };
+        if magic
+            && (no_teleport(&self.maps[origin], player.position)
+                || no_teleport(&self.maps[target], arrival))
+        {
+            return Some("Something blocks the magic of the gate.".to_string());
+        }
+
+        let followers: Vec<NPC> = self.maps[origin].npcs.take_where(|npc| npc.follower);
+        for mut follower in followers {
+            match self.free_spot_near(target, arrival) {
+                Some(position) => {
+                    follower.position = position;
+                    follower.home = position;
+                    // Spawner indices only mean something on their own map.
+                    follower.spawner = None;
+                    self.maps[target].npcs.push(follower);
+                }
+                None => self.maps[origin].npcs.push(follower),
+            }
+        }
+
+        // Spawners of the map left behind must not keep the tile blocked.
+        self.maps[origin].map.clear_player_position();
+        self.current = target;
+        player.position = arrival;
+        self.maps[target].map.update_player_position(arrival);
+        Some(format!("{} {}.", verb, self.maps[target].name))
+    }
+
+    fn free_spot_near(&self, map_index: usize, center: Position) -> Option<Position> {
+        let map_file = &self.maps[map_index];
+        for radius in 1..=FOLLOWER_RADIUS {
+            for y in center.y.saturating_sub(radius)..=center.y + radius {
+                for x in center.x.saturating_sub(radius)..=center.x + radius {
+                    let spot = Position::new(x, y);
+                    let taken = spot == center || map_file.npcs.any_at(spot);
+                    if !taken && map_file.map.is_walkable(Position::new(x, y)) {
+                        return Some(spot);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inventory::item::Item;
+    use crate::maps::maps::Maps;
+    use crate::maps::portal::Portal;
+    use crate::maps::spatial::EntityList;
+    use crate::world::weather::Climate;
+
+    fn area(name: &str) -> MapFile {
+        MapFile {
+            name: name.to_string(),
+            spawn: Position::new(1, 1),
+            climate: Climate::default(),
+            map: Maps::with_walls(10, 10),
+            npcs: EntityList::new(),
+            items: EntityList::new(),
+            portals: Vec::new(),
+            placements: Vec::new(),
+            spawners: Vec::new(),
+            path: None,
+        }
+    }
+
+    fn linked_world() -> World {
+        let mut britain = area("Britain");
+        britain
+            .map
+            .set_tile(Position::new(5, 5), Terrain::StairsDown);
+        britain.portals.push(Portal::new(
+            Position::new(5, 5),
+            "Despise",
+            Position::new(2, 2),
+        ));
+        britain
+            .items
+            .push(Item::new("Health Potion", Position::new(3, 3)));
+
+        let mut despise = area("Despise");
+        despise.map.set_tile(Position::new(2, 2), Terrain::StairsUp);
+        despise.portals.push(Portal::new(
+            Position::new(2, 2),
+            "Britain",
+            Position::new(5, 5),
+        ));
+
+        let mut world = World::new(britain);
+        world.add_map(despise);
+        world
+    }
+
+    #[test]
+    fn test_registry() {
+        let world = linked_world();
+        let names: Vec<&str> = world
+            .maps()
+            .iter()
+            .map(|map_file| map_file.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Britain", "Despise"]);
+        assert_eq!(world.current().name, "Britain");
+        assert!(world.get("Despise").is_some());
+        assert!(world.get("Wind").is_none());
+        assert!(world.broken_portals().is_empty());
+    }
+
+    #[test]
+    fn test_travel_through_stairs() {
+        let mut world = linked_world();
+        let mut player = Player::new("Hero");
+
+        player.position = Position::new(4, 4);
+        assert_eq!(world.travel(&mut player), None);
+
+        player.position = Position::new(5, 5);
+        assert_eq!(
+            world.travel(&mut player),
+            Some("You go down the stairs to Despise.".to_string())
+        );
+        assert_eq!(world.current().name, "Despise");
+        assert_eq!(player.position, Position::new(2, 2));
+        assert!(world.current().items.is_empty());
+
+        assert_eq!(
+            world.travel(&mut player),
+            Some("You climb the stairs to Britain.".to_string())
+        );
+        assert_eq!(world.current().name, "Britain");
+        assert_eq!(world.current().items.len(), 1);
+        assert!(
+            world
+                .get("Despise")
+                .unwrap()
+                .map
+                .is_empty(Position::new(2, 2)),
+            "the player left Despise"
+        );
+    }
+
+    #[test]
+    fn test_followers_travel_with_player() {
+        let mut world = linked_world();
+        let mut pet = NPC::new("Horse", "Neigh!", Position::new(5, 6), 30, 5);
+        pet.follower = true;
+        world.current_mut().npcs.push(pet);
+        world
+            .current_mut()
+            .npcs
+            .push(NPC::new("Guard", "Halt!", Position::new(6, 6), 50, 10));
+
+        let mut player = Player::new("Hero");
+        player.position = Position::new(5, 5);
+        world.travel(&mut player);
+
+        let despise = world.get("Despise").unwrap();
+        assert_eq!(despise.npcs.len(), 1);
+        assert_eq!(despise.npcs[0].name, "Horse");
+        assert_ne!(despise.npcs[0].position, Position::new(2, 2));
+        assert!(despise.map.is_walkable(despise.npcs[0].position));
+        assert_eq!(world.get("Britain").unwrap().npcs[0].name, "Guard");
+    }
+
+    #[test]
+    fn test_spawners_run_on_every_map() {
+        use crate::entities::template::NpcTemplate;
+        use crate::world::spawner::Spawner;
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let mut world = linked_world();
+        world.templates.insert(NpcTemplate::new("rat", "Rat", 5, 1));
+        for name in ["Britain", "Despise"] {
+            world
+                .get_mut_with_templates(name)
+                .unwrap()
+                .0
+                .spawners
+                .push(Spawner::new(
+                    vec!["rat".to_string()],
+                    Position::new(4, 4),
+                    1,
+                    2,
+                    20,
+                ));
+        }
+        let mut rng = StdRng::seed_from_u64(4);
+        assert_eq!(world.update_spawners(&mut rng), 4);
+
+        world
+            .get_mut_with_templates("Despise")
+            .unwrap()
+            .0
+            .npcs
+            .remove(0);
+        assert_eq!(world.update_spawners(&mut rng), 0);
+        world.clock.advance(20);
+        assert_eq!(world.update_spawners(&mut rng), 1);
+        assert_eq!(world.get("Despise").unwrap().npcs.len(), 2);
+    }
+
+    #[test]
+    fn test_bundled_maps_are_linked() {
+        use crate::maps::map_file::load_map;
+        let mut world = World::new(load_map("data/maps/start.map").unwrap());
+        world.add_map(load_map("data/maps/despise.map").unwrap());
+        world.add_map(crate::game::wilderness());
+        world.add_map(crate::game::island());
+        assert!(world.broken_portals().is_empty());
+    }
+
+    #[test]
+    fn test_island_has_guarded_towns_and_spawners() {
+        let island = crate::game::island();
+        let town = island
+            .map
+            .region_at(island.spawn)
+            .expect("the gate is in a town");
+        assert!(town.flags.guarded);
+        assert!(island.map.regions().len() > 1, "every town is a region");
+        let templates: Vec<&str> = island
+            .spawners
+            .iter()
+            .map(|spawner| spawner.templates[0].as_str())
+            .collect();
+        assert_eq!(templates, vec!["rat", "orc", "skeleton"]);
+        assert!(island
+            .spawners
+            .iter()
+            .all(|spawner| island.map.is_walkable(spawner.center)));
+    }
+
+    #[test]
+    fn test_no_teleport_regions_block_gates() {
+        use crate::maps::region::{Area, Region, RegionFlags};
+        let mut world = linked_world();
+        world.current_mut().portals.push(Portal::new(
+            Position::new(3, 3),
+            "Despise",
+            Position::new(4, 4),
+        ));
+        let vault = Area::Rect {
+            top_left: Position::new(4, 4),
+            bottom_right: Position::new(6, 6),
+        };
+        let flags = RegionFlags {
+            no_teleport: true,
+            ..RegionFlags::default()
+        };
+        world
+            .get_mut_with_templates("Despise")
+            .unwrap()
+            .0
+            .map
+            .add_region(Region::new("Vault", vault, flags));
+
+        let mut player = Player::new("Hero");
+        player.position = Position::new(3, 3);
+        assert_eq!(
+            world.travel(&mut player),
+            Some("Something blocks the magic of the gate.".to_string())
+        );
+        assert_eq!(world.current().name, "Britain");
+
+        player.position = Position::new(5, 5);
+        assert!(
+            world
+                .travel(&mut player)
+                .unwrap()
+                .starts_with("You go down the stairs"),
+            "stairs are not magic"
+        );
+    }
+
+    #[test]
+    fn test_broken_portals() {
+        let mut world = linked_world();
+        world.current_mut().portals.push(Portal::new(
+            Position::new(1, 1),
+            "Wind",
+            Position::new(1, 1),
+        ));
+        world.current_mut().portals.push(Portal::new(
+            Position::new(1, 2),
+            "Despise",
+            Position::new(0, 0),
+        ));
+        assert_eq!(world.broken_portals().len(), 2);
+    }
+
+    #[test]
+    fn test_travel_streams_the_arrival() {
+        use crate::maps::chunk::{GeneratedChunks, CHUNK_SIZE};
+        use crate::maps::maps::Maps;
+        let mut world = linked_world();
+        let mut wilderness = area("Wilderness");
+        let gate = Position::new(CHUNK_SIZE * 10 + 3, CHUNK_SIZE * 10 + 3);
+        wilderness.map = Maps::streamed(
+            CHUNK_SIZE * 20,
+            CHUNK_SIZE * 20,
+            Box::new(GeneratedChunks::new(|_, _| Terrain::Grass)),
+        );
+        world.add_map(wilderness);
+        world
+            .current_mut()
+            .portals
+            .push(Portal::new(Position::new(3, 3), "Wilderness", gate));
+        assert!(world.broken_portals().is_empty());
+
+        let mut player = Player::new("Hero");
+        player.position = Position::new(3, 3);
+        assert_eq!(
+            world.travel(&mut player),
+            Some("You travel to Wilderness.".to_string())
+        );
+        assert_eq!(player.position, gate);
+        assert!(world.current().map.is_loaded(gate));
+        assert!(world.unload_all().is_ok());
+        assert_eq!(world.current().map.loaded_chunks(), 0);
+    }
+}